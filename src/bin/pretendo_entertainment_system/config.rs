@@ -0,0 +1,406 @@
+use crate::pads::{self, Pads};
+use sdl2::controller::{Axis, Button};
+use sdl2::event::Event;
+use sdl2::keyboard::{KeyboardState, Scancode};
+use sdl2::mouse::{MouseButton, MouseState};
+use std::fmt;
+use std::str::FromStr;
+
+/// The bindings used for anything a config file leaves out
+pub const DEFAULT_CONFIG: &str = include_str!("default.ini");
+
+/// The names of a controller's buttons in the config file, in the order they're stored in
+const BUTTON_NAMES: [&str; 10] = [
+    "a", "b", "select", "start", "up", "down", "left", "right", "turbo_a", "turbo_b",
+];
+
+/// An input on the host that can be bound to an action
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Binding {
+    Key(Scancode),
+    Mouse(MouseButton),
+    /// A button of the game controller with this index
+    PadButton(usize, Button),
+    /// An axis of the game controller with this index, pushed in the positive (true) or negative
+    /// direction
+    PadAxis(usize, Axis, bool),
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    /// Parses an SDL key name, `mouse:<button>`, `pad<n>:<button>` or `pad<n>:<axis>` followed by
+    /// `+` or `-`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((device, input)) = s.split_once(':') {
+            if device == "mouse" {
+                return match input {
+                    "left" => Ok(Binding::Mouse(MouseButton::Left)),
+                    "middle" => Ok(Binding::Mouse(MouseButton::Middle)),
+                    "right" => Ok(Binding::Mouse(MouseButton::Right)),
+                    "x1" => Ok(Binding::Mouse(MouseButton::X1)),
+                    "x2" => Ok(Binding::Mouse(MouseButton::X2)),
+                    _ => Err(format!("unknown mouse button '{}'", input)),
+                };
+            }
+            let pad: Option<usize> = device
+                .strip_prefix("pad")
+                .and_then(|number| number.parse::<usize>().ok())
+                .filter(|&number| number > 0);
+            if let Some(pad) = pad {
+                let axis = |name: &str| {
+                    Axis::from_string(name)
+                        .ok_or(format!("unknown game controller axis '{}'", name))
+                };
+                return if let Some(name) = input.strip_suffix('+') {
+                    Ok(Binding::PadAxis(pad - 1, axis(name)?, true))
+                } else if let Some(name) = input.strip_suffix('-') {
+                    Ok(Binding::PadAxis(pad - 1, axis(name)?, false))
+                } else {
+                    Button::from_string(input)
+                        .map(|button| Binding::PadButton(pad - 1, button))
+                        .ok_or(format!("unknown game controller button '{}'", input))
+                };
+            }
+        }
+        // some key names have colons in them, like "Keypad :"
+        Scancode::from_name(s)
+            .map(Binding::Key)
+            .ok_or(format!("unknown key '{}'", s))
+    }
+}
+
+/// What turns a paddle's knob
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum KnobAxis {
+    /// Moving the mouse left and right
+    X,
+    /// Moving the mouse up and down
+    Y,
+    /// An axis of the game controller with this index. The knob follows the axis's position.
+    Pad(usize, Axis),
+}
+
+/// Emulator functions that aren't part of an input device
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hotkeys {
+    pub quit: Vec<Binding>,
+    pub reset: Vec<Binding>,
+    pub power_cycle: Vec<Binding>,
+    /// Rewinds while held
+    pub rewind: Vec<Binding>,
+    pub save_state: Vec<Binding>,
+    pub load_state: Vec<Binding>,
+    /// Each one selects a save state slot
+    pub slots: [Vec<Binding>; 10],
+    pub play_tape: Vec<Binding>,
+    pub record_tape: Vec<Binding>,
+    pub stop_tape: Vec<Binding>,
+    pub capture_keyboard: Vec<Binding>,
+}
+
+/// Every action's bindings. An action is triggered by any of its bindings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    /// A, B, Select, Start, Up, Down, Left, Right, Turbo A and Turbo B on each controller
+    pub controllers: [[Vec<Binding>; 10]; 4],
+    pub zapper_trigger: Vec<Binding>,
+    pub vaus_knob: KnobAxis,
+    pub vaus_button: Vec<Binding>,
+    /// The Power Pad's squares, in rows of four from the top left
+    pub power_pad: [Vec<Binding>; 12],
+    pub hotkeys: Hotkeys,
+    /// How far game controller sticks and triggers have to be pushed, from 0 to 1
+    pub deadzone: f32,
+}
+
+impl Bindings {
+    /// Nothing bound to anything
+    fn empty() -> Self {
+        Bindings {
+            controllers: Default::default(),
+            zapper_trigger: Vec::new(),
+            vaus_knob: KnobAxis::X,
+            vaus_button: Vec::new(),
+            power_pad: Default::default(),
+            hotkeys: Hotkeys::default(),
+            deadzone: 0.0,
+        }
+    }
+
+    /// The default bindings, with the ones in the config file replacing them
+    pub fn from_config(config: &str) -> Result<Self, ConfigError> {
+        let mut bindings = Bindings::empty();
+        bindings
+            .apply(DEFAULT_CONFIG)
+            .expect("the default config is valid");
+        bindings.apply(config)?;
+        Ok(bindings)
+    }
+
+    /// Binds every action listed in the config file to the inputs listed for it, replacing its
+    /// old bindings
+    fn apply(&mut self, config: &str) -> Result<(), ConfigError> {
+        let mut rebound: Vec<(&str, &str)> = Vec::new();
+        for entry in parse_ini(config)? {
+            let error = |message: String| ConfigError {
+                line: entry.line,
+                message,
+            };
+            if (entry.section, entry.key) == ("vaus", "knob") {
+                self.vaus_knob = match entry.value {
+                    "mouse:x" => KnobAxis::X,
+                    "mouse:y" => KnobAxis::Y,
+                    axis => parse_pad_axis(axis)
+                        .map(|(pad, axis)| KnobAxis::Pad(pad, axis))
+                        .ok_or_else(|| {
+                            error(format!(
+                                "invalid knob axis '{}' (expected mouse:x, mouse:y or \
+                                 pad<n>:<axis>)",
+                                axis
+                            ))
+                        })?,
+                };
+                continue;
+            }
+            if (entry.section, entry.key) == ("pads", "deadzone") {
+                self.deadzone = entry
+                    .value
+                    .parse()
+                    .ok()
+                    .filter(|deadzone| (0.0..1.0).contains(deadzone))
+                    .ok_or_else(|| {
+                        error(format!(
+                            "invalid deadzone '{}' (expected a number from 0 to 1)",
+                            entry.value
+                        ))
+                    })?;
+                continue;
+            }
+            let action: &mut Vec<Binding> =
+                self.action(entry.section, entry.key).ok_or_else(|| {
+                    error(format!(
+                        "unknown action '{}' in [{}]",
+                        entry.key, entry.section
+                    ))
+                })?;
+            if !rebound.contains(&(entry.section, entry.key)) {
+                rebound.push((entry.section, entry.key));
+                action.clear();
+            }
+            // an empty value leaves the action unbound
+            if !entry.value.is_empty() {
+                action.push(entry.value.parse().map_err(error)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// The bindings of an action in the config file
+    fn action(&mut self, section: &str, key: &str) -> Option<&mut Vec<Binding>> {
+        if let Some(player) = section.strip_prefix("controller") {
+            let player: usize = player.parse::<usize>().ok()?.checked_sub(1)?;
+            let button: usize = BUTTON_NAMES.iter().position(|&name| name == key)?;
+            return self
+                .controllers
+                .get_mut(player)
+                .map(|controller| &mut controller[button]);
+        }
+        let hotkeys: &mut Hotkeys = &mut self.hotkeys;
+        match (section, key) {
+            ("zapper", "trigger") => Some(&mut self.zapper_trigger),
+            ("vaus", "button") => Some(&mut self.vaus_button),
+            ("power_pad", _) => {
+                let square: usize = key.strip_prefix("square")?.parse().ok()?;
+                self.power_pad.get_mut(square.checked_sub(1)?)
+            }
+            ("hotkeys", "quit") => Some(&mut hotkeys.quit),
+            ("hotkeys", "reset") => Some(&mut hotkeys.reset),
+            ("hotkeys", "power_cycle") => Some(&mut hotkeys.power_cycle),
+            ("hotkeys", "rewind") => Some(&mut hotkeys.rewind),
+            ("hotkeys", "save_state") => Some(&mut hotkeys.save_state),
+            ("hotkeys", "load_state") => Some(&mut hotkeys.load_state),
+            ("hotkeys", "play_tape") => Some(&mut hotkeys.play_tape),
+            ("hotkeys", "record_tape") => Some(&mut hotkeys.record_tape),
+            ("hotkeys", "stop_tape") => Some(&mut hotkeys.stop_tape),
+            ("hotkeys", "capture_keyboard") => Some(&mut hotkeys.capture_keyboard),
+            ("hotkeys", _) => {
+                let slot: usize = key.strip_prefix("slot")?.parse().ok()?;
+                hotkeys.slots.get_mut(slot)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Parses `pad<n>:<axis>`, without the direction a binding has
+fn parse_pad_axis(s: &str) -> Option<(usize, Axis)> {
+    let (device, name) = s.split_once(':')?;
+    let pad: usize = device
+        .strip_prefix("pad")?
+        .parse()
+        .ok()
+        .filter(|&number| number > 0)?;
+    Some((pad - 1, Axis::from_string(name)?))
+}
+
+/// An `action = input` line of the config file
+#[derive(Debug, PartialEq)]
+struct Entry<'a> {
+    line: usize,
+    section: &'a str,
+    key: &'a str,
+    value: &'a str,
+}
+
+/// Splits an INI file into its entries. Lines starting with `#` are comments, since `;` is a key.
+fn parse_ini(text: &str) -> Result<Vec<Entry<'_>>, ConfigError> {
+    let mut section: &str = "";
+    let mut entries: Vec<Entry> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line: &str = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            section = line[1..line.len() - 1].trim();
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) => entries.push(Entry {
+                line: i + 1,
+                section,
+                key: key.trim(),
+                value: value.trim(),
+            }),
+            None => {
+                return Err(ConfigError {
+                    line: i + 1,
+                    message: format!("expected 'action = input', got '{}'", line),
+                })
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// The state of the host's input devices
+pub struct Inputs<'a> {
+    /// None while the Family BASIC keyboard has the keyboard
+    pub keyboard: Option<KeyboardState<'a>>,
+    pub mouse: MouseState,
+    pub pads: &'a Pads,
+    pub deadzone: f32,
+}
+
+impl Inputs<'_> {
+    pub fn is_held(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(scancode) => self
+                .keyboard
+                .as_ref()
+                .is_some_and(|keyboard| keyboard.is_scancode_pressed(scancode)),
+            Binding::Mouse(button) => self.mouse.is_mouse_button_pressed(button),
+            Binding::PadButton(pad, button) => {
+                self.pads.get(pad).is_some_and(|pad| pad.button(button))
+            }
+            Binding::PadAxis(pad, axis, positive) => self
+                .pads
+                .get(pad)
+                .is_some_and(|pad| pads::is_axis_pushed(pad, axis, positive, self.deadzone)),
+        }
+    }
+
+    /// Whether an action is held, through any of its bindings
+    pub fn any_held(&self, bindings: &[Binding]) -> bool {
+        bindings.iter().any(|&binding| self.is_held(binding))
+    }
+}
+
+/// The input an event presses, if it presses a key or button. Held keys don't repeat.
+pub fn pressed_binding(event: &Event, pads: &Pads) -> Option<Binding> {
+    match *event {
+        Event::KeyDown {
+            scancode: Some(scancode),
+            repeat: false,
+            ..
+        } => Some(Binding::Key(scancode)),
+        Event::MouseButtonDown { mouse_btn, .. } => Some(Binding::Mouse(mouse_btn)),
+        Event::ControllerButtonDown { which, button, .. } => pads
+            .position(which)
+            .map(|pad| Binding::PadButton(pad, button)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ini() {
+        let config = "# comment\n[hotkeys]\n  reset = R \n\n[ power_pad ]\nsquare2 = =\nrewind =\n";
+        let entry = |line, section, key, value| Entry {
+            line,
+            section,
+            key,
+            value,
+        };
+        assert_eq!(
+            parse_ini(config),
+            Ok(vec![
+                entry(3, "hotkeys", "reset", "R"),
+                entry(6, "power_pad", "square2", "="),
+                entry(7, "power_pad", "rewind", ""),
+            ])
+        );
+        assert_eq!(parse_ini("[hotkeys]\nreset\n").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut bindings = Bindings::empty();
+        bindings
+            .hotkeys
+            .reset
+            .push(Binding::Mouse(MouseButton::Right));
+        bindings
+            .apply("[hotkeys]\nreset =\n[vaus]\nknob = mouse:y\n")
+            .unwrap();
+        assert!(bindings.hotkeys.reset.is_empty());
+        assert_eq!(bindings.vaus_knob, KnobAxis::Y);
+        bindings.apply("[vaus]\nknob = pad2:triggerleft\n").unwrap();
+        assert!(matches!(bindings.vaus_knob, KnobAxis::Pad(1, _)));
+        assert!(bindings.apply("[vaus]\nknob = pad0:leftx\n").is_err());
+        bindings.apply("[pads]\ndeadzone = 0.25\n").unwrap();
+        assert_eq!(bindings.deadzone, 0.25);
+        assert!(bindings.apply("[pads]\ndeadzone = 1.5\n").is_err());
+        bindings
+            .apply("[zapper]\ntrigger = mouse:left\ntrigger = mouse:right\n")
+            .unwrap();
+        assert_eq!(
+            bindings.zapper_trigger,
+            vec![
+                Binding::Mouse(MouseButton::Left),
+                Binding::Mouse(MouseButton::Right)
+            ]
+        );
+
+        let error: ConfigError = bindings.apply("\n[controller5]\na = Z\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        assert!(bindings.apply("[hotkeys]\nslot10 = 0\n").is_err());
+        assert!(bindings.apply("[zapper]\ntrigger = mouse:side\n").is_err());
+    }
+}
@@ -0,0 +1,743 @@
+mod config;
+mod pads;
+
+use config::{pressed_binding, Binding, Bindings, Inputs, KnobAxis};
+use pads::Pads;
+use pretendo_entertainment_system::cartridge::ines::{ROMError, INES};
+use pretendo_entertainment_system::cartridge::Mapper;
+use pretendo_entertainment_system::emulator::Emulator;
+use pretendo_entertainment_system::movie::{Movie, MovieStart};
+use pretendo_entertainment_system::ppu::display::Display;
+use pretendo_entertainment_system::rewind::{RewindAudio, RewindConfig};
+use pretendo_entertainment_system::state::io::{
+    Buttons, ExpansionDevice, FamicomFourPlayers, FamilyBasicKeyboard, FourScore, Input,
+    InputDevice, Key, Paddle, Pointer, Port, PowerPad, PowerPadSide, StandardController, TapeState,
+    Turbo, Vaus, VausModel, Zapper,
+};
+use pretendo_entertainment_system::state::power_on::{ParsePowerOnStateError, PowerOnState};
+use pretendo_entertainment_system::state::NES;
+use pretendo_entertainment_system::tape::Tape;
+use sdl2;
+use sdl2::audio::AudioSpecDesired;
+use sdl2::event::Event;
+use sdl2::keyboard::Scancode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use structopt::StructOpt;
+
+const PIXEL_SCALE: u32 = 2;
+
+/// Bindings are read from here when `--config` isn't given, if it exists
+const CONFIG_PATH: &str = "pretendo.ini";
+
+/// The host keys for each key of the Family BASIC keyboard, laid out like a Japanese keyboard
+const FAMILY_KEYBOARD_MAP: [(Scancode, Key); 74] = [
+    (Scancode::F1, Key::F1),
+    (Scancode::F2, Key::F2),
+    (Scancode::F3, Key::F3),
+    (Scancode::F4, Key::F4),
+    (Scancode::F5, Key::F5),
+    (Scancode::F6, Key::F6),
+    (Scancode::F7, Key::F7),
+    (Scancode::F8, Key::F8),
+    (Scancode::Num0, Key::Num0),
+    (Scancode::Num1, Key::Num1),
+    (Scancode::Num2, Key::Num2),
+    (Scancode::Num3, Key::Num3),
+    (Scancode::Num4, Key::Num4),
+    (Scancode::Num5, Key::Num5),
+    (Scancode::Num6, Key::Num6),
+    (Scancode::Num7, Key::Num7),
+    (Scancode::Num8, Key::Num8),
+    (Scancode::Num9, Key::Num9),
+    (Scancode::A, Key::A),
+    (Scancode::B, Key::B),
+    (Scancode::C, Key::C),
+    (Scancode::D, Key::D),
+    (Scancode::E, Key::E),
+    (Scancode::F, Key::F),
+    (Scancode::G, Key::G),
+    (Scancode::H, Key::H),
+    (Scancode::I, Key::I),
+    (Scancode::J, Key::J),
+    (Scancode::K, Key::K),
+    (Scancode::L, Key::L),
+    (Scancode::M, Key::M),
+    (Scancode::N, Key::N),
+    (Scancode::O, Key::O),
+    (Scancode::P, Key::P),
+    (Scancode::Q, Key::Q),
+    (Scancode::R, Key::R),
+    (Scancode::S, Key::S),
+    (Scancode::T, Key::T),
+    (Scancode::U, Key::U),
+    (Scancode::V, Key::V),
+    (Scancode::W, Key::W),
+    (Scancode::X, Key::X),
+    (Scancode::Y, Key::Y),
+    (Scancode::Z, Key::Z),
+    (Scancode::Minus, Key::Minus),
+    (Scancode::Equals, Key::Caret),
+    (Scancode::Grave, Key::Yen),
+    (Scancode::LeftBracket, Key::At),
+    (Scancode::RightBracket, Key::LeftBracket),
+    (Scancode::Backslash, Key::RightBracket),
+    (Scancode::Semicolon, Key::Semicolon),
+    (Scancode::Apostrophe, Key::Colon),
+    (Scancode::Comma, Key::Comma),
+    (Scancode::Period, Key::Period),
+    (Scancode::Slash, Key::Slash),
+    (Scancode::International1, Key::Underscore),
+    (Scancode::Return, Key::Return),
+    (Scancode::Pause, Key::Stop),
+    (Scancode::RAlt, Key::Kana),
+    (Scancode::LShift, Key::LeftShift),
+    (Scancode::RShift, Key::RightShift),
+    (Scancode::LCtrl, Key::Ctr),
+    (Scancode::Escape, Key::Esc),
+    (Scancode::LAlt, Key::Grph),
+    (Scancode::Space, Key::Space),
+    (Scancode::Home, Key::ClrHome),
+    (Scancode::Insert, Key::Ins),
+    (Scancode::Delete, Key::Del),
+    (Scancode::Backspace, Key::Del),
+    (Scancode::Tab, Key::Stop),
+    (Scancode::Up, Key::Up),
+    (Scancode::Down, Key::Down),
+    (Scancode::Left, Key::Left),
+    (Scancode::Right, Key::Right),
+];
+
+#[derive(StructOpt)]
+#[structopt(version = "0.1", author = "Theodore Wang")]
+struct Opts {
+    /// Input ROM
+    #[structopt(parse(from_os_str))]
+    rom: PathBuf,
+
+    /// Contents of RAM at power-on: zeros, ones, alternating, random or random:<seed>
+    #[structopt(long, default_value = "zeros", parse(try_from_str = parse_power_on_state))]
+    power_on_ram: PowerOnState,
+
+    /// Number of frames between rewind snapshots. 0 disables rewinding.
+    #[structopt(long, default_value = "4")]
+    rewind_interval: u32,
+
+    /// Memory available to the rewind history, in MiB
+    #[structopt(long, default_value = "64")]
+    rewind_memory: usize,
+
+    /// What to play while rewinding: reversed or silence
+    #[structopt(long, default_value = "reversed")]
+    rewind_audio: RewindAudio,
+
+    /// Number of frames turbo A holds A down, and then lets it go, for
+    #[structopt(long, default_value = "2")]
+    turbo_a_rate: u32,
+
+    /// Number of frames turbo B holds B down, and then lets it go, for
+    #[structopt(long, default_value = "2")]
+    turbo_b_rate: u32,
+
+    /// Number of frames to run ahead of the one shown, to hide input lag
+    #[structopt(long, default_value = "0")]
+    run_ahead: u32,
+
+    /// Records input to an .fm2 movie, which is written when the emulator exits
+    #[structopt(long, parse(from_os_str))]
+    record: Option<PathBuf>,
+
+    /// Starts the recording from a save state instead of power-on
+    #[structopt(long, parse(from_os_str), requires = "record")]
+    record_from: Option<PathBuf>,
+
+    /// Plays back an .fm2 movie
+    #[structopt(long, parse(from_os_str), conflicts_with = "record")]
+    play: Option<PathBuf>,
+
+    /// Device plugged into the first controller port: standard, four-score or none.
+    /// Defaults to what the ROM's NES 2.0 header asks for, or standard.
+    #[structopt(long)]
+    port1: Option<DeviceChoice>,
+
+    /// Device plugged into the second controller port: standard, zapper, four-score, vaus,
+    /// power-pad-a, power-pad-b or none.
+    /// Defaults to what the ROM's NES 2.0 header asks for, or standard.
+    #[structopt(long)]
+    port2: Option<DeviceChoice>,
+
+    /// Device plugged into the Famicom expansion port: four-players, vaus, keyboard or none.
+    /// Defaults to what the ROM's NES 2.0 header asks for, or none.
+    #[structopt(long)]
+    expansion: Option<ExpansionChoice>,
+
+    /// Key and game controller bindings, in the format of default.ini. Defaults to pretendo.ini,
+    /// if there is one.
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// The data recorder's tape, as a .wav file. Defaults to <rom>.wav.
+    #[structopt(long, parse(from_os_str))]
+    tape: Option<PathBuf>,
+}
+
+/// The devices that can be plugged into the controller ports from the command line
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum DeviceChoice {
+    None,
+    Standard,
+    /// Aimed with the mouse and fired with the left button
+    Zapper,
+    /// Takes up both ports, so it should be chosen for both
+    FourScore,
+    /// The NES Arkanoid controller, turned by moving the mouse
+    Vaus,
+    PowerPad(PowerPadSide),
+}
+
+impl FromStr for DeviceChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(DeviceChoice::None),
+            "standard" => Ok(DeviceChoice::Standard),
+            "zapper" => Ok(DeviceChoice::Zapper),
+            "four-score" => Ok(DeviceChoice::FourScore),
+            "vaus" => Ok(DeviceChoice::Vaus),
+            "power-pad-a" => Ok(DeviceChoice::PowerPad(PowerPadSide::A)),
+            "power-pad-b" => Ok(DeviceChoice::PowerPad(PowerPadSide::B)),
+            _ => Err(format!(
+                "invalid device '{}' (expected standard, zapper, four-score, vaus, power-pad-a, \
+                 power-pad-b or none)",
+                s
+            )),
+        }
+    }
+}
+
+/// The devices that can be plugged into the expansion port from the command line
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ExpansionChoice {
+    None,
+    FourPlayers,
+    /// The Famicom Arkanoid controller, turned by moving the mouse
+    Vaus,
+    /// The Family BASIC keyboard, with a data recorder
+    Keyboard,
+}
+
+impl FromStr for ExpansionChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ExpansionChoice::None),
+            "four-players" => Ok(ExpansionChoice::FourPlayers),
+            "vaus" => Ok(ExpansionChoice::Vaus),
+            "keyboard" => Ok(ExpansionChoice::Keyboard),
+            _ => Err(format!(
+                "invalid expansion device '{}' (expected four-players, vaus, keyboard or none)",
+                s
+            )),
+        }
+    }
+}
+
+/// The devices to plug into port one, port two and the expansion port when the command line
+/// doesn't say
+fn default_devices(
+    expansion_device: Option<ExpansionDevice>,
+) -> (DeviceChoice, DeviceChoice, ExpansionChoice) {
+    match expansion_device {
+        Some(ExpansionDevice::FourScore) => (
+            DeviceChoice::FourScore,
+            DeviceChoice::FourScore,
+            ExpansionChoice::None,
+        ),
+        Some(ExpansionDevice::FamicomFourPlayers) => (
+            DeviceChoice::Standard,
+            DeviceChoice::Standard,
+            ExpansionChoice::FourPlayers,
+        ),
+        Some(ExpansionDevice::Zapper) => (
+            DeviceChoice::Standard,
+            DeviceChoice::Zapper,
+            ExpansionChoice::None,
+        ),
+        Some(ExpansionDevice::Vaus(VausModel::Nes)) => (
+            DeviceChoice::Standard,
+            DeviceChoice::Vaus,
+            ExpansionChoice::None,
+        ),
+        Some(ExpansionDevice::Vaus(VausModel::Famicom)) => (
+            DeviceChoice::Standard,
+            DeviceChoice::Standard,
+            ExpansionChoice::Vaus,
+        ),
+        Some(ExpansionDevice::PowerPad(side)) => (
+            DeviceChoice::Standard,
+            DeviceChoice::PowerPad(side),
+            ExpansionChoice::None,
+        ),
+        Some(ExpansionDevice::FamilyBasicKeyboard) => (
+            DeviceChoice::Standard,
+            DeviceChoice::Standard,
+            ExpansionChoice::Keyboard,
+        ),
+        Some(ExpansionDevice::StandardControllers) | None => (
+            DeviceChoice::Standard,
+            DeviceChoice::Standard,
+            ExpansionChoice::None,
+        ),
+    }
+}
+
+/// Creates the device for a controller port. A standard controller in port one is player 1's, and
+/// one in port two is player 2's.
+fn create_device(choice: DeviceChoice, port: Port) -> Option<Box<dyn InputDevice>> {
+    let player: usize = if port == Port::Two { 1 } else { 0 };
+    match choice {
+        DeviceChoice::None => None,
+        DeviceChoice::Standard => Some(Box::new(StandardController::new(player))),
+        DeviceChoice::Zapper => Some(Box::new(Zapper::new())),
+        DeviceChoice::FourScore => Some(Box::new(FourScore::new(port))),
+        DeviceChoice::Vaus => Some(Box::new(Vaus::new(VausModel::Nes))),
+        DeviceChoice::PowerPad(side) => Some(Box::new(PowerPad::new(side))),
+    }
+}
+
+fn create_expansion_device(choice: ExpansionChoice) -> Option<Box<dyn InputDevice>> {
+    match choice {
+        ExpansionChoice::None => None,
+        ExpansionChoice::FourPlayers => Some(Box::new(FamicomFourPlayers::new())),
+        ExpansionChoice::Vaus => Some(Box::new(Vaus::new(VausModel::Famicom))),
+        ExpansionChoice::Keyboard => Some(Box::new(FamilyBasicKeyboard::new())),
+    }
+}
+
+/// Reads the bindings from the config file. Only `CONFIG_PATH` is allowed to be missing.
+fn load_bindings(path: Option<&Path>) -> Result<Bindings, String> {
+    let (path, required) = match path {
+        Some(path) => (path, true),
+        None => (Path::new(CONFIG_PATH), false),
+    };
+    let config: String = match fs::read_to_string(path) {
+        Ok(config) => config,
+        Err(_) if !required => String::new(),
+        Err(err) => return Err(format!("Could not read {}: {}", path.display(), err)),
+    };
+    Bindings::from_config(&config).map_err(|err| format!("Error in {}: {}", path.display(), err))
+}
+
+/// Like `PowerOnState::from_str`, but a plain `random` picks a seed from the current time
+fn parse_power_on_state(s: &str) -> Result<PowerOnState, ParsePowerOnStateError> {
+    if s == "random" {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        return Ok(PowerOnState::Random(seed));
+    }
+    s.parse()
+}
+
+/// Save states live next to the ROM, as `<rom>.ss0` through `<rom>.ss9`
+fn save_state_path(rom: &Path, slot: usize) -> PathBuf {
+    rom.with_extension(format!("ss{}", slot))
+}
+
+fn save_state(emulator: &mut Emulator, path: &Path) {
+    match fs::write(path, emulator.save_state()) {
+        Ok(()) => println!("Saved state to {}", path.display()),
+        Err(err) => eprintln!("Could not save state to {}: {}", path.display(), err),
+    }
+}
+
+fn load_state(emulator: &mut Emulator, path: &Path) {
+    match fs::read(path) {
+        Ok(data) => match emulator.load_state(&data) {
+            Ok(()) => println!("Loaded state from {}", path.display()),
+            Err(err) => eprintln!("Could not load state from {}: {}", path.display(), err),
+        },
+        Err(err) => eprintln!("Could not read {}: {}", path.display(), err),
+    }
+}
+
+fn record_movie(emulator: &mut Emulator, path: &Path, rom: &Path, state: Option<&Path>) {
+    let start: MovieStart = match state {
+        Some(path) => match fs::read(path) {
+            Ok(data) => MovieStart::SaveState(data),
+            Err(err) => return eprintln!("Could not read {}: {}", path.display(), err),
+        },
+        None => MovieStart::PowerOn,
+    };
+    let rom_filename = rom
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    match emulator.record_movie(Movie::new(&rom_filename, start)) {
+        Ok(()) => println!("Recording to {}", path.display()),
+        Err(err) => eprintln!("Could not start recording: {}", err),
+    }
+}
+
+fn play_movie(emulator: &mut Emulator, path: &Path) {
+    let movie: Movie = match fs::read_to_string(path) {
+        Ok(text) => match Movie::from_fm2(&text) {
+            Ok(movie) => movie,
+            Err(err) => return eprintln!("Could not read {}: {}", path.display(), err),
+        },
+        Err(err) => return eprintln!("Could not read {}: {}", path.display(), err),
+    };
+    match emulator.play_movie(movie) {
+        Ok(()) => println!("Playing {}", path.display()),
+        Err(err) => eprintln!("Could not play {}: {}", path.display(), err),
+    }
+}
+
+fn save_movie(emulator: &mut Emulator, path: &Path) {
+    if let Some(movie) = emulator.stop_movie() {
+        match fs::write(path, movie.to_fm2()) {
+            Ok(()) => println!("Saved movie to {}", path.display()),
+            Err(err) => eprintln!("Could not save movie to {}: {}", path.display(), err),
+        }
+    }
+}
+
+/// Puts the tape in the data recorder, if there's a data recorder and the tape exists
+fn load_tape(emulator: &mut Emulator, path: &Path) {
+    let recorder = match emulator.data_recorder() {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    match fs::read(path) {
+        Ok(data) => match Tape::from_wav(&data) {
+            Ok(tape) => {
+                recorder.insert(tape);
+                println!("Inserted tape {}", path.display());
+            }
+            Err(err) => eprintln!("Could not read {}: {}", path.display(), err),
+        },
+        Err(_) => println!(
+            "Inserted a blank tape, which is saved to {}",
+            path.display()
+        ),
+    }
+}
+
+/// Stops the data recorder, saving the tape if it was recording
+fn stop_tape(emulator: &mut Emulator, path: &Path) {
+    let recorder = match emulator.data_recorder() {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    let recording: bool = recorder.state() == TapeState::Recording;
+    recorder.stop();
+    if recording {
+        match fs::write(path, recorder.tape().to_wav()) {
+            Ok(()) => println!("Saved tape to {}", path.display()),
+            Err(err) => eprintln!("Could not save tape to {}: {}", path.display(), err),
+        }
+    }
+}
+
+fn main() -> Result<(), ROMError> {
+    // Parse arguments
+    let opts = Opts::from_args();
+    let bindings: Bindings = match load_bindings(opts.config.as_deref()) {
+        Ok(bindings) => bindings,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // Initialize NES
+    let mut rom = File::open(&opts.rom)?;
+    let ines: INES = INES::from_file(&mut rom)?;
+    let (port1, port2, expansion) = default_devices(ines.expansion_device());
+    let mapper: Box<dyn Mapper> = ines.to_mapper();
+    let nes: NES = NES::with_power_on_state(mapper, opts.power_on_ram);
+    let mut emulator = Emulator::new(nes);
+    if opts.rewind_interval > 0 {
+        emulator.set_rewind(Some(RewindConfig {
+            interval: opts.rewind_interval,
+            memory_limit: opts.rewind_memory * 1024 * 1024,
+            audio: opts.rewind_audio,
+        }));
+    }
+    emulator.set_run_ahead(opts.run_ahead);
+    emulator.set_turbo(Turbo {
+        a: opts.turbo_a_rate,
+        b: opts.turbo_b_rate,
+    });
+    let port1: DeviceChoice = opts.port1.unwrap_or(port1);
+    let port2: DeviceChoice = opts.port2.unwrap_or(port2);
+    let expansion: ExpansionChoice = opts.expansion.unwrap_or(expansion);
+    emulator.set_device(Port::One, create_device(port1, Port::One));
+    emulator.set_device(Port::Two, create_device(port2, Port::Two));
+    emulator.set_device(Port::Expansion, create_expansion_device(expansion));
+    let uses_paddle: bool = port1 == DeviceChoice::Vaus
+        || port2 == DeviceChoice::Vaus
+        || expansion == ExpansionChoice::Vaus;
+    let uses_keyboard: bool = expansion == ExpansionChoice::Keyboard;
+    let uses_power_pad: bool =
+        matches!(port1, DeviceChoice::PowerPad(_)) || matches!(port2, DeviceChoice::PowerPad(_));
+    let tape_path: PathBuf = opts
+        .tape
+        .clone()
+        .unwrap_or_else(|| opts.rom.with_extension("wav"));
+    load_tape(&mut emulator, &tape_path);
+    if let Some(path) = &opts.record {
+        record_movie(&mut emulator, path, &opts.rom, opts.record_from.as_deref());
+    } else if let Some(path) = &opts.play {
+        play_movie(&mut emulator, path);
+    }
+
+    // Initialize an SDL window and texture
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let window = video_subsystem
+        .window(
+            "Pretendo Entertainment System",
+            Display::WIDTH as u32 * PIXEL_SCALE,
+            Display::HEIGHT as u32 * PIXEL_SCALE,
+        )
+        .position_centered()
+        .build()
+        .unwrap();
+    let mut canvas: WindowCanvas = window.into_canvas().present_vsync().build().unwrap();
+    let texture_creator: TextureCreator<_> = canvas.texture_creator();
+    let mut texture: Texture = texture_creator
+        .create_texture_streaming(
+            PixelFormatEnum::RGBA8888,
+            Display::WIDTH as u32,
+            Display::HEIGHT as u32,
+        )
+        .expect("Could not create texture!");
+
+    // Initialize an SDL event pump
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    // Paddles follow the mouse's movement, so keep it in the window
+    let mouse_knob: bool = matches!(bindings.vaus_knob, KnobAxis::X | KnobAxis::Y);
+    sdl_context
+        .mouse()
+        .set_relative_mouse_mode(uses_paddle && mouse_knob);
+
+    // Game controllers are opened as they're plugged in, including the ones plugged in already
+    let game_controller_subsystem = sdl_context.game_controller().unwrap();
+    let mut pads = Pads::new();
+
+    // Initialize the SDL audio system
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let desired_spec = AudioSpecDesired {
+        freq: Some(44100),
+        channels: Some(1),
+        samples: Some(1024),
+    };
+    let audio_queue = audio_subsystem
+        .open_queue::<f32, _>(None, &desired_spec)
+        .unwrap();
+
+    // Initialize some helper variables
+    let sleep_duration = Duration::new(0, 1_000_000_000u32 / 60);
+    let mut save_state_slot: usize = 0;
+    let mut playing_movie: bool = emulator.is_playing_movie();
+    let mut paddle_position: f32 = 0.5;
+    // the Family BASIC keyboard gets every key until capture_keyboard is pressed
+    let mut keyboard_captured: bool = uses_keyboard;
+    if uses_keyboard {
+        println!("Keyboard captured, press the capture_keyboard hotkey to release it");
+    }
+
+    'running: loop {
+        let start = Instant::now();
+
+        // wait for quit or hotkeys
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(pad) = pads.add(&game_controller_subsystem, which) {
+                        println!("Plugged in pad{}: {}", pad + 1, pads.name(pad));
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    if let Some(pad) = pads.remove(which) {
+                        println!("Unplugged pad{}", pad + 1);
+                    }
+                }
+                _ => {}
+            }
+            let pressed: Binding = match pressed_binding(&event, &pads) {
+                Some(pressed) => pressed,
+                None => continue,
+            };
+            let hotkeys = &bindings.hotkeys;
+            if uses_keyboard && hotkeys.capture_keyboard.contains(&pressed) {
+                keyboard_captured = !keyboard_captured;
+                if keyboard_captured {
+                    println!("Keyboard captured, press the capture_keyboard hotkey to release it");
+                } else {
+                    println!("Keyboard released");
+                }
+            } else if keyboard_captured && matches!(pressed, Binding::Key(_)) {
+                // typed on the Family BASIC keyboard
+            } else if uses_power_pad
+                && bindings
+                    .power_pad
+                    .iter()
+                    .any(|square| square.contains(&pressed))
+            {
+                // stepped on the Power Pad
+            } else if hotkeys.quit.contains(&pressed) {
+                break 'running;
+            } else if hotkeys.reset.contains(&pressed) {
+                emulator.reset();
+            } else if hotkeys.power_cycle.contains(&pressed) {
+                emulator.power_cycle();
+            } else if hotkeys.save_state.contains(&pressed) {
+                save_state(&mut emulator, &save_state_path(&opts.rom, save_state_slot));
+            } else if hotkeys.load_state.contains(&pressed) {
+                load_state(&mut emulator, &save_state_path(&opts.rom, save_state_slot));
+            } else if hotkeys.play_tape.contains(&pressed) {
+                if let Some(recorder) = emulator.data_recorder() {
+                    recorder.play();
+                    println!("Playing tape");
+                }
+            } else if hotkeys.record_tape.contains(&pressed) {
+                if let Some(recorder) = emulator.data_recorder() {
+                    recorder.record();
+                    println!("Recording tape");
+                }
+            } else if hotkeys.stop_tape.contains(&pressed) {
+                stop_tape(&mut emulator, &tape_path);
+            } else if let Some(slot) = hotkeys
+                .slots
+                .iter()
+                .position(|slot| slot.contains(&pressed))
+            {
+                save_state_slot = slot;
+                println!("Selected save state slot {}", slot);
+            }
+        }
+
+        // run the NES for a frame, or step back one while rewind is held
+        let keyboard = event_pump.keyboard_state();
+        let inputs = Inputs {
+            keyboard: if keyboard_captured {
+                None
+            } else {
+                Some(event_pump.keyboard_state())
+            },
+            mouse: event_pump.mouse_state(),
+            pads: &pads,
+            deadzone: bindings.deadzone,
+        };
+        let rewinding: bool = inputs.any_held(&bindings.hotkeys.rewind);
+        let mut input = Input::default();
+        if keyboard_captured {
+            for &(scancode, key) in FAMILY_KEYBOARD_MAP.iter() {
+                if keyboard.is_scancode_pressed(scancode) {
+                    input.keys.press(key);
+                }
+            }
+        }
+        for (buttons, controller) in input.buttons.iter_mut().zip(bindings.controllers.iter()) {
+            let held = |button: usize| inputs.any_held(&controller[button]);
+            *buttons = Buttons {
+                a: held(0),
+                b: held(1),
+                select: held(2),
+                start: held(3),
+                up: held(4),
+                down: held(5),
+                left: held(6),
+                right: held(7),
+                turbo_a: held(8),
+                turbo_b: held(9),
+            };
+        }
+        for (square, square_bindings) in input.mat.iter_mut().zip(bindings.power_pad.iter()) {
+            *square = inputs.any_held(square_bindings);
+        }
+        let (x, y) = (
+            inputs.mouse.x() / PIXEL_SCALE as i32,
+            inputs.mouse.y() / PIXEL_SCALE as i32,
+        );
+        let on_screen: bool =
+            (0..Display::WIDTH as i32).contains(&x) && (0..Display::HEIGHT as i32).contains(&y);
+        input.pointer = Pointer {
+            position: if on_screen {
+                Some((x as usize, y as usize))
+            } else {
+                None
+            },
+            trigger: inputs.any_held(&bindings.zapper_trigger),
+        };
+        // moving the mouse across the window turns the knob all the way
+        let motion = event_pump.relative_mouse_state();
+        let mouse_turn = |distance: i32, window_size: u32| {
+            (paddle_position + distance as f32 / window_size as f32).clamp(0.0, 1.0)
+        };
+        paddle_position = match bindings.vaus_knob {
+            KnobAxis::X => mouse_turn(motion.x(), Display::WIDTH as u32 * PIXEL_SCALE),
+            KnobAxis::Y => mouse_turn(motion.y(), Display::HEIGHT as u32 * PIXEL_SCALE),
+            KnobAxis::Pad(pad, axis) => pads
+                .get(pad)
+                .map_or(paddle_position, |pad| pads::axis_position(pad, axis)),
+        };
+        input.paddle = Paddle {
+            position: paddle_position,
+            button: inputs.any_held(&bindings.vaus_button),
+        };
+        emulator.set_input(input);
+        let frame = if rewinding {
+            // the oldest frame stays on screen once the history runs out
+            emulator.rewind_frame()
+        } else {
+            Some(emulator.run_frame())
+        };
+        if let Some(frame) = frame {
+            // Start playback
+            audio_queue.queue(frame.audio);
+            audio_queue.resume();
+
+            // update the display
+            texture
+                .update(
+                    None,
+                    frame.framebuffer,
+                    Display::WIDTH * Display::BYTES_PER_PIXEL,
+                )
+                .expect("Could not update texture!");
+            canvas.clear();
+            canvas
+                .copy(&texture, None, None)
+                .expect("Could not copy texture!");
+            canvas.present();
+        }
+
+        if playing_movie && !emulator.is_playing_movie() {
+            playing_movie = false;
+            println!("Movie finished");
+        }
+
+        // sleep for the remaining time
+        let end = Instant::now();
+        if end - start < sleep_duration {
+            ::std::thread::sleep(sleep_duration - (end - start));
+        }
+    }
+
+    if let Some(path) = &opts.record {
+        save_movie(&mut emulator, path);
+    }
+    stop_tape(&mut emulator, &tape_path);
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use sdl2::controller::{Axis, GameController};
+use sdl2::GameControllerSubsystem;
+
+/// A stick pushed less than this far from a direction still counts as pushed in that direction,
+/// so that diagonals press two directions (sin 22.5°)
+const DIAGONAL: f32 = 0.383;
+
+/// The game controllers that are plugged in. A controller keeps its number while it's plugged
+/// in, and a newly plugged in one takes the lowest number that's free, so unplugging one
+/// controller doesn't move the other players to a different one.
+pub struct Pads {
+    slots: Vec<Option<GameController>>,
+}
+
+impl Pads {
+    pub fn new() -> Self {
+        Pads { slots: Vec::new() }
+    }
+
+    /// Opens the controller with this device index, returning its number. SDL also reports the
+    /// controllers that are plugged in at startup this way.
+    pub fn add(&mut self, subsystem: &GameControllerSubsystem, index: u32) -> Option<usize> {
+        let pad: GameController = subsystem.open(index).ok()?;
+        if self.position(pad.instance_id() as u32).is_some() {
+            return None;
+        }
+        match self.slots.iter().position(Option::is_none) {
+            Some(slot) => {
+                self.slots[slot] = Some(pad);
+                Some(slot)
+            }
+            None => {
+                self.slots.push(Some(pad));
+                Some(self.slots.len() - 1)
+            }
+        }
+    }
+
+    /// Closes the controller with this instance ID, returning its number
+    pub fn remove(&mut self, instance_id: u32) -> Option<usize> {
+        let slot: usize = self.position(instance_id)?;
+        self.slots[slot] = None;
+        Some(slot)
+    }
+
+    /// The number of the controller with this instance ID
+    pub fn position(&self, instance_id: u32) -> Option<usize> {
+        self.slots.iter().position(|pad| {
+            pad.as_ref()
+                .is_some_and(|pad| pad.instance_id() as u32 == instance_id)
+        })
+    }
+
+    pub fn get(&self, pad: usize) -> Option<&GameController> {
+        self.slots.get(pad)?.as_ref()
+    }
+
+    pub fn name(&self, pad: usize) -> String {
+        self.get(pad).map_or_else(String::new, GameController::name)
+    }
+}
+
+impl Default for Pads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether an axis is pushed past the deadzone, from 0 to 1, in the positive or negative
+/// direction. Sticks are read like a d-pad: the deadzone is measured from the center, and each
+/// direction covers a 135° slice of the stick's movement, which overlaps at the diagonals.
+pub fn is_axis_pushed(pad: &GameController, axis: Axis, positive: bool, deadzone: f32) -> bool {
+    let value = |axis: Axis| f32::from(pad.axis(axis)) / f32::from(i16::MAX);
+    let other: Option<Axis> = match axis {
+        Axis::LeftX => Some(Axis::LeftY),
+        Axis::LeftY => Some(Axis::LeftX),
+        Axis::RightX => Some(Axis::RightY),
+        Axis::RightY => Some(Axis::RightX),
+        Axis::TriggerLeft | Axis::TriggerRight => None,
+    };
+    let pushed: f32 = if positive { value(axis) } else { -value(axis) };
+    let distance: f32 = match other {
+        Some(other) => pushed.hypot(value(other)),
+        None => pushed,
+    };
+    distance > deadzone && pushed > distance * DIAGONAL
+}
+
+/// Where an axis is, from 0 to 1. Sticks go from one end to the other, and triggers from released
+/// to pulled all the way.
+pub fn axis_position(pad: &GameController, axis: Axis) -> f32 {
+    let value: f32 = f32::from(pad.axis(axis)) / f32::from(i16::MAX);
+    let position: f32 = match axis {
+        Axis::TriggerLeft | Axis::TriggerRight => value,
+        _ => (value + 1.0) / 2.0,
+    };
+    position.clamp(0.0, 1.0)
+}
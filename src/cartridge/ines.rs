@@ -2,16 +2,22 @@ use super::mapper0::Mapper0;
 use super::mapper2::Mapper2;
 use super::{Mapper, NametableMirroring, CHR_PAGE_SIZE, PRG_PAGE_SIZE, TRAINER_SIZE};
 use crate::bitops::BitOps;
+use crate::state::io::ExpansionDevice;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 use std::io;
 
 pub struct INES {
     pub prg: Vec<u8>,
     pub chr: Vec<u8>,
+    /// Cartridges without CHR-ROM come with CHR-RAM instead
+    chr_ram: bool,
     flags6: u8,
     flags7: u8,
     _flags8: u8,
     _flags9: u8,
     _flags10: u8,
+    /// Byte 15 of an NES 2.0 header, or 0 for older headers
+    default_expansion_device: u8,
 }
 
 impl INES {
@@ -20,11 +26,13 @@ impl INES {
         INES {
             prg,
             chr,
+            chr_ram: false,
             flags6: 0,
             flags7: 0,
             _flags8: 0,
             _flags9: 0,
             _flags10: 0,
+            default_expansion_device: 0,
         }
     }
 
@@ -40,11 +48,14 @@ impl INES {
         let _flags8 = header[8];
         let _flags9 = header[9];
         let _flags10 = header[10];
+        let nes2: bool = flags7 & 0b1100 == 0b1000;
+        let default_expansion_device: u8 = if nes2 { header[15] & 0b11_1111 } else { 0 };
         if flags6.is_bit_set(2) {
             take(file, TRAINER_SIZE)?;
         }
         let prg = take(file, prg_pages as usize * PRG_PAGE_SIZE)?;
-        let chr = if chr_pages == 0 {
+        let chr_ram: bool = chr_pages == 0;
+        let chr = if chr_ram {
             vec![0; CHR_PAGE_SIZE]
         } else {
             take(file, chr_pages as usize * CHR_PAGE_SIZE)?
@@ -52,11 +63,13 @@ impl INES {
         Ok(INES {
             prg,
             chr,
+            chr_ram,
             flags6,
             flags7,
             _flags8,
             _flags9,
             _flags10,
+            default_expansion_device,
         })
     }
 
@@ -76,6 +89,39 @@ impl INES {
             _ => NametableMirroring::FourScreen,
         }
     }
+
+    /// The devices the game expects to be plugged in, if the header says and they're supported
+    pub fn expansion_device(&self) -> Option<ExpansionDevice> {
+        ExpansionDevice::from_id(self.default_expansion_device)
+    }
+
+    /// The CRC32 of the PRG-ROM and CHR-ROM, which identifies the game
+    pub fn crc32(&self) -> u32 {
+        let chr_rom: &[u8] = if self.chr_ram { &[] } else { &self.chr };
+        !self.prg.iter().chain(chr_rom).fold(!0, |crc, &byte| {
+            (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+                (crc >> 1) ^ (CRC32_POLYNOMIAL & (!(crc & 1)).wrapping_add(1))
+            })
+        })
+    }
+}
+
+const CRC32_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// Only CHR-RAM is saved, since everything else in the ROM is read-only
+impl Snapshot for INES {
+    fn save(&self, writer: &mut StateWriter) {
+        if self.chr_ram {
+            writer.write_bytes(&self.chr);
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        if self.chr_ram {
+            reader.read_bytes(&mut self.chr)?;
+        }
+        Ok(())
+    }
 }
 
 fn take(file: &mut impl io::Read, length: usize) -> Result<Vec<u8>, ROMError> {
@@ -95,3 +141,27 @@ impl From<io::Error> for ROMError {
         ROMError::IOError(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        let rom = INES::mock(b"12345".to_vec(), b"6789".to_vec());
+        assert_eq!(rom.crc32(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_expansion_device() {
+        let mut file: Vec<u8> = b"NES\x1A\x01\x01\x00\x08\x00\x00\x00\x00\x00\x00\x00\x02".to_vec();
+        file.resize(16 + PRG_PAGE_SIZE + CHR_PAGE_SIZE, 0);
+        let rom = INES::from_file(&mut &file[..]).unwrap();
+        assert_eq!(rom.expansion_device(), Some(ExpansionDevice::FourScore));
+
+        // iNES headers don't have the field
+        file[7] = 0;
+        let rom = INES::from_file(&mut &file[..]).unwrap();
+        assert_eq!(rom.expansion_device(), None);
+    }
+}
@@ -1,5 +1,6 @@
 use super::ines::INES;
 use super::{CPUMapper, NametableMirroring, PPUMapper, PRG_RAM_SIZE};
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 
 #[cfg(test)]
 use super::{CHR_PAGE_SIZE, PRG_PAGE_SIZE};
@@ -44,6 +45,26 @@ impl CPUMapper for Mapper0 {
             _ => unreachable!(),
         }
     }
+
+    fn get_rom(&self) -> &INES {
+        &self.rom
+    }
+
+    fn get_prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+impl Snapshot for Mapper0 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.prg_ram);
+        self.rom.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_bytes(&mut self.prg_ram)?;
+        self.rom.load(reader)
+    }
 }
 
 impl PPUMapper for Mapper0 {
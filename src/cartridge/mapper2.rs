@@ -1,5 +1,6 @@
 use super::ines::INES;
 use super::{CPUMapper, NametableMirroring, PPUMapper, PRG_PAGE_SIZE, PRG_RAM_SIZE};
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 
 pub struct Mapper2 {
     total_banks: usize,
@@ -44,6 +45,31 @@ impl CPUMapper for Mapper2 {
             _ => unreachable!(),
         }
     }
+
+    fn get_rom(&self) -> &INES {
+        &self.rom
+    }
+
+    fn get_prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+}
+
+impl Snapshot for Mapper2 {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_usize(self.lower_bank);
+        writer.write_bytes(&self.prg_ram);
+        self.rom.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.lower_bank = reader.read_usize()?;
+        if self.lower_bank >= self.total_banks {
+            return Err(SaveStateError::InvalidData);
+        }
+        reader.read_bytes(&mut self.prg_ram)?;
+        self.rom.load(reader)
+    }
 }
 
 impl PPUMapper for Mapper2 {
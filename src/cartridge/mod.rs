@@ -2,6 +2,9 @@ pub mod ines;
 pub mod mapper0;
 mod mapper2;
 
+use crate::state::snapshot::Snapshot;
+use ines::INES;
+
 const PRG_PAGE_SIZE: usize = 0x4000;
 const CHR_PAGE_SIZE: usize = 0x2000;
 const PRG_RAM_SIZE: usize = 0x2000;
@@ -12,6 +15,22 @@ const TRAINER_SIZE: usize = 0x200;
 pub trait CPUMapper {
     fn get(&self, addr: u16) -> u8;
     fn set(&mut self, addr: u16, val: u8);
+
+    /// Returns the ROM the mapper was created from
+    fn get_rom(&self) -> &INES;
+
+    /// Whether the mapper is pulling the IRQ line low
+    fn is_irq_asserted(&self) -> bool {
+        false
+    }
+
+    /// Called when the console's reset button is pressed
+    fn reset(&mut self) {}
+
+    /// Returns the mapper's PRG-RAM, if it has any
+    fn get_prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
 }
 
 /// The mapper visible to the PPU
@@ -21,15 +40,16 @@ pub trait PPUMapper {
     fn get_nametable_mirroring(&self) -> NametableMirroring;
 }
 
-/// Trait representing a mapper
-pub trait Mapper: CPUMapper + PPUMapper {
+/// Trait representing a mapper.
+/// Mappers save their own registers and RAM, but not their ROM.
+pub trait Mapper: CPUMapper + PPUMapper + Snapshot {
     fn as_cpu_mapper(&self) -> &dyn CPUMapper;
     fn as_ppu_mapper(&self) -> &dyn PPUMapper;
     fn as_cpu_mapper_mut(&mut self) -> &mut dyn CPUMapper;
     fn as_ppu_mapper_mut(&mut self) -> &mut dyn PPUMapper;
 }
 
-impl<T: CPUMapper + PPUMapper> Mapper for T {
+impl<T: CPUMapper + PPUMapper + Snapshot> Mapper for T {
     fn as_cpu_mapper(&self) -> &dyn CPUMapper {
         self
     }
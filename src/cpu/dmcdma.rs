@@ -0,0 +1,172 @@
+use crate::state::cpu::{Clock, Memory, DMCDMA};
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::state_machine::CycleState;
+
+/// The progress of a DMC DMA
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DMCDMAState {
+    /// The address of the read the CPU was halted on
+    halt_addr: u16,
+    step: DMCDMAStep,
+}
+
+/// The cycle a DMC DMA runs next
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum DMCDMAStep {
+    Dummy,
+    /// Skipped if the DMA is already on a get cycle
+    Alignment,
+    Get,
+    Done,
+}
+
+const DMC_DMA_STEPS: [DMCDMAStep; 4] = [
+    DMCDMAStep::Dummy,
+    DMCDMAStep::Alignment,
+    DMCDMAStep::Get,
+    DMCDMAStep::Done,
+];
+
+impl DMCDMAState {
+    pub fn new(halt_addr: u16) -> Self {
+        DMCDMAState {
+            halt_addr,
+            step: DMCDMAStep::Dummy,
+        }
+    }
+}
+
+impl Snapshot for DMCDMAState {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.halt_addr);
+        writer.write_u8(self.step as u8);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.halt_addr = reader.read_u16()?;
+        self.step = *DMC_DMA_STEPS
+            .get(usize::from(reader.read_u8()?))
+            .ok_or(SaveStateError::InvalidData)?;
+        Ok(())
+    }
+}
+
+/// Runs a cycle of a DMC sample fetch, after the CPU has been halted on one of its read cycles. The
+/// CPU keeps repeating that read until the DMA is over, and only then makes the read for real. This
+/// is why DMC DMA corrupts reads from registers with side effects, like $2007 and $4016.
+pub fn dmcdma<S: DMCDMA + Memory + Clock>(
+    cpu: &mut S,
+    state: &mut DMCDMAState,
+) -> CycleState<(), ()> {
+    state.step = match state.step {
+        DMCDMAStep::Dummy => {
+            cpu.dummy_read(state.halt_addr);
+            DMCDMAStep::Alignment
+        }
+        DMCDMAStep::Alignment if cpu.is_odd_cycle() => {
+            cpu.dummy_read(state.halt_addr);
+            DMCDMAStep::Get
+        }
+        DMCDMAStep::Alignment | DMCDMAStep::Get => {
+            let addr: u16 = cpu.get_dmc_dma_addr();
+            let val: u8 = cpu.get_mem(addr);
+            cpu.load_dmc_sample(val);
+            DMCDMAStep::Done
+        }
+        DMCDMAStep::Done => return CycleState::Complete(()),
+    };
+    CycleState::Yielded(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::ines::INES;
+    use crate::cartridge::mapper0::Mapper0;
+    use crate::cpu::{cycle, ExecutionState, InstructionState};
+    use crate::state::cpu::{Execution, InterruptState, Memory, Registers, DMCDMA};
+    use crate::state::ppu::{DebugRegisters, VBlank};
+    use crate::state::NES;
+
+    /// An NES whose NMI vector points to $9000, and whose IRQ/BRK vector points to $A000
+    fn nes_with_vectors() -> NES {
+        let mut prg: Vec<u8> = vec![0; 0x4000];
+        prg[0x3FFA..0x3FFC].copy_from_slice(&[0x00, 0x90]);
+        prg[0x3FFE..0x4000].copy_from_slice(&[0x00, 0xA0]);
+        NES::new(Box::new(Mapper0::new(INES::mock(prg, vec![0; 0x2000]))))
+    }
+
+    fn start_dmc_dma(cpu: &mut NES) {
+        cpu.set_mem(0x4012, 0x01);
+        cpu.set_mem(0x4013, 0x00);
+        cpu.set_mem(0x4015, 0b1_0000);
+    }
+
+    #[test]
+    fn test_dmcdma() {
+        let mut cpu = NES::mock();
+        // LDA $2007
+        cpu.set_mem(0x0000, 0xAD);
+        cpu.set_mem(0x0001, 0x07);
+        cpu.set_mem(0x0002, 0x20);
+        cpu.set_pc(0x0000);
+        cpu.set_mem(0x2006, 0x20);
+        cpu.set_mem(0x2006, 0x00);
+        for val in 0..8 {
+            cpu.set_mem(0x2007, val);
+        }
+        cpu.set_mem(0x2006, 0x20);
+        cpu.set_mem(0x2006, 0x00);
+        // run up to the read of $2007
+        for _ in 0..3 {
+            cycle(&mut cpu);
+        }
+        start_dmc_dma(&mut cpu);
+        let mut stalled = 0;
+        while let InstructionState::DMCDMA = cycle(&mut cpu) {
+            stalled += 1;
+        }
+        assert!(stalled == 3 || stalled == 4);
+        assert!(!cpu.is_dmc_dma_pending());
+        // the read of $2007 was repeated on every stalled cycle but the get, and the CPU got the
+        // last one
+        assert_eq!(cpu.get_v(), 0x2000 + stalled);
+        assert_eq!(u16::from(cpu.get_a()), stalled - 2);
+    }
+
+    #[test]
+    fn test_dmcdma_during_nmi_hijack() {
+        let mut cpu = nes_with_vectors();
+        // BRK
+        cpu.set_mem(0x0000, 0x00);
+        cpu.set_pc(0x0000);
+        cpu.set_mem(0x2000, 0b1000_0000);
+        cpu.start_vblank();
+        // run up to the read of the vector, by which time the NMI has been detected
+        for _ in 0..5 {
+            cycle(&mut cpu);
+        }
+        start_dmc_dma(&mut cpu);
+        while let InstructionState::DMCDMA | InstructionState::Yielded(_) = cycle(&mut cpu) {}
+        // the halted vector read doesn't lose the NMI that hijacks BRK
+        assert_eq!(cpu.get_pc(), 0x9000);
+    }
+
+    #[test]
+    fn test_dmcdma_during_interrupt() {
+        let mut cpu = nes_with_vectors();
+        cpu.set_execution_state(ExecutionState::Interrupt(
+            InterruptState::NMI,
+            Default::default(),
+        ));
+        assert_eq!(cycle(&mut cpu), InstructionState::NMI);
+        start_dmc_dma(&mut cpu);
+        // the sequence's next read is halted, rather than the DMA waiting for it to finish
+        assert_eq!(cycle(&mut cpu), InstructionState::DMCDMA);
+        while let InstructionState::DMCDMA = cycle(&mut cpu) {}
+        assert!(!cpu.is_dmc_dma_pending());
+        for _ in 0..5 {
+            assert_eq!(cycle(&mut cpu), InstructionState::NMI);
+        }
+        assert_eq!(cpu.get_pc(), 0x9000);
+    }
+}
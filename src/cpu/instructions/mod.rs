@@ -25,6 +25,7 @@ pub mod str;
 pub mod trr;
 
 use crate::cpu::variables::{Flag, RegisterName};
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 use crate::state::CPU;
 use std::fmt::Debug;
 
@@ -116,3 +117,130 @@ pub enum InstructionName {
     ST(RegisterName),
     T(RegisterName, RegisterName),
 }
+
+impl Snapshot for InstructionName {
+    fn save(&self, writer: &mut StateWriter) {
+        match self {
+            InstructionName::ADC => writer.write_u8(0),
+            InstructionName::AND => writer.write_u8(1),
+            InstructionName::ASL => writer.write_u8(2),
+            InstructionName::BC(operand) => {
+                writer.write_u8(3);
+                operand.save(writer);
+            }
+            InstructionName::BS(operand) => {
+                writer.write_u8(4);
+                operand.save(writer);
+            }
+            InstructionName::BIT => writer.write_u8(5),
+            InstructionName::BRK => writer.write_u8(6),
+            InstructionName::CL(operand) => {
+                writer.write_u8(7);
+                operand.save(writer);
+            }
+            InstructionName::CLI => writer.write_u8(8),
+            InstructionName::CLV => writer.write_u8(9),
+            InstructionName::CP(operand) => {
+                writer.write_u8(10);
+                operand.save(writer);
+            }
+            InstructionName::DEC => writer.write_u8(11),
+            InstructionName::DE(operand) => {
+                writer.write_u8(12);
+                operand.save(writer);
+            }
+            InstructionName::EOR => writer.write_u8(13),
+            InstructionName::INC => writer.write_u8(14),
+            InstructionName::IN(operand) => {
+                writer.write_u8(15);
+                operand.save(writer);
+            }
+            InstructionName::JSR => writer.write_u8(16),
+            InstructionName::JMP => writer.write_u8(17),
+            InstructionName::LD(operand) => {
+                writer.write_u8(18);
+                operand.save(writer);
+            }
+            InstructionName::LSR => writer.write_u8(19),
+            InstructionName::NOP => writer.write_u8(20),
+            InstructionName::ORA => writer.write_u8(21),
+            InstructionName::PH(operand) => {
+                writer.write_u8(22);
+                operand.save(writer);
+            }
+            InstructionName::PL(operand) => {
+                writer.write_u8(23);
+                operand.save(writer);
+            }
+            InstructionName::ROL => writer.write_u8(24),
+            InstructionName::ROR => writer.write_u8(25),
+            InstructionName::RTI => writer.write_u8(26),
+            InstructionName::RTS => writer.write_u8(27),
+            InstructionName::SBC => writer.write_u8(28),
+            InstructionName::SE(operand) => {
+                writer.write_u8(29);
+                operand.save(writer);
+            }
+            InstructionName::ST(operand) => {
+                writer.write_u8(30);
+                operand.save(writer);
+            }
+            InstructionName::T(from, to) => {
+                writer.write_u8(31);
+                from.save(writer);
+                to.save(writer);
+            }
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        *self = match reader.read_u8()? {
+            0 => InstructionName::ADC,
+            1 => InstructionName::AND,
+            2 => InstructionName::ASL,
+            3 => InstructionName::BC(load_flag(reader)?),
+            4 => InstructionName::BS(load_flag(reader)?),
+            5 => InstructionName::BIT,
+            6 => InstructionName::BRK,
+            7 => InstructionName::CL(load_flag(reader)?),
+            8 => InstructionName::CLI,
+            9 => InstructionName::CLV,
+            10 => InstructionName::CP(load_register_name(reader)?),
+            11 => InstructionName::DEC,
+            12 => InstructionName::DE(load_register_name(reader)?),
+            13 => InstructionName::EOR,
+            14 => InstructionName::INC,
+            15 => InstructionName::IN(load_register_name(reader)?),
+            16 => InstructionName::JSR,
+            17 => InstructionName::JMP,
+            18 => InstructionName::LD(load_register_name(reader)?),
+            19 => InstructionName::LSR,
+            20 => InstructionName::NOP,
+            21 => InstructionName::ORA,
+            22 => InstructionName::PH(load_register_name(reader)?),
+            23 => InstructionName::PL(load_register_name(reader)?),
+            24 => InstructionName::ROL,
+            25 => InstructionName::ROR,
+            26 => InstructionName::RTI,
+            27 => InstructionName::RTS,
+            28 => InstructionName::SBC,
+            29 => InstructionName::SE(load_flag(reader)?),
+            30 => InstructionName::ST(load_register_name(reader)?),
+            31 => InstructionName::T(load_register_name(reader)?, load_register_name(reader)?),
+            _ => return Err(SaveStateError::InvalidData),
+        };
+        Ok(())
+    }
+}
+
+fn load_flag(reader: &mut StateReader) -> Result<Flag, SaveStateError> {
+    let mut flag = Flag::C;
+    flag.load(reader)?;
+    Ok(flag)
+}
+
+fn load_register_name(reader: &mut StateReader) -> Result<RegisterName, SaveStateError> {
+    let mut register = RegisterName::A;
+    register.load(reader)?;
+    Ok(register)
+}
@@ -1,64 +1,194 @@
 use super::variables::Flag;
 use crate::bitops::BitOps;
-use crate::state::cpu::{Memory, Registers, Stack};
-use std::cell::RefCell;
-use std::ops::Generator;
+use crate::state::cpu::{Interrupt, InterruptState, Memory, Registers, Stack};
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::state_machine::CycleState;
 
-// TODO: implement interrupt hijacking and branch instruction interrupt polling
+// TODO: implement branch instruction interrupt polling
 
 const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
 const IRQ_VECTOR: u16 = 0xFFFE;
 
-/// Creates an NMI interrupt
-pub fn nmi<'a, S: Memory + Stack + Registers>(
-    cpu: &'a RefCell<S>,
-) -> impl Generator<Yield = (), Return = ()> + 'a {
-    move || {
-        yield;
-        yield;
-        let pc_high: u8 = cpu.borrow().get_pch();
-        cpu.borrow_mut().push_stack(pc_high);
-        yield;
-        let pc_low: u8 = cpu.borrow().get_pcl();
-        cpu.borrow_mut().push_stack(pc_low);
-        yield;
-        let mut p_register: u8 = cpu.borrow().get_p();
-        p_register.clear_bit(Flag::B as usize);
-        cpu.borrow_mut().push_stack(p_register);
-        yield;
-        let interrupt_low: u8 = cpu.borrow().get_mem(NMI_VECTOR);
-        cpu.borrow_mut().set_pcl(interrupt_low);
-        cpu.borrow_mut().assign_flag(Flag::I, true);
-        yield;
-        let interrupt_high: u8 = cpu.borrow().get_mem(NMI_VECTOR + 1);
-        cpu.borrow_mut().set_pch(interrupt_high);
-        yield;
+/// The number of cycles taken by every interrupt sequence
+const SEQUENCE_LENGTH: u8 = 7;
+
+/// The progress of an interrupt sequence
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct InterruptSequence {
+    /// The cycle that runs next
+    cycle: u8,
+    /// The vector chosen on the second-last cycle, which is fetched from on the last one
+    vector: u16,
+}
+
+impl Snapshot for InterruptSequence {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.cycle);
+        writer.write_u16(self.vector);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.cycle = reader.read_u8()?;
+        self.vector = reader.read_u16()?;
+        if self.cycle > SEQUENCE_LENGTH {
+            return Err(SaveStateError::InvalidData);
+        }
+        Ok(())
+    }
+}
+
+/// Runs a cycle of a RESET interrupt.
+/// The sequence is the same as other interrupts, but the stack pushes are turned into reads. The
+/// stack pointer still gets decremented.
+pub fn reset<S: Memory + Registers>(
+    cpu: &mut S,
+    sequence: &mut InterruptSequence,
+) -> CycleState<(), ()> {
+    let cycle: u8 = sequence.cycle;
+    sequence.cycle += 1;
+    match cycle {
+        0 | 1 => {
+            let pc: u16 = cpu.get_pc();
+            cpu.dummy_read(pc);
+        }
+        2..=4 => {
+            let s: u8 = cpu.get_s();
+            cpu.dummy_read(0x100 | u16::from(s));
+            cpu.set_s(s.wrapping_sub(1));
+        }
+        5 => {
+            sequence.vector = RESET_VECTOR;
+            fetch_vector_low(cpu, sequence.vector);
+        }
+        6 => fetch_vector_high(cpu, sequence.vector),
+        _ => return CycleState::Complete(()),
+    }
+    CycleState::Yielded(())
+}
+
+/// Runs a cycle of an NMI interrupt
+pub fn nmi<S: Memory + Stack + Registers>(
+    cpu: &mut S,
+    sequence: &mut InterruptSequence,
+) -> CycleState<(), ()> {
+    let cycle: u8 = sequence.cycle;
+    sequence.cycle += 1;
+    match cycle {
+        0..=4 => push_state(cpu, cycle),
+        5 => {
+            sequence.vector = NMI_VECTOR;
+            fetch_vector_low(cpu, sequence.vector);
+        }
+        6 => fetch_vector_high(cpu, sequence.vector),
+        _ => return CycleState::Complete(()),
+    }
+    CycleState::Yielded(())
+}
+
+/// Returns the vector to fetch at the end of a BRK or IRQ sequence.
+/// If an NMI has been detected by then, it hijacks the sequence and the NMI vector is used instead.
+pub fn hijack_vector<S: Interrupt>(cpu: &mut S, vector: u16) -> u16 {
+    if cpu.poll_interrupts() == InterruptState::NMI {
+        cpu.clear_nmi();
+        NMI_VECTOR
+    } else {
+        vector
     }
 }
 
-/// Creates an IRQ interrupt
-pub fn irq<'a, S: Memory + Stack + Registers>(
-    cpu: &'a RefCell<S>,
-) -> impl Generator<Yield = (), Return = ()> + 'a {
-    move || {
-        yield;
-        yield;
-        let pc_high: u8 = cpu.borrow().get_pch();
-        cpu.borrow_mut().push_stack(pc_high);
-        yield;
-        let pc_low: u8 = cpu.borrow().get_pcl();
-        cpu.borrow_mut().push_stack(pc_low);
-        yield;
-        let mut p_register: u8 = cpu.borrow().get_p();
-        p_register.clear_bit(Flag::B as usize);
-        cpu.borrow_mut().push_stack(p_register);
-        yield;
-        let interrupt_low: u8 = cpu.borrow().get_mem(IRQ_VECTOR);
-        cpu.borrow_mut().set_pcl(interrupt_low);
-        cpu.borrow_mut().assign_flag(Flag::I, true);
-        yield;
-        let interrupt_high: u8 = cpu.borrow().get_mem(IRQ_VECTOR + 1);
-        cpu.borrow_mut().set_pch(interrupt_high);
-        yield;
+/// Runs a cycle of an IRQ interrupt
+pub fn irq<S: Memory + Stack + Registers + Interrupt>(
+    cpu: &mut S,
+    sequence: &mut InterruptSequence,
+) -> CycleState<(), ()> {
+    let cycle: u8 = sequence.cycle;
+    sequence.cycle += 1;
+    match cycle {
+        0..=4 => push_state(cpu, cycle),
+        5 => {
+            sequence.vector = hijack_vector(cpu, IRQ_VECTOR);
+            fetch_vector_low(cpu, sequence.vector);
+        }
+        6 => fetch_vector_high(cpu, sequence.vector),
+        _ => return CycleState::Complete(()),
+    }
+    CycleState::Yielded(())
+}
+
+/// The first five cycles of NMI and IRQ, which push the PC and P registers
+fn push_state<S: Memory + Stack + Registers>(cpu: &mut S, cycle: u8) {
+    match cycle {
+        0 | 1 => {
+            let pc: u16 = cpu.get_pc();
+            cpu.dummy_read(pc);
+        }
+        2 => {
+            let pc_high: u8 = cpu.get_pch();
+            cpu.push_stack(pc_high);
+        }
+        3 => {
+            let pc_low: u8 = cpu.get_pcl();
+            cpu.push_stack(pc_low);
+        }
+        _ => {
+            let mut p_register: u8 = cpu.get_p();
+            p_register.clear_bit(Flag::B as usize);
+            cpu.push_stack(p_register);
+        }
+    }
+}
+
+fn fetch_vector_low<S: Memory + Registers>(cpu: &mut S, vector: u16) {
+    let interrupt_low: u8 = cpu.get_mem(vector);
+    cpu.set_pcl(interrupt_low);
+    cpu.assign_flag(Flag::I, true);
+}
+
+fn fetch_vector_high<S: Memory + Registers>(cpu: &mut S, vector: u16) {
+    let interrupt_high: u8 = cpu.get_mem(vector + 1);
+    cpu.set_pch(interrupt_high);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ppu::VBlank;
+    use crate::state::NES;
+    use crate::state_machine::CycleState;
+
+    #[test]
+    fn test_reset() {
+        let mut cpu = NES::mock();
+        cpu.set_s(0xFD);
+        cpu.set_mem(0x01FD, 0x12);
+        cpu.assign_flag(Flag::I, false);
+        let mut sequence = InterruptSequence::default();
+        let mut cycles = 0;
+        while let CycleState::Yielded(_) = reset(&mut cpu, &mut sequence) {
+            cycles += 1;
+        }
+        assert_eq!(cycles, 7);
+        assert_eq!(cpu.get_s(), 0xFA);
+        assert_eq!(cpu.get_mem(0x01FD), 0x12);
+        assert_eq!(cpu.is_flag_set(Flag::I), true);
+    }
+
+    #[test]
+    fn test_irq_hijack() {
+        let mut cpu = NES::mock();
+        cpu.set_mem(0x2000, 0b1000_0000);
+        let mut sequence = InterruptSequence::default();
+        for _ in 0..3 {
+            let _ = irq(&mut cpu, &mut sequence);
+            cpu.detect_nmi_edge();
+        }
+        cpu.start_vblank();
+        cpu.detect_nmi_edge();
+        cpu.detect_nmi_edge();
+        assert_eq!(cpu.poll_interrupts(), InterruptState::NMI);
+        while let CycleState::Yielded(_) = irq(&mut cpu, &mut sequence) {}
+        // the NMI was serviced by the IRQ sequence
+        assert_eq!(cpu.poll_interrupts(), InterruptState::None);
     }
 }
@@ -1,26 +1,26 @@
-mod instructions;
-mod interrupt;
-mod oamdma;
-mod opcode_generators;
+pub mod dmcdma;
+pub mod instructions;
+pub mod interrupt;
+pub mod oamdma;
+pub mod opcode_generators;
 pub mod variables;
 
 use crate::state::cpu::InterruptState;
-use crate::state::cpu::{Memory, Registers, Stack};
+use crate::state::cpu::{Memory, Registers};
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 use crate::state::CPU;
+use crate::state_machine::CycleState;
+use dmcdma::{dmcdma, DMCDMAState};
 use instructions::{
     adc::ADC, and::AND, asl::ASL, bcf::BC, bit::BIT, bsf::BS, clf::CL, cpr::CP, dec::DEC, der::DE,
     eor::EOR, inc::INC, inr::IN, ldr::LD, lsr::LSR, nop::NOP, ora::ORA, phr::PH, plr::PL, rol::ROL,
-    ror::ROR, sbc::SBC, sef::SE, str::ST, trr::T,
+    ror::ROR, sbc::SBC, sef::SE, str::ST, trr::T, InstructionName,
 };
-use oamdma::oamdma;
+use interrupt::InterruptSequence;
+use oamdma::{oamdma, OAMDMAState};
 use opcode_generators::{
     absolute, absolute_x, absolute_y, immediate, implied, indirect, indirect_x, indirect_y,
-    relative, zero, zero_x, zero_y, CPUCycle,
-};
-use std::{
-    cell::RefCell,
-    ops::{Generator, GeneratorState},
-    pin::Pin,
+    relative, zero, zero_x, zero_y, AddressingMode, CPUCycle, OpcodeState,
 };
 use variables::{
     a_register::A, p_register::P, stack_pointer::S, x_register::X, y_register::Y, Flag,
@@ -47,287 +47,511 @@ use variables::{
 const RESET_VECTOR: u16 = 0xFFFC;
 
 /// Executes a CPU cycle
-pub fn cycle<'a, S: CPU>(
-    cpu: &'a RefCell<S>,
-) -> impl Generator<Yield = InstructionState, Return = ()> + 'a {
-    move || loop {
-        let mut instruction_generator = get_instruction(cpu);
-        let mut pending_interrupt = InterruptState::None;
-        'opcode: loop {
-            let cycle_state = instruction_generator.as_mut().resume(());
-            if cpu.borrow().is_oam_dma_triggered() {
-                let mut oamdma_generator = oamdma(cpu);
-                while let GeneratorState::Yielded(_) = Pin::new(&mut oamdma_generator).resume(()) {
-                    yield InstructionState::OAMDMA;
-                    cpu.borrow_mut().toggle_odd_even();
+pub fn cycle<S: CPU>(cpu: &mut S) -> InstructionState {
+    let mut execution: ExecutionState = cpu.get_execution_state();
+    let instruction_state: InstructionState = loop {
+        match execution {
+            ExecutionState::Fetch => {
+                // service the interrupt that was polled during the previous instruction
+                let pending_interrupt: InterruptState = cpu.get_pending_interrupt();
+                cpu.set_pending_interrupt(InterruptState::None);
+                match pending_interrupt {
+                    InterruptState::Reset => cpu.clear_reset(),
+                    InterruptState::NMI => cpu.clear_nmi(),
+                    InterruptState::IRQ => {}
+                    InterruptState::None => {
+                        execution =
+                            ExecutionState::Opcode(OpcodeState::new(cpu.get_and_increment_pc()));
+                        continue;
+                    }
                 }
-                cpu.borrow_mut().untrigger_oam_dma();
+                execution =
+                    ExecutionState::Interrupt(pending_interrupt, InterruptSequence::default());
             }
-            match cycle_state {
-                GeneratorState::Yielded(x) => {
-                    yield InstructionState::Yielded(x);
-                    cpu.borrow_mut().toggle_odd_even();
-                    // we check the second-last cyle of each instruction for an interrupt
-                    pending_interrupt = cpu.borrow().get_pending_interrupt();
+            ExecutionState::Interrupt(interrupt, mut sequence) => {
+                if cpu.is_dmc_dma_pending() {
+                    let mut halted: InterruptSequence = sequence;
+                    let halt_addr: Option<u16> = halt_cycle(cpu, |cpu| {
+                        execute_interrupt(cpu, interrupt, &mut halted);
+                    });
+                    if let Some(addr) = halt_addr {
+                        let dma = DMCDMAState::new(addr);
+                        execution = ExecutionState::InterruptDMCDMA(interrupt, sequence, dma);
+                        break InstructionState::DMCDMA;
+                    }
                 }
-                GeneratorState::Complete(x) => {
-                    yield InstructionState::Complete(x);
-                    cpu.borrow_mut().toggle_odd_even();
-                    break 'opcode;
+                if let CycleState::Yielded(()) = execute_interrupt(cpu, interrupt, &mut sequence) {
+                    execution = ExecutionState::Interrupt(interrupt, sequence);
+                    break match interrupt {
+                        InterruptState::Reset => InstructionState::Reset,
+                        InterruptState::NMI => InstructionState::NMI,
+                        _ => InstructionState::IRQ,
+                    };
                 }
+                execution = ExecutionState::Opcode(OpcodeState::new(cpu.get_and_increment_pc()));
             }
-        }
-        if pending_interrupt == InterruptState::NMI {
-            cpu.borrow_mut().clear_interrupt();
-            let mut nmi_generator = interrupt::nmi(cpu);
-            while let GeneratorState::Yielded(_) = Pin::new(&mut nmi_generator).resume(()) {
-                yield InstructionState::NMI;
-                cpu.borrow_mut().toggle_odd_even();
+            ExecutionState::Opcode(opcode) => {
+                if opcode.cycle > 0 {
+                    // we check the second-last cyle of each instruction for an interrupt, so
+                    // changes to the I flag made by CLI, SEI and PLP only take effect after the
+                    // next instruction
+                    let polled_interrupt: InterruptState = cpu.poll_interrupts();
+                    cpu.set_pending_interrupt(polled_interrupt);
+                }
+                if cpu.is_dmc_dma_pending() {
+                    let mut halted: OpcodeState = opcode;
+                    let halt_addr: Option<u16> = halt_cycle(cpu, |cpu| {
+                        execute_opcode(cpu, &mut halted);
+                    });
+                    if let Some(addr) = halt_addr {
+                        execution = ExecutionState::DMCDMA(opcode, DMCDMAState::new(addr));
+                        break InstructionState::DMCDMA;
+                    }
+                }
+                let (next, instruction_state) = execute_cycle(cpu, opcode);
+                execution = next;
+                if let Some(instruction_state) = instruction_state {
+                    break instruction_state;
+                }
             }
-        } else if pending_interrupt == InterruptState::IRQ {
-            cpu.borrow_mut().clear_interrupt();
-            let mut irq_generator = interrupt::irq(cpu);
-            while let GeneratorState::Yielded(_) = Pin::new(&mut irq_generator).resume(()) {
-                yield InstructionState::IRQ;
-                cpu.borrow_mut().toggle_odd_even();
+            ExecutionState::DMCDMA(opcode, mut dma) => {
+                if let CycleState::Yielded(()) = dmcdma(cpu, &mut dma) {
+                    execution = ExecutionState::DMCDMA(opcode, dma);
+                    break InstructionState::DMCDMA;
+                }
+                let (next, instruction_state) = execute_cycle(cpu, opcode);
+                execution = next;
+                if let Some(instruction_state) = instruction_state {
+                    break instruction_state;
+                }
+            }
+            ExecutionState::InterruptDMCDMA(interrupt, sequence, mut dma) => {
+                if let CycleState::Yielded(()) = dmcdma(cpu, &mut dma) {
+                    execution = ExecutionState::InterruptDMCDMA(interrupt, sequence, dma);
+                    break InstructionState::DMCDMA;
+                }
+                execution = ExecutionState::Interrupt(interrupt, sequence);
+            }
+            ExecutionState::OAMDMA(opcode, mut dma, cycle_state) => {
+                if let CycleState::Yielded(()) = oamdma(cpu, &mut dma) {
+                    execution = ExecutionState::OAMDMA(opcode, dma, cycle_state);
+                    break InstructionState::OAMDMA;
+                }
+                cpu.untrigger_oam_dma();
+                let (next, instruction_state) = finish_cycle(opcode, cycle_state);
+                execution = next;
+                break instruction_state;
             }
         }
+    };
+    cpu.set_execution_state(execution);
+    // the NMI line is sampled at the end of every cycle
+    cpu.detect_nmi_edge();
+    cpu.advance_cycle();
+    instruction_state
+}
+
+/// Runs the opcode's next cycle. If the cycle triggers an OAM DMA, it isn't reported until the DMA
+/// is over.
+fn execute_cycle<S: CPU>(
+    cpu: &mut S,
+    mut opcode: OpcodeState,
+) -> (ExecutionState, Option<InstructionState>) {
+    let cycle_state: CycleState<CPUCycle, CPUCycle> = execute_opcode(cpu, &mut opcode);
+    if cpu.is_oam_dma_triggered() {
+        return (
+            ExecutionState::OAMDMA(opcode, OAMDMAState::default(), cycle_state),
+            None,
+        );
+    }
+    let (execution, instruction_state) = finish_cycle(opcode, cycle_state);
+    (execution, Some(instruction_state))
+}
+
+/// Tries to halt the CPU on the cycle that `run_cycle` runs. The CPU can only be halted on a read:
+/// the read still happens, but the rest of the cycle is undone, so that the cycle runs again once
+/// the DMA is over. Returns the address of the read, or `None` if the CPU couldn't be halted.
+fn halt_cycle<S: CPU, F: FnOnce(&mut S)>(cpu: &mut S, run_cycle: F) -> Option<u16> {
+    cpu.start_halt();
+    run_cycle(cpu);
+    cpu.finish_halt()
+}
+
+/// Runs the next cycle of an interrupt sequence
+fn execute_interrupt<S: CPU>(
+    cpu: &mut S,
+    interrupt: InterruptState,
+    sequence: &mut InterruptSequence,
+) -> CycleState<(), ()> {
+    match interrupt {
+        InterruptState::Reset => interrupt::reset(cpu, sequence),
+        InterruptState::NMI => interrupt::nmi(cpu, sequence),
+        _ => interrupt::irq(cpu, sequence),
+    }
+}
+
+fn finish_cycle(
+    opcode: OpcodeState,
+    cycle_state: CycleState<CPUCycle, CPUCycle>,
+) -> (ExecutionState, InstructionState) {
+    match cycle_state {
+        CycleState::Yielded(x) => (ExecutionState::Opcode(opcode), InstructionState::Yielded(x)),
+        // nothing else happens until the next call, so this is a clean boundary between
+        // instructions
+        CycleState::Complete(x) => (ExecutionState::Fetch, InstructionState::Complete(x)),
     }
 }
 
-/// Returns the next instruction
-fn get_instruction<'a, S: CPU>(
-    cpu: &'a RefCell<S>,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    let opcode: u8 = cpu.borrow_mut().get_and_increment_pc();
-    match opcode {
+/// Runs the next cycle of the opcode being executed
+fn execute_opcode<S: CPU>(cpu: &mut S, state: &mut OpcodeState) -> CycleState<CPUCycle, CPUCycle> {
+    match state.opcode {
         // ADC
-        0x69 => immediate::read(cpu, ADC),
-        0x65 => zero::read(cpu, ADC),
-        0x75 => zero_x::read(cpu, ADC),
-        0x6D => absolute::read(cpu, ADC),
-        0x7D => absolute_x::read(cpu, ADC),
-        0x79 => absolute_y::read(cpu, ADC),
-        0x61 => indirect_x::read(cpu, ADC),
-        0x71 => indirect_y::read(cpu, ADC),
+        0x69 => immediate::read(cpu, ADC, state),
+        0x65 => zero::read(cpu, ADC, state),
+        0x75 => zero_x::read(cpu, ADC, state),
+        0x6D => absolute::read(cpu, ADC, state),
+        0x7D => absolute_x::read(cpu, ADC, state),
+        0x79 => absolute_y::read(cpu, ADC, state),
+        0x61 => indirect_x::read(cpu, ADC, state),
+        0x71 => indirect_y::read(cpu, ADC, state),
         // AND
-        0x29 => immediate::read(cpu, AND),
-        0x25 => zero::read(cpu, AND),
-        0x35 => zero_x::read(cpu, AND),
-        0x2D => absolute::read(cpu, AND),
-        0x3D => absolute_x::read(cpu, AND),
-        0x39 => absolute_y::read(cpu, AND),
-        0x21 => indirect_x::read(cpu, AND),
-        0x31 => indirect_y::read(cpu, AND),
+        0x29 => immediate::read(cpu, AND, state),
+        0x25 => zero::read(cpu, AND, state),
+        0x35 => zero_x::read(cpu, AND, state),
+        0x2D => absolute::read(cpu, AND, state),
+        0x3D => absolute_x::read(cpu, AND, state),
+        0x39 => absolute_y::read(cpu, AND, state),
+        0x21 => indirect_x::read(cpu, AND, state),
+        0x31 => indirect_y::read(cpu, AND, state),
         // ASL
-        0x0A => implied::implied(cpu, ASL),
-        0x06 => zero::modify(cpu, ASL),
-        0x16 => zero_x::modify(cpu, ASL),
-        0x0E => absolute::modify(cpu, ASL),
-        0x1E => absolute_x::modify(cpu, ASL),
+        0x0A => implied::implied(cpu, ASL, state),
+        0x06 => zero::modify(cpu, ASL, state),
+        0x16 => zero_x::modify(cpu, ASL, state),
+        0x0E => absolute::modify(cpu, ASL, state),
+        0x1E => absolute_x::modify(cpu, ASL, state),
         // BCC
-        0x90 => relative::relative(cpu, BC(Flag::C)),
+        0x90 => relative::relative(cpu, BC(Flag::C), state),
         // BCS
-        0xB0 => relative::relative(cpu, BS(Flag::C)),
+        0xB0 => relative::relative(cpu, BS(Flag::C), state),
         // BEQ
-        0xF0 => relative::relative(cpu, BS(Flag::Z)),
+        0xF0 => relative::relative(cpu, BS(Flag::Z), state),
         // BIT
-        0x24 => zero::read(cpu, BIT),
-        0x2C => absolute::read(cpu, BIT),
+        0x24 => zero::read(cpu, BIT, state),
+        0x2C => absolute::read(cpu, BIT, state),
         // BMI
-        0x30 => relative::relative(cpu, BS(Flag::N)),
+        0x30 => relative::relative(cpu, BS(Flag::N), state),
         // BNE
-        0xD0 => relative::relative(cpu, BC(Flag::Z)),
+        0xD0 => relative::relative(cpu, BC(Flag::Z), state),
         // BPL
-        0x10 => relative::relative(cpu, BC(Flag::N)),
+        0x10 => relative::relative(cpu, BC(Flag::N), state),
         // BRK
-        0x00 => implied::brk(cpu),
+        0x00 => implied::brk(cpu, state),
         // BVC
-        0x50 => relative::relative(cpu, BC(Flag::V)),
+        0x50 => relative::relative(cpu, BC(Flag::V), state),
         // BVS
-        0x70 => relative::relative(cpu, BS(Flag::V)),
+        0x70 => relative::relative(cpu, BS(Flag::V), state),
         // CLC
-        0x18 => implied::implied(cpu, CL(Flag::C)),
+        0x18 => implied::implied(cpu, CL(Flag::C), state),
         // CLD
-        0xD8 => implied::implied(cpu, CL(Flag::D)),
+        0xD8 => implied::implied(cpu, CL(Flag::D), state),
         // CLI
-        0x58 => implied::implied(cpu, CL(Flag::I)),
+        0x58 => implied::implied(cpu, CL(Flag::I), state),
         // CLV
-        0xB8 => implied::implied(cpu, CL(Flag::V)),
+        0xB8 => implied::implied(cpu, CL(Flag::V), state),
         // CMP
-        0xC9 => immediate::read(cpu, CP(A)),
-        0xC5 => zero::read(cpu, CP(A)),
-        0xD5 => zero_x::read(cpu, CP(A)),
-        0xCD => absolute::read(cpu, CP(A)),
-        0xDD => absolute_x::read(cpu, CP(A)),
-        0xD9 => absolute_y::read(cpu, CP(A)),
-        0xC1 => indirect_x::read(cpu, CP(A)),
-        0xD1 => indirect_y::read(cpu, CP(A)),
+        0xC9 => immediate::read(cpu, CP(A), state),
+        0xC5 => zero::read(cpu, CP(A), state),
+        0xD5 => zero_x::read(cpu, CP(A), state),
+        0xCD => absolute::read(cpu, CP(A), state),
+        0xDD => absolute_x::read(cpu, CP(A), state),
+        0xD9 => absolute_y::read(cpu, CP(A), state),
+        0xC1 => indirect_x::read(cpu, CP(A), state),
+        0xD1 => indirect_y::read(cpu, CP(A), state),
         // CPX
-        0xE0 => immediate::read(cpu, CP(X)),
-        0xE4 => zero::read(cpu, CP(X)),
-        0xEC => absolute::read(cpu, CP(X)),
+        0xE0 => immediate::read(cpu, CP(X), state),
+        0xE4 => zero::read(cpu, CP(X), state),
+        0xEC => absolute::read(cpu, CP(X), state),
         // CPY
-        0xC0 => immediate::read(cpu, CP(Y)),
-        0xC4 => zero::read(cpu, CP(Y)),
-        0xCC => absolute::read(cpu, CP(Y)),
+        0xC0 => immediate::read(cpu, CP(Y), state),
+        0xC4 => zero::read(cpu, CP(Y), state),
+        0xCC => absolute::read(cpu, CP(Y), state),
         // DEC
-        0xC6 => zero::modify(cpu, DEC),
-        0xD6 => zero_x::modify(cpu, DEC),
-        0xCE => absolute::modify(cpu, DEC),
-        0xDE => absolute_x::modify(cpu, DEC),
+        0xC6 => zero::modify(cpu, DEC, state),
+        0xD6 => zero_x::modify(cpu, DEC, state),
+        0xCE => absolute::modify(cpu, DEC, state),
+        0xDE => absolute_x::modify(cpu, DEC, state),
         // DEX
-        0xCA => implied::implied(cpu, DE(X)),
+        0xCA => implied::implied(cpu, DE(X), state),
         // DEY
-        0x88 => implied::implied(cpu, DE(Y)),
+        0x88 => implied::implied(cpu, DE(Y), state),
         // EOR
-        0x49 => immediate::read(cpu, EOR),
-        0x45 => zero::read(cpu, EOR),
-        0x55 => zero_x::read(cpu, EOR),
-        0x4D => absolute::read(cpu, EOR),
-        0x5D => absolute_x::read(cpu, EOR),
-        0x59 => absolute_y::read(cpu, EOR),
-        0x41 => indirect_x::read(cpu, EOR),
-        0x51 => indirect_y::read(cpu, EOR),
+        0x49 => immediate::read(cpu, EOR, state),
+        0x45 => zero::read(cpu, EOR, state),
+        0x55 => zero_x::read(cpu, EOR, state),
+        0x4D => absolute::read(cpu, EOR, state),
+        0x5D => absolute_x::read(cpu, EOR, state),
+        0x59 => absolute_y::read(cpu, EOR, state),
+        0x41 => indirect_x::read(cpu, EOR, state),
+        0x51 => indirect_y::read(cpu, EOR, state),
         // INC
-        0xE6 => zero::modify(cpu, INC),
-        0xF6 => zero_x::modify(cpu, INC),
-        0xEE => absolute::modify(cpu, INC),
-        0xFE => absolute_x::modify(cpu, INC),
+        0xE6 => zero::modify(cpu, INC, state),
+        0xF6 => zero_x::modify(cpu, INC, state),
+        0xEE => absolute::modify(cpu, INC, state),
+        0xFE => absolute_x::modify(cpu, INC, state),
         // INX
-        0xE8 => implied::implied(cpu, IN(X)),
+        0xE8 => implied::implied(cpu, IN(X), state),
         // INY
-        0xC8 => implied::implied(cpu, IN(Y)),
+        0xC8 => implied::implied(cpu, IN(Y), state),
         // JMP
-        0x4C => absolute::jmp(cpu),
-        0x6C => indirect::jmp(cpu),
+        0x4C => absolute::jmp(cpu, state),
+        0x6C => indirect::jmp(cpu, state),
         // JSR
-        0x20 => absolute::jsr(cpu),
+        0x20 => absolute::jsr(cpu, state),
         // LDA
-        0xA9 => immediate::read(cpu, LD(A)),
-        0xA5 => zero::read(cpu, LD(A)),
-        0xB5 => zero_x::read(cpu, LD(A)),
-        0xAD => absolute::read(cpu, LD(A)),
-        0xBD => absolute_x::read(cpu, LD(A)),
-        0xB9 => absolute_y::read(cpu, LD(A)),
-        0xA1 => indirect_x::read(cpu, LD(A)),
-        0xB1 => indirect_y::read(cpu, LD(A)),
+        0xA9 => immediate::read(cpu, LD(A), state),
+        0xA5 => zero::read(cpu, LD(A), state),
+        0xB5 => zero_x::read(cpu, LD(A), state),
+        0xAD => absolute::read(cpu, LD(A), state),
+        0xBD => absolute_x::read(cpu, LD(A), state),
+        0xB9 => absolute_y::read(cpu, LD(A), state),
+        0xA1 => indirect_x::read(cpu, LD(A), state),
+        0xB1 => indirect_y::read(cpu, LD(A), state),
         // LDX
-        0xA2 => immediate::read(cpu, LD(X)),
-        0xA6 => zero::read(cpu, LD(X)),
-        0xB6 => zero_y::read(cpu, LD(X)),
-        0xAE => absolute::read(cpu, LD(X)),
-        0xBE => absolute_y::read(cpu, LD(X)),
+        0xA2 => immediate::read(cpu, LD(X), state),
+        0xA6 => zero::read(cpu, LD(X), state),
+        0xB6 => zero_y::read(cpu, LD(X), state),
+        0xAE => absolute::read(cpu, LD(X), state),
+        0xBE => absolute_y::read(cpu, LD(X), state),
         // LDY
-        0xA0 => immediate::read(cpu, LD(Y)),
-        0xA4 => zero::read(cpu, LD(Y)),
-        0xB4 => zero_x::read(cpu, LD(Y)),
-        0xAC => absolute::read(cpu, LD(Y)),
-        0xBC => absolute_x::read(cpu, LD(Y)),
+        0xA0 => immediate::read(cpu, LD(Y), state),
+        0xA4 => zero::read(cpu, LD(Y), state),
+        0xB4 => zero_x::read(cpu, LD(Y), state),
+        0xAC => absolute::read(cpu, LD(Y), state),
+        0xBC => absolute_x::read(cpu, LD(Y), state),
         // LSR
-        0x4A => implied::implied(cpu, LSR),
-        0x46 => zero::modify(cpu, LSR),
-        0x56 => zero_x::modify(cpu, LSR),
-        0x4E => absolute::modify(cpu, LSR),
-        0x5E => absolute_x::modify(cpu, LSR),
+        0x4A => implied::implied(cpu, LSR, state),
+        0x46 => zero::modify(cpu, LSR, state),
+        0x56 => zero_x::modify(cpu, LSR, state),
+        0x4E => absolute::modify(cpu, LSR, state),
+        0x5E => absolute_x::modify(cpu, LSR, state),
         // NOP
-        0xEA => implied::implied(cpu, NOP),
+        0xEA => implied::implied(cpu, NOP, state),
         // ORA
-        0x09 => immediate::read(cpu, ORA),
-        0x05 => zero::read(cpu, ORA),
-        0x15 => zero_x::read(cpu, ORA),
-        0x0D => absolute::read(cpu, ORA),
-        0x1D => absolute_x::read(cpu, ORA),
-        0x19 => absolute_y::read(cpu, ORA),
-        0x01 => indirect_x::read(cpu, ORA),
-        0x11 => indirect_y::read(cpu, ORA),
+        0x09 => immediate::read(cpu, ORA, state),
+        0x05 => zero::read(cpu, ORA, state),
+        0x15 => zero_x::read(cpu, ORA, state),
+        0x0D => absolute::read(cpu, ORA, state),
+        0x1D => absolute_x::read(cpu, ORA, state),
+        0x19 => absolute_y::read(cpu, ORA, state),
+        0x01 => indirect_x::read(cpu, ORA, state),
+        0x11 => indirect_y::read(cpu, ORA, state),
         // PHA
-        0x48 => implied::push_stack(cpu, PH(A)),
+        0x48 => implied::push_stack(cpu, PH(A), state),
         // PHP
-        0x08 => implied::push_stack(cpu, PH(P)),
+        0x08 => implied::push_stack(cpu, PH(P), state),
         // PLA
-        0x68 => implied::pull_stack(cpu, PL(A)),
+        0x68 => implied::pull_stack(cpu, PL(A), state),
         // PLP
-        0x28 => implied::pull_stack(cpu, PL(P)),
+        0x28 => implied::pull_stack(cpu, PL(P), state),
         // ROL
-        0x2A => implied::implied(cpu, ROL),
-        0x26 => zero::modify(cpu, ROL),
-        0x36 => zero_x::modify(cpu, ROL),
-        0x2E => absolute::modify(cpu, ROL),
-        0x3E => absolute_x::modify(cpu, ROL),
+        0x2A => implied::implied(cpu, ROL, state),
+        0x26 => zero::modify(cpu, ROL, state),
+        0x36 => zero_x::modify(cpu, ROL, state),
+        0x2E => absolute::modify(cpu, ROL, state),
+        0x3E => absolute_x::modify(cpu, ROL, state),
         // ROR
-        0x6A => implied::implied(cpu, ROR),
-        0x66 => zero::modify(cpu, ROR),
-        0x76 => zero_x::modify(cpu, ROR),
-        0x6E => absolute::modify(cpu, ROR),
-        0x7E => absolute_x::modify(cpu, ROR),
+        0x6A => implied::implied(cpu, ROR, state),
+        0x66 => zero::modify(cpu, ROR, state),
+        0x76 => zero_x::modify(cpu, ROR, state),
+        0x6E => absolute::modify(cpu, ROR, state),
+        0x7E => absolute_x::modify(cpu, ROR, state),
         // RTI
-        0x40 => implied::rti(cpu),
+        0x40 => implied::rti(cpu, state),
         // RTS
-        0x60 => implied::rts(cpu),
+        0x60 => implied::rts(cpu, state),
         // SBC
-        0xE9 => immediate::read(cpu, SBC),
-        0xE5 => zero::read(cpu, SBC),
-        0xF5 => zero_x::read(cpu, SBC),
-        0xED => absolute::read(cpu, SBC),
-        0xFD => absolute_x::read(cpu, SBC),
-        0xF9 => absolute_y::read(cpu, SBC),
-        0xE1 => indirect_x::read(cpu, SBC),
-        0xF1 => indirect_y::read(cpu, SBC),
+        0xE9 => immediate::read(cpu, SBC, state),
+        0xE5 => zero::read(cpu, SBC, state),
+        0xF5 => zero_x::read(cpu, SBC, state),
+        0xED => absolute::read(cpu, SBC, state),
+        0xFD => absolute_x::read(cpu, SBC, state),
+        0xF9 => absolute_y::read(cpu, SBC, state),
+        0xE1 => indirect_x::read(cpu, SBC, state),
+        0xF1 => indirect_y::read(cpu, SBC, state),
         // SEC
-        0x38 => implied::implied(cpu, SE(Flag::C)),
+        0x38 => implied::implied(cpu, SE(Flag::C), state),
         // SED
-        0xF8 => implied::implied(cpu, SE(Flag::D)),
+        0xF8 => implied::implied(cpu, SE(Flag::D), state),
         // SEI
-        0x78 => implied::implied(cpu, SE(Flag::I)),
+        0x78 => implied::implied(cpu, SE(Flag::I), state),
         // STA
-        0x85 => zero::write(cpu, ST(A)),
-        0x95 => zero_x::write(cpu, ST(A)),
-        0x8D => absolute::write(cpu, ST(A)),
-        0x9D => absolute_x::write(cpu, ST(A)),
-        0x99 => absolute_y::write(cpu, ST(A)),
-        0x81 => indirect_x::write(cpu, ST(A)),
-        0x91 => indirect_y::write(cpu, ST(A)),
+        0x85 => zero::write(cpu, ST(A), state),
+        0x95 => zero_x::write(cpu, ST(A), state),
+        0x8D => absolute::write(cpu, ST(A), state),
+        0x9D => absolute_x::write(cpu, ST(A), state),
+        0x99 => absolute_y::write(cpu, ST(A), state),
+        0x81 => indirect_x::write(cpu, ST(A), state),
+        0x91 => indirect_y::write(cpu, ST(A), state),
         // STX
-        0x86 => zero::write(cpu, ST(X)),
-        0x96 => zero_y::write(cpu, ST(X)),
-        0x8E => absolute::write(cpu, ST(X)),
+        0x86 => zero::write(cpu, ST(X), state),
+        0x96 => zero_y::write(cpu, ST(X), state),
+        0x8E => absolute::write(cpu, ST(X), state),
         // STY
-        0x84 => zero::write(cpu, ST(Y)),
-        0x94 => zero_x::write(cpu, ST(Y)),
-        0x8C => absolute::write(cpu, ST(Y)),
+        0x84 => zero::write(cpu, ST(Y), state),
+        0x94 => zero_x::write(cpu, ST(Y), state),
+        0x8C => absolute::write(cpu, ST(Y), state),
         // TAX
-        0xAA => implied::implied(cpu, T(A, X)),
+        0xAA => implied::implied(cpu, T(A, X), state),
         // TAY
-        0xA8 => implied::implied(cpu, T(A, Y)),
+        0xA8 => implied::implied(cpu, T(A, Y), state),
         // TSX
-        0xBA => implied::implied(cpu, T(S, X)),
+        0xBA => implied::implied(cpu, T(S, X), state),
         // TXA
-        0x8A => implied::implied(cpu, T(X, A)),
+        0x8A => implied::implied(cpu, T(X, A), state),
         // TXS
-        0x9A => implied::implied(cpu, T(X, S)),
+        0x9A => implied::implied(cpu, T(X, S), state),
         // TYA
-        0x98 => implied::implied(cpu, T(Y, A)),
+        0x98 => implied::implied(cpu, T(Y, A), state),
         // we treat unofficial opcodes (and unimplemented ones) as being NOP
         // it is not strictly correct, but it will have to do for now
-        _ => implied::implied(cpu, NOP),
+        _ => implied::implied(cpu, NOP, state),
     }
 }
 
 /// Jumps to the place pointed at by the reset vector
-pub fn reset<'a, S: Memory + Stack + Registers>(cpu: &'a RefCell<S>) {
-    let interrupt_low: u8 = cpu.borrow().get_mem(RESET_VECTOR);
-    cpu.borrow_mut().set_pcl(interrupt_low);
-    let interrupt_high: u8 = cpu.borrow().get_mem(RESET_VECTOR + 1);
-    cpu.borrow_mut().set_pch(interrupt_high);
+/// This is only meant for booting; a running console should be reset with `NES::reset`.
+pub fn reset<S: Memory + Registers>(cpu: &mut S) {
+    let interrupt_low: u8 = cpu.get_mem(RESET_VECTOR);
+    cpu.set_pcl(interrupt_low);
+    let interrupt_high: u8 = cpu.get_mem(RESET_VECTOR + 1);
+    cpu.set_pch(interrupt_high);
 }
 
 /// Represents the state of an instruction
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum InstructionState {
     OAMDMA,
+    DMCDMA,
+    Reset,
     NMI,
     IRQ,
     Yielded(CPUCycle),
     Complete(CPUCycle),
 }
+
+/// What the CPU is in the middle of. It is kept in the NES between cycles, so that the CPU can be
+/// inspected and saved at any point.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ExecutionState {
+    /// Between instructions. The next cycle services the pending interrupt, or fetches an opcode.
+    #[default]
+    Fetch,
+    Interrupt(InterruptState, InterruptSequence),
+    Opcode(OpcodeState),
+    /// The DMC has halted the CPU on the opcode's next cycle, which runs once the DMA is over
+    DMCDMA(OpcodeState, DMCDMAState),
+    /// The DMC has halted the CPU on the interrupt sequence's next cycle
+    InterruptDMCDMA(InterruptState, InterruptSequence, DMCDMAState),
+    /// The opcode's last cycle triggered an OAM DMA. The cycle is reported once the DMA is over.
+    OAMDMA(OpcodeState, OAMDMAState, CycleState<CPUCycle, CPUCycle>),
+}
+
+impl Snapshot for ExecutionState {
+    fn save(&self, writer: &mut StateWriter) {
+        match self {
+            ExecutionState::Fetch => writer.write_u8(0),
+            ExecutionState::Interrupt(interrupt, sequence) => {
+                writer.write_u8(1);
+                interrupt.save(writer);
+                sequence.save(writer);
+            }
+            ExecutionState::Opcode(opcode) => {
+                writer.write_u8(2);
+                opcode.save(writer);
+            }
+            ExecutionState::DMCDMA(opcode, dma) => {
+                writer.write_u8(3);
+                opcode.save(writer);
+                dma.save(writer);
+            }
+            ExecutionState::OAMDMA(opcode, dma, cycle_state) => {
+                writer.write_u8(4);
+                opcode.save(writer);
+                dma.save(writer);
+                let (complete, cycle): (bool, &CPUCycle) = match cycle_state {
+                    CycleState::Yielded(cycle) => (false, cycle),
+                    CycleState::Complete(cycle) => (true, cycle),
+                };
+                writer.write_bool(complete);
+                cycle.save(writer);
+            }
+            ExecutionState::InterruptDMCDMA(interrupt, sequence, dma) => {
+                writer.write_u8(5);
+                interrupt.save(writer);
+                sequence.save(writer);
+                dma.save(writer);
+            }
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let mut opcode = OpcodeState::default();
+        *self = match reader.read_u8()? {
+            0 => ExecutionState::Fetch,
+            1 => {
+                let (interrupt, sequence) = load_interrupt(reader)?;
+                ExecutionState::Interrupt(interrupt, sequence)
+            }
+            2 => {
+                opcode.load(reader)?;
+                ExecutionState::Opcode(opcode)
+            }
+            3 => {
+                opcode.load(reader)?;
+                let mut dma = DMCDMAState::new(0);
+                dma.load(reader)?;
+                ExecutionState::DMCDMA(opcode, dma)
+            }
+            4 => {
+                opcode.load(reader)?;
+                let mut dma = OAMDMAState::default();
+                dma.load(reader)?;
+                let complete: bool = reader.read_bool()?;
+                let mut cycle = CPUCycle {
+                    instruction: InstructionName::NOP,
+                    mode: AddressingMode::Implied,
+                    cycle: 0,
+                };
+                cycle.load(reader)?;
+                let cycle_state = if complete {
+                    CycleState::Complete(cycle)
+                } else {
+                    CycleState::Yielded(cycle)
+                };
+                ExecutionState::OAMDMA(opcode, dma, cycle_state)
+            }
+            5 => {
+                let (interrupt, sequence) = load_interrupt(reader)?;
+                let mut dma = DMCDMAState::new(0);
+                dma.load(reader)?;
+                ExecutionState::InterruptDMCDMA(interrupt, sequence, dma)
+            }
+            _ => return Err(SaveStateError::InvalidData),
+        };
+        Ok(())
+    }
+}
+
+/// Loads the interrupt being serviced, and how far along its sequence is
+fn load_interrupt(
+    reader: &mut StateReader,
+) -> Result<(InterruptState, InterruptSequence), SaveStateError> {
+    let mut interrupt = InterruptState::None;
+    interrupt.load(reader)?;
+    if interrupt == InterruptState::None {
+        return Err(SaveStateError::InvalidData);
+    }
+    let mut sequence = InterruptSequence::default();
+    sequence.load(reader)?;
+    Ok((interrupt, sequence))
+}
@@ -1,22 +1,104 @@
-use crate::state::cpu::{Memory, OAMDMA};
-use std::cell::RefCell;
-use std::ops::Generator;
+use crate::state::cpu::{Clock, Memory, DMCDMA, OAMDMA};
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::state_machine::CycleState;
 
-pub fn oamdma<'a, S: OAMDMA + Memory>(
-    cpu: &'a RefCell<S>,
-) -> impl Generator<Yield = (), Return = ()> + 'a {
-    move || {
-        yield;
-        if cpu.borrow().is_odd_cycle() {
-            yield;
+/// The cycle an OAM DMA runs next
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum OAMDMAState {
+    #[default]
+    Halt,
+    /// Skipped if the DMA is already on a get cycle
+    Alignment,
+    /// Reads a byte, unless a DMC fetch takes over the cycle
+    Get(u8),
+    /// The cycle after a DMC fetch, needed to realign
+    Realignment(u8),
+    /// Reads a byte after a DMC fetch
+    GetAfterSample(u8),
+    Put {
+        index: u8,
+        byte: u8,
+    },
+    Done,
+}
+
+impl Snapshot for OAMDMAState {
+    fn save(&self, writer: &mut StateWriter) {
+        let (tag, index, byte): (u8, u8, u8) = match *self {
+            OAMDMAState::Halt => (0, 0, 0),
+            OAMDMAState::Alignment => (1, 0, 0),
+            OAMDMAState::Get(index) => (2, index, 0),
+            OAMDMAState::Realignment(index) => (3, index, 0),
+            OAMDMAState::GetAfterSample(index) => (4, index, 0),
+            OAMDMAState::Put { index, byte } => (5, index, byte),
+            OAMDMAState::Done => (6, 0, 0),
+        };
+        writer.write_u8(tag);
+        writer.write_u8(index);
+        writer.write_u8(byte);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let tag: u8 = reader.read_u8()?;
+        let index: u8 = reader.read_u8()?;
+        let byte: u8 = reader.read_u8()?;
+        *self = match tag {
+            0 => OAMDMAState::Halt,
+            1 => OAMDMAState::Alignment,
+            2 => OAMDMAState::Get(index),
+            3 => OAMDMAState::Realignment(index),
+            4 => OAMDMAState::GetAfterSample(index),
+            5 => OAMDMAState::Put { index, byte },
+            6 => OAMDMAState::Done,
+            _ => return Err(SaveStateError::InvalidData),
+        };
+        Ok(())
+    }
+}
+
+/// Runs a cycle of an OAM DMA, which copies a page of CPU memory to OAM
+pub fn oamdma<S: OAMDMA + DMCDMA + Memory + Clock>(
+    cpu: &mut S,
+    state: &mut OAMDMAState,
+) -> CycleState<(), ()> {
+    *state = match *state {
+        OAMDMAState::Halt => OAMDMAState::Alignment,
+        OAMDMAState::Alignment if cpu.is_odd_cycle() => OAMDMAState::Get(0),
+        OAMDMAState::Alignment => get(cpu, 0),
+        OAMDMAState::Get(index) => get(cpu, index),
+        OAMDMAState::Realignment(index) => OAMDMAState::GetAfterSample(index),
+        OAMDMAState::GetAfterSample(index) => OAMDMAState::Put {
+            index,
+            byte: read_byte(cpu, index),
+        },
+        OAMDMAState::Put { index, byte } => {
+            cpu.write_oam(usize::from(index), byte);
+            if index == 0xFF {
+                OAMDMAState::Done
+            } else {
+                OAMDMAState::Get(index + 1)
+            }
         }
-        for i in 0..=0xFF {
-            let byte = cpu
-                .borrow()
-                .get_mem(u16::from_be_bytes([cpu.borrow().get_oam_dma(), i]));
-            yield;
-            cpu.borrow_mut().write_oam(usize::from(i), byte);
-            yield;
+        OAMDMAState::Done => return CycleState::Complete(()),
+    };
+    CycleState::Yielded(())
+}
+
+fn get<S: OAMDMA + DMCDMA + Memory>(cpu: &mut S, index: u8) -> OAMDMAState {
+    if cpu.is_dmc_dma_pending() {
+        // the DMC fetch takes over a get cycle, and another cycle is needed to realign
+        let addr: u16 = cpu.get_dmc_dma_addr();
+        let val: u8 = cpu.get_mem(addr);
+        cpu.load_dmc_sample(val);
+        OAMDMAState::Realignment(index)
+    } else {
+        OAMDMAState::Put {
+            index,
+            byte: read_byte(cpu, index),
         }
     }
 }
+
+fn read_byte<S: OAMDMA + Memory>(cpu: &S, index: u8) -> u8 {
+    cpu.get_mem(u16::from_be_bytes([cpu.get_oam_dma(), index]))
+}
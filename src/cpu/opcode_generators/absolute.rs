@@ -1,150 +1,109 @@
 use crate::{
     cpu::{
         instructions::{InstructionName, Modify, Read, Write},
-        opcode_generators::{AddressingMode, CPUCycle},
+        opcode_generators::{AddressingMode, CPUCycle, OpcodeState},
     },
     state::CPU,
+    state_machine::CycleState,
 };
-use std::{cell::RefCell, ops::Generator, pin::Pin};
 
-/// Creates the opcode for 'Read' instructions with absolute addressing
-pub fn read<'a, T: Read<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Read' instruction with absolute addressing
+pub fn read<T: Read<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Absolute,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        instruction.execute(
-            &mut cpu.borrow_mut(),
-            u16::from_be_bytes([high_byte, low_byte]),
-        );
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Absolute);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        2 => state.high_byte = cpu.get_and_increment_pc(),
+        _ => {
+            instruction.execute(cpu, state.addr());
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the opcode for 'Write' instructions with absolute addressing
-pub fn write<'a, T: Write<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Write' instruction with absolute addressing
+pub fn write<T: Write<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Absolute,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        instruction.execute(
-            &mut cpu.borrow_mut(),
-            u16::from_be_bytes([high_byte, low_byte]),
-        );
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Absolute);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        2 => state.high_byte = cpu.get_and_increment_pc(),
+        _ => {
+            instruction.execute(cpu, state.addr());
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the opcode for 'Modify' instructions with absolute addressing
-pub fn modify<'a, T: Modify<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Modify' instruction with absolute addressing
+pub fn modify<T: Modify<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Absolute,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let addr = u16::from_be_bytes([high_byte, low_byte]);
-        let val: u8 = cpu.borrow().get_mem(addr);
-        yield cycle;
-        cycle.next();
-        cpu.borrow_mut().set_mem(addr, val);
-        yield cycle;
-        cycle.next();
-        instruction.execute(&mut cpu.borrow_mut(), addr, val);
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Absolute);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        2 => state.high_byte = cpu.get_and_increment_pc(),
+        3 => state.val = cpu.get_mem(state.addr()),
+        4 => cpu.set_mem(state.addr(), state.val),
+        _ => {
+            instruction.execute(cpu, state.addr(), state.val);
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the JSR opcode with absolute addressing
-pub fn jsr<'a, S: CPU>(
-    cpu: &'a RefCell<S>,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: InstructionName::JSR,
-            mode: AddressingMode::Absolute,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
+/// Runs a cycle of the JSR opcode with absolute addressing
+pub fn jsr<S: CPU>(cpu: &mut S, state: &mut OpcodeState) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(InstructionName::JSR, AddressingMode::Absolute);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
         // undocumented internal operation
-        yield cycle;
-        cycle.next();
-        let pc_high: u8 = cpu.borrow().get_pch();
-        cpu.borrow_mut().push_stack(pc_high);
-        yield cycle;
-        cycle.next();
-        let pc_low: u8 = cpu.borrow().get_pcl();
-        cpu.borrow_mut().push_stack(pc_low);
-        yield cycle;
-        cycle.next();
-        let high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        cpu.borrow_mut()
-            .set_pc(u16::from_be_bytes([high_byte, low_byte]));
-        cycle
-    })
+        2 => {}
+        3 => {
+            let pc_high: u8 = cpu.get_pch();
+            cpu.push_stack(pc_high);
+        }
+        4 => {
+            let pc_low: u8 = cpu.get_pcl();
+            cpu.push_stack(pc_low);
+        }
+        _ => {
+            state.high_byte = cpu.get_and_increment_pc();
+            cpu.set_pc(state.addr());
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the JMP opcode with absolute addressing
-pub fn jmp<'a, S: CPU>(
-    cpu: &'a RefCell<S>,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: InstructionName::JMP,
-            mode: AddressingMode::Absolute,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        cpu.borrow_mut()
-            .set_pc(u16::from_be_bytes([high_byte, low_byte]));
-        cycle
-    })
+/// Runs a cycle of the JMP opcode with absolute addressing
+pub fn jmp<S: CPU>(cpu: &mut S, state: &mut OpcodeState) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(InstructionName::JMP, AddressingMode::Absolute);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        _ => {
+            state.high_byte = cpu.get_and_increment_pc();
+            cpu.set_pc(state.addr());
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
 #[cfg(test)]
@@ -154,7 +113,7 @@ mod tests {
     use crate::cpu::variables::a_register::A;
     use crate::state::cpu::{Memory, Registers};
     use crate::state::NES;
-    use std::ops::GeneratorState;
+    use crate::state_machine::CycleState;
 
     #[test]
     fn test_read() {
@@ -165,24 +124,23 @@ mod tests {
         cpu.set_mem(cpu.get_pc() + 1, 0x00);
         cpu.set_mem(0x0023, 3);
         cpu.set_a(12);
-        let cpu = RefCell::new(cpu);
         let instruction = ADC;
-        let mut opcode = read(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Absolute,
             cycle: 0,
         };
         for _ in 0..3 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_a(), 12);
+            let state = read(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_a(), 12);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_a(), 15);
-        assert_eq!(cpu.borrow().get_pc(), 2);
+        let state = read(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_a(), 15);
+        assert_eq!(cpu.get_pc(), 2);
     }
 
     #[test]
@@ -193,24 +151,23 @@ mod tests {
         cpu.set_mem(cpu.get_pc(), 0x23);
         cpu.set_mem(cpu.get_pc() + 1, 0x01);
         cpu.set_mem(0x0123, 0b0100_0101);
-        let cpu = RefCell::new(cpu);
         let instruction = ASL;
-        let mut opcode = modify(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Absolute,
             cycle: 0,
         };
         for _ in 0..5 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_mem(0x0123), 0b0100_0101);
+            let state = modify(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_mem(0x0123), 0b0100_0101);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_mem(0x0123), 0b1000_1010);
-        assert_eq!(cpu.borrow().get_pc(), 2);
+        let state = modify(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_mem(0x0123), 0b1000_1010);
+        assert_eq!(cpu.get_pc(), 2);
     }
 
     #[test]
@@ -221,24 +178,23 @@ mod tests {
         cpu.set_mem(cpu.get_pc(), 0x23);
         cpu.set_mem(cpu.get_pc() + 1, 0x01);
         cpu.set_mem(0x0123, 0);
-        let cpu = RefCell::new(cpu);
         let instruction = ST(A);
-        let mut opcode = write(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Absolute,
             cycle: 0,
         };
         for _ in 0..3 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_mem(0x0123), 0);
+            let state = write(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_mem(0x0123), 0);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_mem(0x0123), 30);
-        assert_eq!(cpu.borrow().get_pc(), 2);
+        let state = write(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_mem(0x0123), 30);
+        assert_eq!(cpu.get_pc(), 2);
     }
 
     #[test]
@@ -253,59 +209,43 @@ mod tests {
         cpu.set_pc(u16::from_be_bytes([pc_high, pc_low]));
         cpu.set_mem(cpu.get_pc(), new_pc_low);
         cpu.set_mem(cpu.get_pc() + 1, new_pc_high);
-        let cpu = RefCell::new(cpu);
-        let mut opcode = jsr(&cpu);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: InstructionName::JSR,
             mode: AddressingMode::Absolute,
             cycle: 0,
         };
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), u16::from_be_bytes([pc_high, pc_low]));
+        let state = jsr(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high, pc_low]));
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([pc_high, pc_low]) + 1
-        );
+        let state = jsr(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high, pc_low]) + 1);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([pc_high, pc_low]) + 1
-        );
+        let state = jsr(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high, pc_low]) + 1);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_sp - 1);
-        assert_eq!(cpu.borrow().get_mem(0x01FF), pc_high);
-        assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([pc_high, pc_low]) + 1
-        );
+        let state = jsr(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_sp - 1);
+        assert_eq!(cpu.get_mem(0x01FF), pc_high);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high, pc_low]) + 1);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_sp - 2);
-        assert_eq!(cpu.borrow().get_mem(0x01FF), pc_high);
-        assert_eq!(cpu.borrow().get_mem(0x01FE), pc_low + 1);
-        assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([pc_high, pc_low]) + 1
-        );
+        let state = jsr(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_sp - 2);
+        assert_eq!(cpu.get_mem(0x01FF), pc_high);
+        assert_eq!(cpu.get_mem(0x01FE), pc_low + 1);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high, pc_low]) + 1);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_sp - 2);
-        assert_eq!(cpu.borrow().get_mem(0x01FF), pc_high);
-        assert_eq!(cpu.borrow().get_mem(0x01FE), pc_low + 1);
-        assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([new_pc_high, new_pc_low])
-        );
+        let state = jsr(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_s(), initial_sp - 2);
+        assert_eq!(cpu.get_mem(0x01FF), pc_high);
+        assert_eq!(cpu.get_mem(0x01FE), pc_low + 1);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([new_pc_high, new_pc_low]));
     }
 
     #[test]
@@ -314,23 +254,22 @@ mod tests {
         cpu.set_pc(0);
         cpu.set_mem(0, 0x34);
         cpu.set_mem(1, 0x41);
-        let cpu = RefCell::new(cpu);
-        let mut opcode = jmp(&cpu);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: InstructionName::JMP,
             mode: AddressingMode::Absolute,
             cycle: 0,
         };
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0);
+        let state = jmp(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 1);
+        let state = jmp(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 1);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x4134);
+        let state = jmp(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_pc(), 0x4134);
     }
 }
@@ -1,113 +1,95 @@
 use crate::{
     cpu::{
         instructions::{Modify, Read, Write},
-        opcode_generators::{AddressingMode, CPUCycle},
+        opcode_generators::{AddressingMode, CPUCycle, OpcodeState},
     },
     state::CPU,
+    state_machine::CycleState,
 };
-use std::{cell::RefCell, ops::Generator, pin::Pin};
 
-/// Creates the opcode for 'Read' instructions with absolute X addressing
-pub fn read<'a, T: Read<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Read' instruction with absolute X addressing
+pub fn read<T: Read<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::AbsoluteX,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let mut high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        let (low_byte, overflow): (u8, bool) = low_byte.overflowing_add(cpu.borrow().get_x());
-        yield cycle;
-        cycle.next();
-        if overflow {
-            high_byte = high_byte.wrapping_add(1);
-            yield cycle;
-            cycle.next();
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::AbsoluteX);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        2 => {
+            state.high_byte = cpu.get_and_increment_pc();
+            state.add_index(cpu.get_x());
+        }
+        3 if state.carry => {
+            // the high byte hasn't been fixed yet, so the wrong address is read
+            cpu.dummy_read(state.addr());
+            state.high_byte = state.high_byte.wrapping_add(1);
         }
-        instruction.execute(
-            &mut cpu.borrow_mut(),
-            u16::from_be_bytes([high_byte, low_byte]),
-        );
-        cycle
-    })
+        _ => {
+            instruction.execute(cpu, state.addr());
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the opcode for 'Write' instructions with absolute X addressing
-pub fn write<'a, T: Write<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Write' instruction with absolute X addressing
+pub fn write<T: Write<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::AbsoluteX,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let mut high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        let (low_byte, overflow): (u8, bool) = low_byte.overflowing_add(cpu.borrow().get_x());
-        yield cycle;
-        cycle.next();
-        if overflow {
-            high_byte = high_byte.wrapping_add(1);
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::AbsoluteX);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        2 => {
+            state.high_byte = cpu.get_and_increment_pc();
+            state.add_index(cpu.get_x());
+        }
+        3 => {
+            cpu.dummy_read(state.addr());
+            if state.carry {
+                state.high_byte = state.high_byte.wrapping_add(1);
+            }
+        }
+        _ => {
+            instruction.execute(cpu, state.addr());
+            return CycleState::Complete(cycle);
         }
-        yield cycle;
-        cycle.next();
-        instruction.execute(
-            &mut cpu.borrow_mut(),
-            u16::from_be_bytes([high_byte, low_byte]),
-        );
-        cycle
-    })
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the opcode for 'Modify' instructions with absolute X addressing
-pub fn modify<'a, T: Modify<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Modify' instruction with absolute X addressing
+pub fn modify<T: Modify<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::AbsoluteX,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let mut high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        let (low_byte, overflow): (u8, bool) = low_byte.overflowing_add(cpu.borrow().get_x());
-        yield cycle;
-        cycle.next();
-        if overflow {
-            high_byte = high_byte.wrapping_add(1);
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::AbsoluteX);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        2 => {
+            state.high_byte = cpu.get_and_increment_pc();
+            state.add_index(cpu.get_x());
         }
-        yield cycle;
-        cycle.next();
-        let addr = u16::from_be_bytes([high_byte, low_byte]);
-        let val: u8 = cpu.borrow().get_mem(addr);
-        yield cycle;
-        cycle.next();
-        cpu.borrow_mut().set_mem(addr, val);
-        yield cycle;
-        cycle.next();
-        instruction.execute(&mut cpu.borrow_mut(), addr, val);
-        cycle
-    })
+        3 => {
+            cpu.dummy_read(state.addr());
+            if state.carry {
+                state.high_byte = state.high_byte.wrapping_add(1);
+            }
+        }
+        4 => state.val = cpu.get_mem(state.addr()),
+        5 => cpu.set_mem(state.addr(), state.val),
+        _ => {
+            instruction.execute(cpu, state.addr(), state.val);
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
 #[cfg(test)]
@@ -117,7 +99,7 @@ mod tests {
     use crate::cpu::variables::a_register::A;
     use crate::state::cpu::{Memory, Registers};
     use crate::state::NES;
-    use std::ops::GeneratorState;
+    use crate::state_machine::CycleState;
 
     #[test]
     fn test_read() {
@@ -128,24 +110,23 @@ mod tests {
         cpu.set_mem(cpu.get_pc() + 1, 0x03);
         cpu.set_mem(0x0326, 43);
         cpu.set_a(120);
-        let cpu = RefCell::new(cpu);
         let instruction = ADC;
-        let mut opcode = read(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::AbsoluteX,
             cycle: 0,
         };
         for _ in 0..3 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_a(), 120);
+            let state = read(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_a(), 120);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_a(), 163);
-        assert_eq!(cpu.borrow().get_pc(), 2);
+        let state = read(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_a(), 163);
+        assert_eq!(cpu.get_pc(), 2);
     }
 
     #[test]
@@ -156,24 +137,23 @@ mod tests {
         cpu.set_mem(cpu.get_pc(), 0x23);
         cpu.set_mem(cpu.get_pc() + 1, 0x03);
         cpu.set_mem(0x0326, 0b0100_0101);
-        let cpu = RefCell::new(cpu);
         let instruction = ASL;
-        let mut opcode = modify(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::AbsoluteX,
             cycle: 0,
         };
         for _ in 0..6 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_mem(0x0326), 0b0100_0101);
+            let state = modify(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_mem(0x0326), 0b0100_0101);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_mem(0x0326), 0b1000_1010);
-        assert_eq!(cpu.borrow().get_pc(), 2);
+        let state = modify(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_mem(0x0326), 0b1000_1010);
+        assert_eq!(cpu.get_pc(), 2);
     }
 
     #[test]
@@ -185,23 +165,22 @@ mod tests {
         cpu.set_mem(cpu.get_pc(), 0x23);
         cpu.set_mem(cpu.get_pc() + 1, 0x03);
         cpu.set_mem(0x0327, 0);
-        let cpu = RefCell::new(cpu);
         let instruction = ST(A);
-        let mut opcode = write(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::AbsoluteX,
             cycle: 0,
         };
         for _ in 0..4 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_mem(0x0327), 0);
+            let state = write(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_mem(0x0327), 0);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_mem(0x0327), 43);
-        assert_eq!(cpu.borrow().get_pc(), 2);
+        let state = write(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_mem(0x0327), 43);
+        assert_eq!(cpu.get_pc(), 2);
     }
 }
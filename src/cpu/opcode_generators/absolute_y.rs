@@ -1,74 +1,65 @@
-use crate::cpu::{
-    instructions::{Read, Write},
-    opcode_generators::{AddressingMode, CPUCycle},
+use crate::{
+    cpu::{
+        instructions::{Read, Write},
+        opcode_generators::{AddressingMode, CPUCycle, OpcodeState},
+    },
+    state::CPU,
+    state_machine::CycleState,
 };
-use crate::state::CPU;
-use std::{cell::RefCell, ops::Generator, pin::Pin};
 
-/// Creates the opcode for 'Read' instructions with absolute Y addressing
-pub fn read<'a, T: Read<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Read' instruction with absolute Y addressing
+pub fn read<T: Read<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::AbsoluteY,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let mut high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        let (low_byte, overflow): (u8, bool) = low_byte.overflowing_add(cpu.borrow().get_y());
-        yield cycle;
-        cycle.next();
-        if overflow {
-            high_byte = high_byte.wrapping_add(1);
-            yield cycle;
-            cycle.next();
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::AbsoluteY);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        2 => {
+            state.high_byte = cpu.get_and_increment_pc();
+            state.add_index(cpu.get_y());
+        }
+        3 if state.carry => {
+            // the high byte hasn't been fixed yet, so the wrong address is read
+            cpu.dummy_read(state.addr());
+            state.high_byte = state.high_byte.wrapping_add(1);
         }
-        instruction.execute(
-            &mut cpu.borrow_mut(),
-            u16::from_be_bytes([high_byte, low_byte]),
-        );
-        cycle
-    })
+        _ => {
+            instruction.execute(cpu, state.addr());
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the opcode for 'Write' instructions with absolute Y addressing
-pub fn write<'a, T: Write<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Write' instruction with absolute Y addressing
+pub fn write<T: Write<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::AbsoluteY,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let mut high_byte: u8 = cpu.borrow_mut().get_and_increment_pc();
-        let (low_byte, overflow): (u8, bool) = low_byte.overflowing_add(cpu.borrow().get_y());
-        yield cycle;
-        cycle.next();
-        if overflow {
-            high_byte = high_byte.wrapping_add(1);
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::AbsoluteY);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        2 => {
+            state.high_byte = cpu.get_and_increment_pc();
+            state.add_index(cpu.get_y());
+        }
+        3 => {
+            cpu.dummy_read(state.addr());
+            if state.carry {
+                state.high_byte = state.high_byte.wrapping_add(1);
+            }
         }
-        yield cycle;
-        cycle.next();
-        instruction.execute(
-            &mut cpu.borrow_mut(),
-            u16::from_be_bytes([high_byte, low_byte]),
-        );
-        cycle
-    })
+        _ => {
+            instruction.execute(cpu, state.addr());
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
 #[cfg(test)]
@@ -78,7 +69,7 @@ mod tests {
     use crate::cpu::variables::a_register::A;
     use crate::state::cpu::{Memory, Registers};
     use crate::state::NES;
-    use std::ops::GeneratorState;
+    use crate::state_machine::CycleState;
 
     #[test]
     fn test_read() {
@@ -89,24 +80,23 @@ mod tests {
         cpu.set_mem(0x3125, 61);
         cpu.set_a(38);
         cpu.set_pc(0);
-        let cpu = RefCell::new(cpu);
         let instruction = ADC;
-        let mut opcode = read(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::AbsoluteY,
             cycle: 0,
         };
         for _ in 0..3 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_a(), 38);
+            let state = read(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_a(), 38);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_a(), 99);
-        assert_eq!(cpu.borrow().get_pc(), 2);
+        let state = read(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_a(), 99);
+        assert_eq!(cpu.get_pc(), 2);
     }
 
     #[test]
@@ -118,23 +108,22 @@ mod tests {
         cpu.set_mem(cpu.get_pc(), 0x23);
         cpu.set_mem(cpu.get_pc() + 1, 0x05);
         cpu.set_mem(0x0527, 0);
-        let cpu = RefCell::new(cpu);
         let instruction = ST(A);
-        let mut opcode = write(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::AbsoluteY,
             cycle: 0,
         };
         for _ in 0..4 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_mem(0x0527), 0);
+            let state = write(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_mem(0x0527), 0);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_mem(0x0527), 43);
-        assert_eq!(cpu.borrow().get_pc(), 2);
+        let state = write(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_mem(0x0527), 43);
+        assert_eq!(cpu.get_pc(), 2);
     }
 }
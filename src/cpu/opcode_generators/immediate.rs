@@ -1,28 +1,24 @@
 use crate::cpu::{
     instructions::Read,
-    opcode_generators::{AddressingMode, CPUCycle},
+    opcode_generators::{AddressingMode, CPUCycle, OpcodeState},
 };
 use crate::state::CPU;
-use std::{cell::RefCell, ops::Generator, pin::Pin};
+use crate::state_machine::CycleState;
 
-/// Creates the opcode for 'Read' instructions with immediate addressing
-pub fn read<'a, T: Read<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Read' instruction with immediate addressing
+pub fn read<T: Read<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Immediate,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let addr: u16 = cpu.borrow().get_pc();
-        instruction.execute(&mut cpu.borrow_mut(), addr);
-        cpu.borrow_mut().increment_pc();
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Immediate);
+    if cycle.cycle == 0 {
+        return CycleState::Yielded(cycle);
+    }
+    let addr: u16 = cpu.get_pc();
+    instruction.execute(cpu, addr);
+    cpu.increment_pc();
+    CycleState::Complete(cycle)
 }
 
 #[cfg(test)]
@@ -31,7 +27,7 @@ mod tests {
     use crate::cpu::instructions::{adc::ADC, Instruction};
     use crate::state::cpu::{Memory, Registers};
     use crate::state::NES;
-    use std::ops::GeneratorState;
+    use crate::state_machine::CycleState;
 
     #[test]
     fn test_read() {
@@ -39,23 +35,22 @@ mod tests {
         cpu.set_pc(0);
         cpu.set_mem(cpu.get_pc(), 3);
         cpu.set_a(12);
-        let cpu = RefCell::new(cpu);
         let instruction = ADC;
-        let mut opcode = read(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Immediate,
             cycle: 0,
         };
         for _ in 0..1 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_a(), 12);
+            let state = read(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_a(), 12);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_a(), 15);
-        assert_eq!(cpu.borrow().get_pc(), 1);
+        let state = read(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_a(), 15);
+        assert_eq!(cpu.get_pc(), 1);
     }
 }
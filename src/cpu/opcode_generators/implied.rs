@@ -2,183 +2,174 @@ use crate::{
     bitops::BitOps,
     cpu::{
         instructions::{Implied, InstructionName, PullStack, PushStack},
-        opcode_generators::{AddressingMode, CPUCycle},
+        interrupt::hijack_vector,
+        opcode_generators::{AddressingMode, CPUCycle, OpcodeState},
         variables::Flag,
     },
     state::CPU,
+    state_machine::CycleState,
 };
-use std::{cell::RefCell, ops::Generator, pin::Pin};
 
 const BRK_VECTOR: u16 = 0xFFFE;
 
-/// Creates the opcode for instructions with implied/accumulator addressing.
-pub fn implied<'a, T: Implied<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of an instruction with implied/accumulator addressing.
+pub fn implied<T: Implied<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Implied,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        instruction.execute(&mut cpu.borrow_mut());
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Implied);
+    if cycle.cycle == 0 {
+        return CycleState::Yielded(cycle);
+    }
+    let pc: u16 = cpu.get_pc();
+    cpu.dummy_read(pc);
+    instruction.execute(cpu);
+    CycleState::Complete(cycle)
 }
 
-/// Creates the opcode for instructions that push values to the stack (with implied addressing)
-pub fn push_stack<'a, T: PushStack<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of an instruction that pushes values to the stack (with implied addressing)
+pub fn push_stack<T: PushStack<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Implied,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        // read and throw away next byte
-        yield cycle;
-        cycle.next();
-        let register: u8 = instruction.get(&cpu.borrow_mut());
-        cpu.borrow_mut().push_stack(register);
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Implied);
+    match cycle.cycle {
+        0 => {}
+        1 => {
+            // read and throw away next byte
+            let pc: u16 = cpu.get_pc();
+            cpu.dummy_read(pc);
+        }
+        _ => {
+            let register: u8 = instruction.get(cpu);
+            cpu.push_stack(register);
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the opcode for instructions that pull values from the stack (with implied addressing)
-pub fn pull_stack<'a, T: PullStack<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of an instruction that pulls values from the stack (with implied addressing)
+pub fn pull_stack<T: PullStack<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Implied,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        // read and throw away next byte
-        yield cycle;
-        cycle.next();
-        cpu.borrow_mut().pop_stack();
-        yield cycle;
-        cycle.next();
-        let top: u8 = cpu.borrow().top_stack();
-        instruction.set(&mut cpu.borrow_mut(), top);
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Implied);
+    match cycle.cycle {
+        0 => {}
+        1 => {
+            // read and throw away next byte
+            let pc: u16 = cpu.get_pc();
+            cpu.dummy_read(pc);
+        }
+        2 => cpu.pop_stack(),
+        _ => {
+            let top: u8 = cpu.top_stack();
+            instruction.set(cpu, top);
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the RTI opcode (which has implied addressing)
-pub fn rti<'a, S: CPU>(
-    cpu: &'a RefCell<S>,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: InstructionName::RTI,
-            mode: AddressingMode::Implied,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        // throw away next instruction byte
-        yield cycle;
-        cycle.next();
-        cpu.borrow_mut().pop_stack();
-        yield cycle;
-        cycle.next();
-        let top: u8 = cpu.borrow().top_stack();
-        cpu.borrow_mut().set_p(top);
-        cpu.borrow_mut().pop_stack();
-        yield cycle;
-        cycle.next();
-        let top: u8 = cpu.borrow().top_stack();
-        cpu.borrow_mut().set_pcl(top);
-        cpu.borrow_mut().pop_stack();
-        yield cycle;
-        cycle.next();
-        let top: u8 = cpu.borrow().top_stack();
-        cpu.borrow_mut().set_pch(top);
-        cycle
-    })
+/// Runs a cycle of the RTI opcode (which has implied addressing)
+pub fn rti<S: CPU>(cpu: &mut S, state: &mut OpcodeState) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(InstructionName::RTI, AddressingMode::Implied);
+    match cycle.cycle {
+        0 => {}
+        1 => {
+            // throw away next instruction byte
+            let pc: u16 = cpu.get_pc();
+            cpu.dummy_read(pc);
+        }
+        2 => cpu.pop_stack(),
+        3 => {
+            let top: u8 = cpu.top_stack();
+            cpu.set_p(top);
+            cpu.pop_stack();
+        }
+        4 => {
+            let top: u8 = cpu.top_stack();
+            cpu.set_pcl(top);
+            cpu.pop_stack();
+        }
+        _ => {
+            let top: u8 = cpu.top_stack();
+            cpu.set_pch(top);
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the RTS opcode (which has implied addressing)
-pub fn rts<'a, S: CPU>(
-    cpu: &'a RefCell<S>,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: InstructionName::RTS,
-            mode: AddressingMode::Implied,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        // throw away next instruction byte
-        yield cycle;
-        cycle.next();
-        cpu.borrow_mut().pop_stack();
-        yield cycle;
-        cycle.next();
-        let top: u8 = cpu.borrow().top_stack();
-        cpu.borrow_mut().set_pcl(top);
-        cpu.borrow_mut().pop_stack();
-        yield cycle;
-        cycle.next();
-        let top: u8 = cpu.borrow().top_stack();
-        cpu.borrow_mut().set_pch(top);
-        yield cycle;
-        cycle.next();
-        cpu.borrow_mut().increment_pc();
-        cycle
-    })
+/// Runs a cycle of the RTS opcode (which has implied addressing)
+pub fn rts<S: CPU>(cpu: &mut S, state: &mut OpcodeState) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(InstructionName::RTS, AddressingMode::Implied);
+    match cycle.cycle {
+        0 => {}
+        1 => {
+            // throw away next instruction byte
+            let pc: u16 = cpu.get_pc();
+            cpu.dummy_read(pc);
+        }
+        2 => cpu.pop_stack(),
+        3 => {
+            let top: u8 = cpu.top_stack();
+            cpu.set_pcl(top);
+            cpu.pop_stack();
+        }
+        4 => {
+            let top: u8 = cpu.top_stack();
+            cpu.set_pch(top);
+        }
+        _ => {
+            cpu.increment_pc();
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the BRK opcode (which has implied addressing)
-pub fn brk<'a, S: CPU>(
-    cpu: &'a RefCell<S>,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: InstructionName::BRK,
-            mode: AddressingMode::Implied,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        cpu.borrow_mut().increment_pc();
-        yield cycle;
-        cycle.next();
-        let pc_high: u8 = cpu.borrow().get_pch();
-        cpu.borrow_mut().push_stack(pc_high);
-        yield cycle;
-        cycle.next();
-        let pc_low: u8 = cpu.borrow().get_pcl();
-        cpu.borrow_mut().push_stack(pc_low);
-        yield cycle;
-        cycle.next();
-        let mut p_register: u8 = cpu.borrow().get_p();
-        p_register.set_bit(Flag::B as usize);
-        cpu.borrow_mut().push_stack(p_register);
-        yield cycle;
-        cycle.next();
-        let interrupt_low: u8 = cpu.borrow().get_mem(BRK_VECTOR);
-        cpu.borrow_mut().set_pcl(interrupt_low);
-        cpu.borrow_mut().assign_flag(Flag::I, true);
-        yield cycle;
-        cycle.next();
-        let interrupt_high: u8 = cpu.borrow().get_mem(BRK_VECTOR + 1);
-        cpu.borrow_mut().set_pch(interrupt_high);
-        cycle
-    })
+/// Runs a cycle of the BRK opcode (which has implied addressing)
+pub fn brk<S: CPU>(cpu: &mut S, state: &mut OpcodeState) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(InstructionName::BRK, AddressingMode::Implied);
+    match cycle.cycle {
+        0 => {}
+        1 => {
+            let pc: u16 = cpu.get_pc();
+            cpu.dummy_read(pc);
+            cpu.increment_pc();
+        }
+        2 => {
+            let pc_high: u8 = cpu.get_pch();
+            cpu.push_stack(pc_high);
+        }
+        3 => {
+            let pc_low: u8 = cpu.get_pcl();
+            cpu.push_stack(pc_low);
+        }
+        4 => {
+            let mut p_register: u8 = cpu.get_p();
+            p_register.set_bit(Flag::B as usize);
+            cpu.push_stack(p_register);
+        }
+        5 => {
+            // the vector is chosen here, and used for the rest of the instruction
+            state.pointer = hijack_vector(cpu, BRK_VECTOR);
+            let interrupt_low: u8 = cpu.get_mem(state.pointer);
+            cpu.set_pcl(interrupt_low);
+            cpu.assign_flag(Flag::I, true);
+        }
+        _ => {
+            let interrupt_high: u8 = cpu.get_mem(state.pointer + 1);
+            cpu.set_pch(interrupt_high);
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
 #[cfg(test)]
@@ -188,31 +179,30 @@ mod tests {
     use crate::cpu::variables::{a_register::A, p_register::P};
     use crate::state::cpu::{Memory, Registers, Stack};
     use crate::state::NES;
-    use std::ops::GeneratorState;
+    use crate::state_machine::CycleState;
 
     #[test]
     fn test_implied() {
         let mut cpu = NES::mock();
         cpu.set_pc(0);
         cpu.set_a(0b0110_1010);
-        let cpu = RefCell::new(cpu);
         let instruction = ASL;
-        let mut opcode = implied(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Implied,
             cycle: 0,
         };
         for _ in 0..1 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_a(), 0b0110_1010);
+            let state = implied(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_a(), 0b0110_1010);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_a(), 0b1101_0100);
-        assert_eq!(cpu.borrow().get_pc(), 0);
+        let state = implied(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_a(), 0b1101_0100);
+        assert_eq!(cpu.get_pc(), 0);
     }
 
     #[test]
@@ -223,26 +213,25 @@ mod tests {
         let initial_stack_pointer: u8 = 0xFF;
         cpu.set_s(initial_stack_pointer);
         cpu.set_mem(0x01FF, 2);
-        let cpu = RefCell::new(cpu);
         let instruction = PH(A);
-        let mut opcode = push_stack(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Implied,
             cycle: 0,
         };
         for _ in 0..2 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
-            assert_eq!(cpu.borrow().top_stack(), 2);
+            let state = push_stack(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_s(), initial_stack_pointer);
+            assert_eq!(cpu.top_stack(), 2);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 1);
-        cpu.borrow_mut().pop_stack();
-        assert_eq!(cpu.borrow().top_stack(), a_register);
+        let state = push_stack(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer - 1);
+        cpu.pop_stack();
+        assert_eq!(cpu.top_stack(), a_register);
     }
 
     #[test]
@@ -254,30 +243,29 @@ mod tests {
         cpu.set_p(p_register_old);
         cpu.set_s(initial_stack_pointer);
         cpu.push_stack(p_register_new);
-        let cpu = RefCell::new(cpu);
         let instruction = PL(P);
-        let mut opcode = pull_stack(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Implied,
             cycle: 0,
         };
         for _ in 0..2 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 1);
-            assert_eq!(cpu.borrow().get_p(), p_register_old);
+            let state = pull_stack(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_s(), initial_stack_pointer - 1);
+            assert_eq!(cpu.get_p(), p_register_old);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
-        assert_eq!(cpu.borrow().get_p(), p_register_old);
-        cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
-        assert_eq!(cpu.borrow().get_p(), 0b1110_0000);
+        let state = pull_stack(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer);
+        assert_eq!(cpu.get_p(), p_register_old);
+        cycle.next();
+        let state = pull_stack(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer);
+        assert_eq!(cpu.get_p(), 0b1110_0000);
     }
 
     #[test]
@@ -295,47 +283,43 @@ mod tests {
         cpu.push_stack(pc_high_new);
         cpu.push_stack(pc_low_new);
         cpu.push_stack(p_register_new);
-        let cpu = RefCell::new(cpu);
-        let mut opcode = rti(&cpu);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: InstructionName::RTI,
             mode: AddressingMode::Implied,
             cycle: 0,
         };
         for _ in 0..2 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 3);
-            assert_eq!(cpu.borrow().get_p(), p_register_old);
-            assert_eq!(cpu.borrow().get_pc(), pc_old);
+            let state = rti(&mut cpu, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_s(), initial_stack_pointer - 3);
+            assert_eq!(cpu.get_p(), p_register_old);
+            assert_eq!(cpu.get_pc(), pc_old);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 2);
-        assert_eq!(cpu.borrow().get_p(), p_register_old);
-        assert_eq!(cpu.borrow().get_pc(), pc_old);
-        cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 1);
-        assert_eq!(cpu.borrow().get_p(), p_register_new | 0b0010_0000);
-        assert_eq!(cpu.borrow().get_pc(), pc_old);
-        cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
-        assert_eq!(cpu.borrow().get_p(), p_register_new | 0b0010_0000);
-        assert_eq!(cpu.borrow().get_pc(), u16::from_be_bytes([0, pc_low_new]));
-        cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
-        assert_eq!(cpu.borrow().get_p(), p_register_new | 0b0010_0000);
-        assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([pc_high_new, pc_low_new])
-        );
+        let state = rti(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer - 2);
+        assert_eq!(cpu.get_p(), p_register_old);
+        assert_eq!(cpu.get_pc(), pc_old);
+        cycle.next();
+        let state = rti(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer - 1);
+        assert_eq!(cpu.get_p(), p_register_new | 0b0010_0000);
+        assert_eq!(cpu.get_pc(), pc_old);
+        cycle.next();
+        let state = rti(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer);
+        assert_eq!(cpu.get_p(), p_register_new | 0b0010_0000);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([0, pc_low_new]));
+        cycle.next();
+        let state = rti(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer);
+        assert_eq!(cpu.get_p(), p_register_new | 0b0010_0000);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high_new, pc_low_new]));
     }
 
     #[test]
@@ -349,43 +333,39 @@ mod tests {
         cpu.set_s(initial_stack_pointer);
         cpu.push_stack(pc_high_new);
         cpu.push_stack(pc_low_new);
-        let cpu = RefCell::new(cpu);
-        let mut opcode = rts(&cpu);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: InstructionName::RTS,
             mode: AddressingMode::Implied,
             cycle: 0,
         };
         for _ in 0..2 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 2);
-            assert_eq!(cpu.borrow().get_pc(), pc_old);
+            let state = rts(&mut cpu, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_s(), initial_stack_pointer - 2);
+            assert_eq!(cpu.get_pc(), pc_old);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 1);
-        assert_eq!(cpu.borrow().get_pc(), pc_old);
-        cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
-        assert_eq!(cpu.borrow().get_pc(), u16::from_be_bytes([0, pc_low_new]));
-        cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
+        let state = rts(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer - 1);
+        assert_eq!(cpu.get_pc(), pc_old);
+        cycle.next();
+        let state = rts(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([0, pc_low_new]));
+        cycle.next();
+        let state = rts(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high_new, pc_low_new]));
+        cycle.next();
+        let state = rts(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer);
         assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([pc_high_new, pc_low_new])
-        );
-        cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
-        assert_eq!(
-            cpu.borrow().get_pc(),
+            cpu.get_pc(),
             u16::from_be_bytes([pc_high_new, pc_low_new]) + 1
         );
     }
@@ -400,79 +380,69 @@ mod tests {
         cpu.set_p(p_register);
         cpu.set_pc(u16::from_be_bytes([pc_high_old, pc_low_old]));
         cpu.set_s(initial_stack_pointer);
-        let cpu = RefCell::new(cpu);
-        let mut opcode = brk(&cpu);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: InstructionName::BRK,
             mode: AddressingMode::Implied,
             cycle: 0,
         };
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
-        assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([pc_high_old, pc_low_old])
-        );
-        cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer);
+        let state = brk(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high_old, pc_low_old]));
+        cycle.next();
+        let state = brk(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer);
         assert_eq!(
-            cpu.borrow().get_pc(),
+            cpu.get_pc(),
             u16::from_be_bytes([pc_high_old, pc_low_old]) + 1
         );
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 1);
-        assert_eq!(cpu.borrow().get_mem(0x01FF), pc_high_old);
+        let state = brk(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer - 1);
+        assert_eq!(cpu.get_mem(0x01FF), pc_high_old);
         assert_eq!(
-            cpu.borrow().get_pc(),
+            cpu.get_pc(),
             u16::from_be_bytes([pc_high_old, pc_low_old]) + 1
         );
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 2);
-        assert_eq!(cpu.borrow().get_mem(0x01FF), pc_high_old);
-        assert_eq!(cpu.borrow().get_mem(0x01FE), pc_low_old + 1);
+        let state = brk(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer - 2);
+        assert_eq!(cpu.get_mem(0x01FF), pc_high_old);
+        assert_eq!(cpu.get_mem(0x01FE), pc_low_old + 1);
         assert_eq!(
-            cpu.borrow().get_pc(),
+            cpu.get_pc(),
             u16::from_be_bytes([pc_high_old, pc_low_old]) + 1
         );
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 3);
-        assert_eq!(cpu.borrow().get_mem(0x01FF), pc_high_old);
-        assert_eq!(cpu.borrow().get_mem(0x01FE), pc_low_old + 1);
-        assert_eq!(cpu.borrow().get_mem(0x01FD), p_register | 0b0001_0000);
+        let state = brk(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer - 3);
+        assert_eq!(cpu.get_mem(0x01FF), pc_high_old);
+        assert_eq!(cpu.get_mem(0x01FE), pc_low_old + 1);
+        assert_eq!(cpu.get_mem(0x01FD), p_register | 0b0001_0000);
         assert_eq!(
-            cpu.borrow().get_pc(),
+            cpu.get_pc(),
             u16::from_be_bytes([pc_high_old, pc_low_old]) + 1
         );
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 3);
-        assert_eq!(cpu.borrow().get_mem(0x01FF), pc_high_old);
-        assert_eq!(cpu.borrow().get_mem(0x01FE), pc_low_old + 1);
-        assert_eq!(cpu.borrow().get_mem(0x01FD), p_register | 0b0001_0000);
-        assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([pc_high_old, pc_low_new])
-        );
-        cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_s(), initial_stack_pointer - 3);
-        assert_eq!(cpu.borrow().get_mem(0x01FF), pc_high_old);
-        assert_eq!(cpu.borrow().get_mem(0x01FE), pc_low_old + 1);
-        assert_eq!(cpu.borrow().get_mem(0x01FD), p_register | 0b0001_0000);
-        assert_eq!(
-            cpu.borrow().get_pc(),
-            u16::from_be_bytes([pc_high_new, pc_low_new])
-        );
+        let state = brk(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer - 3);
+        assert_eq!(cpu.get_mem(0x01FF), pc_high_old);
+        assert_eq!(cpu.get_mem(0x01FE), pc_low_old + 1);
+        assert_eq!(cpu.get_mem(0x01FD), p_register | 0b0001_0000);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high_old, pc_low_new]));
+        cycle.next();
+        let state = brk(&mut cpu, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_s(), initial_stack_pointer - 3);
+        assert_eq!(cpu.get_mem(0x01FF), pc_high_old);
+        assert_eq!(cpu.get_mem(0x01FE), pc_low_old + 1);
+        assert_eq!(cpu.get_mem(0x01FD), p_register | 0b0001_0000);
+        assert_eq!(cpu.get_pc(), u16::from_be_bytes([pc_high_new, pc_low_new]));
     }
 }
@@ -1,128 +1,95 @@
 use crate::{
     cpu::{
         instructions::{Modify, Read, Write},
-        opcode_generators::{AddressingMode, CPUCycle},
+        opcode_generators::{AddressingMode, CPUCycle, OpcodeState},
     },
     state::CPU,
+    state_machine::CycleState,
 };
-use std::{cell::RefCell, ops::Generator, pin::Pin};
 
-/// Creates the opcode for 'Read' instructions with indirect Y addressing
-pub fn read<'a, T: Read<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
-    instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::IndirectY,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let pointer: u8 = cpu.borrow_mut().get_and_increment_pc();
-        let pointer_low: u16 = u16::from_be_bytes([0, pointer]);
-        let pointer_high: u16 = u16::from_be_bytes([0, pointer.wrapping_add(1)]);
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow().get_mem(pointer_low);
-        yield cycle;
-        cycle.next();
-        let mut high_byte: u8 = cpu.borrow().get_mem(pointer_high);
-        let (low_byte, overflow): (u8, bool) = low_byte.overflowing_add(cpu.borrow().get_y());
-        yield cycle;
-        cycle.next();
-        if overflow {
-            high_byte = high_byte.wrapping_add(1);
-            yield cycle;
-            cycle.next();
+/// Runs the cycles shared by every instruction with indirect Y addressing, which fetch the
+/// unindexed address. Returns `false` once the address has been fetched.
+fn fetch_address<S: CPU>(cpu: &mut S, state: &mut OpcodeState, cycle: u8) -> bool {
+    match cycle {
+        0 => {}
+        1 => state.pointer = u16::from(cpu.get_and_increment_pc()),
+        2 => state.low_byte = cpu.get_mem(state.pointer),
+        3 => {
+            let pointer_high: u16 = u16::from((state.pointer as u8).wrapping_add(1));
+            state.high_byte = cpu.get_mem(pointer_high);
+            state.add_index(cpu.get_y());
         }
-        instruction.execute(
-            &mut cpu.borrow_mut(),
-            u16::from_be_bytes([high_byte, low_byte]),
-        );
-        cycle
-    })
+        _ => return false,
+    }
+    true
 }
 
-/// Creates the opcode for 'Write' instructions with indirect Y addressing
-pub fn write<'a, T: Write<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Read' instruction with indirect Y addressing
+pub fn read<T: Read<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::IndirectY,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let pointer: u8 = cpu.borrow_mut().get_and_increment_pc();
-        let pointer_low: u16 = u16::from_be_bytes([0, pointer]);
-        let pointer_high: u16 = u16::from_be_bytes([0, pointer.wrapping_add(1)]);
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow().get_mem(pointer_low);
-        yield cycle;
-        cycle.next();
-        let mut high_byte: u8 = cpu.borrow().get_mem(pointer_high);
-        let (low_byte, overflow) = low_byte.overflowing_add(cpu.borrow().get_y());
-        yield cycle;
-        cycle.next();
-        if overflow {
-            high_byte = high_byte.wrapping_add(1);
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::IndirectY);
+    if fetch_address(cpu, state, cycle.cycle) {
+        return CycleState::Yielded(cycle);
+    }
+    if cycle.cycle == 4 && state.carry {
+        // the high byte hasn't been fixed yet, so the wrong address is read
+        cpu.dummy_read(state.addr());
+        state.high_byte = state.high_byte.wrapping_add(1);
+        return CycleState::Yielded(cycle);
+    }
+    instruction.execute(cpu, state.addr());
+    CycleState::Complete(cycle)
+}
+
+/// Runs a cycle of a 'Write' instruction with indirect Y addressing
+pub fn write<T: Write<S>, S: CPU>(
+    cpu: &mut S,
+    instruction: T,
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::IndirectY);
+    if fetch_address(cpu, state, cycle.cycle) {
+        return CycleState::Yielded(cycle);
+    }
+    if cycle.cycle == 4 {
+        cpu.dummy_read(state.addr());
+        if state.carry {
+            state.high_byte = state.high_byte.wrapping_add(1);
         }
-        yield cycle;
-        cycle.next();
-        instruction.execute(
-            &mut cpu.borrow_mut(),
-            u16::from_be_bytes([high_byte, low_byte]),
-        );
-        cycle
-    })
+        return CycleState::Yielded(cycle);
+    }
+    instruction.execute(cpu, state.addr());
+    CycleState::Complete(cycle)
 }
 
-/// Creates the opcode for 'Modify' instructions with indirect Y addressing
-pub fn _modify<'a, T: Modify<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Modify' instruction with indirect Y addressing
+pub fn _modify<T: Modify<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::IndirectY,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let pointer: u8 = cpu.borrow_mut().get_and_increment_pc();
-        let pointer_low: u16 = u16::from_be_bytes([0, pointer]);
-        let pointer_high: u16 = u16::from_be_bytes([0, pointer.wrapping_add(1)]);
-        yield cycle;
-        cycle.next();
-        let low_byte: u8 = cpu.borrow().get_mem(pointer_low);
-        yield cycle;
-        cycle.next();
-        let mut high_byte: u8 = cpu.borrow().get_mem(pointer_high);
-        let (low_byte, overflow): (u8, bool) = low_byte.overflowing_add(cpu.borrow().get_y());
-        yield cycle;
-        cycle.next();
-        if overflow {
-            high_byte = high_byte.wrapping_add(1);
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::IndirectY);
+    if fetch_address(cpu, state, cycle.cycle) {
+        return CycleState::Yielded(cycle);
+    }
+    match cycle.cycle {
+        4 => {
+            cpu.dummy_read(state.addr());
+            if state.carry {
+                state.high_byte = state.high_byte.wrapping_add(1);
+            }
+        }
+        5 => state.val = cpu.get_mem(state.addr()),
+        6 => cpu.set_mem(state.addr(), state.val),
+        _ => {
+            instruction.execute(cpu, state.addr(), state.val);
+            return CycleState::Complete(cycle);
         }
-        yield cycle;
-        cycle.next();
-        let addr = u16::from_be_bytes([high_byte, low_byte]);
-        let val: u8 = cpu.borrow().get_mem(addr);
-        yield cycle;
-        cycle.next();
-        cpu.borrow_mut().set_mem(addr, val);
-        yield cycle;
-        cycle.next();
-        instruction.execute(&mut cpu.borrow_mut(), addr, val);
-        cycle
-    })
+    }
+    CycleState::Yielded(cycle)
 }
 
 #[cfg(test)]
@@ -132,7 +99,7 @@ mod tests {
     use crate::cpu::variables::a_register::A;
     use crate::state::cpu::{Memory, Registers};
     use crate::state::NES;
-    use std::ops::GeneratorState;
+    use crate::state_machine::CycleState;
 
     #[test]
     fn test_read() {
@@ -144,24 +111,23 @@ mod tests {
         cpu.set_mem(0x21, 0x11);
         cpu.set_mem(0x1146, 43);
         cpu.set_a(120);
-        let cpu = RefCell::new(cpu);
         let instruction = ADC;
-        let mut opcode = read(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::IndirectY,
             cycle: 0,
         };
         for _ in 0..4 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_a(), 120);
+            let state = read(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_a(), 120);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_a(), 163);
-        assert_eq!(cpu.borrow().get_pc(), 1);
+        let state = read(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_a(), 163);
+        assert_eq!(cpu.get_pc(), 1);
     }
 
     #[test]
@@ -173,24 +139,23 @@ mod tests {
         cpu.set_mem(0x23, 0x26);
         cpu.set_mem(0x24, 0x09);
         cpu.set_mem(0x0929, 0b0100_0101);
-        let cpu = RefCell::new(cpu);
         let instruction = ASL;
-        let mut opcode = _modify(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::IndirectY,
             cycle: 0,
         };
         for _ in 0..7 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_mem(0x0929), 0b0100_0101);
+            let state = _modify(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_mem(0x0929), 0b0100_0101);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_mem(0x0929), 0b1000_1010);
-        assert_eq!(cpu.borrow().get_pc(), 1);
+        let state = _modify(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_mem(0x0929), 0b1000_1010);
+        assert_eq!(cpu.get_pc(), 1);
     }
 
     #[test]
@@ -203,23 +168,22 @@ mod tests {
         cpu.set_mem(0x10, 0x20);
         cpu.set_mem(0x11, 0x03);
         cpu.set_mem(0x0324, 0);
-        let cpu = RefCell::new(cpu);
         let instruction = ST(A);
-        let mut opcode = write(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::IndirectY,
             cycle: 0,
         };
         for _ in 0..5 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_mem(0x0324), 0);
+            let state = write(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_mem(0x0324), 0);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_mem(0x0324), 43);
-        assert_eq!(cpu.borrow().get_pc(), 1);
+        let state = write(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_mem(0x0324), 43);
+        assert_eq!(cpu.get_pc(), 1);
     }
 }
@@ -1,47 +1,51 @@
 use crate::cpu::{
     instructions::Branch,
-    opcode_generators::{AddressingMode, CPUCycle},
+    opcode_generators::{AddressingMode, CPUCycle, OpcodeState},
 };
 use crate::state::CPU;
-use std::{cell::RefCell, ops::Generator, pin::Pin};
+use crate::state_machine::CycleState;
 
-/// Creates the opcode for instructions with relative addressing.
-pub fn relative<'a, T: Branch<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of an instruction with relative addressing.
+pub fn relative<T: Branch<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Relative,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let operand: u8 = cpu.borrow_mut().get_and_increment_pc();
-        let branch: bool = instruction.should_branch(&cpu.borrow());
-        if branch {
-            yield cycle;
-            cycle.next();
-            let [pc_high, pc_low]: [u8; 2] = cpu.borrow().get_pc().to_be_bytes();
-            let new_pc_low: u8 = pc_low.wrapping_add(operand);
-            cpu.borrow_mut().set_pcl(new_pc_low);
-            // if a negative overflow occurs
-            if new_pc_low > pc_low && (operand as i8) < 0 {
-                yield cycle;
-                cycle.next();
-                let pc_high = pc_high.wrapping_sub(1);
-                cpu.borrow_mut().set_pch(pc_high);
-            // if a positive overflow occurs
-            } else if new_pc_low < pc_low && (operand as i8) > 0 {
-                yield cycle;
-                cycle.next();
-                let pc_high = pc_high.wrapping_add(1);
-                cpu.borrow_mut().set_pch(pc_high);
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Relative);
+    match cycle.cycle {
+        0 => {}
+        1 => {
+            state.val = cpu.get_and_increment_pc();
+            if !instruction.should_branch(cpu) {
+                return CycleState::Complete(cycle);
             }
         }
-        cycle
-    })
+        2 => {
+            let [pc_high, pc_low]: [u8; 2] = cpu.get_pc().to_be_bytes();
+            cpu.dummy_read(u16::from_be_bytes([pc_high, pc_low]));
+            let new_pc_low: u8 = pc_low.wrapping_add(state.val);
+            cpu.set_pcl(new_pc_low);
+            // if a negative or positive overflow occurs
+            state.carry = (new_pc_low > pc_low && (state.val as i8) < 0)
+                || (new_pc_low < pc_low && (state.val as i8) > 0);
+            if !state.carry {
+                return CycleState::Complete(cycle);
+            }
+        }
+        _ => {
+            // the low byte has already been changed, but the high byte hasn't been fixed yet
+            let pc: u16 = cpu.get_pc();
+            cpu.dummy_read(pc);
+            let [pc_high, _]: [u8; 2] = pc.to_be_bytes();
+            if (state.val as i8) < 0 {
+                cpu.set_pch(pc_high.wrapping_sub(1));
+            } else {
+                cpu.set_pch(pc_high.wrapping_add(1));
+            }
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
 #[cfg(test)]
@@ -53,7 +57,7 @@ mod tests {
     };
     use crate::state::cpu::{Memory, Registers};
     use crate::state::NES;
-    use std::ops::GeneratorState;
+    use crate::state_machine::CycleState;
 
     #[test]
     fn test_relative_positive_overflow() {
@@ -61,29 +65,28 @@ mod tests {
         cpu.assign_flag(Flag::C, false);
         cpu.set_pc(0x10F5);
         cpu.set_mem(cpu.get_pc(), 13i8 as u8);
-        let cpu = RefCell::new(cpu);
         let instruction = BC(Flag::C);
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Relative,
             cycle: 0,
         };
-        let mut opcode = relative(&cpu, instruction);
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x10F5);
+        let mut opcode = OpcodeState::default();
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0x10F5);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x10F6);
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0x10F6);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x1003);
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0x1003);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x1103);
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_pc(), 0x1103);
     }
 
     #[test]
@@ -92,29 +95,28 @@ mod tests {
         cpu.assign_flag(Flag::C, false);
         cpu.set_pc(0x1204);
         cpu.set_mem(cpu.get_pc(), -30i8 as u8);
-        let cpu = RefCell::new(cpu);
         let instruction = BC(Flag::C);
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Relative,
             cycle: 0,
         };
-        let mut opcode = relative(&cpu, instruction);
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x1204);
+        let mut opcode = OpcodeState::default();
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0x1204);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x1205);
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0x1205);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x12E7);
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0x12E7);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x11E7);
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_pc(), 0x11E7);
     }
 
     #[test]
@@ -123,25 +125,24 @@ mod tests {
         cpu.assign_flag(Flag::C, false);
         cpu.set_pc(0x1204);
         cpu.set_mem(cpu.get_pc(), 30i8 as u8);
-        let cpu = RefCell::new(cpu);
         let instruction = BC(Flag::C);
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Relative,
             cycle: 0,
         };
-        let mut opcode = relative(&cpu, instruction);
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x1204);
+        let mut opcode = OpcodeState::default();
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0x1204);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x1205);
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0x1205);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x1223);
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_pc(), 0x1223);
     }
 
     #[test]
@@ -150,20 +151,19 @@ mod tests {
         cpu.assign_flag(Flag::C, true);
         cpu.set_pc(0x1204);
         cpu.set_mem(cpu.get_pc(), 30i8 as u8);
-        let cpu = RefCell::new(cpu);
         let instruction = BC(Flag::C);
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Relative,
             cycle: 0,
         };
-        let mut opcode = relative(&cpu, instruction);
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Yielded(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x1204);
+        let mut opcode = OpcodeState::default();
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Yielded(cycle));
+        assert_eq!(cpu.get_pc(), 0x1204);
         cycle.next();
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_pc(), 0x1205);
+        let state = relative(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_pc(), 0x1205);
     }
 }
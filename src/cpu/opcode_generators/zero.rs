@@ -1,80 +1,66 @@
 use crate::{
     cpu::{
         instructions::{Modify, Read, Write},
-        opcode_generators::{AddressingMode, CPUCycle},
+        opcode_generators::{AddressingMode, CPUCycle, OpcodeState},
     },
     state::CPU,
+    state_machine::CycleState,
 };
-use std::{cell::RefCell, ops::Generator, pin::Pin};
 
-/// Creates the opcode for 'Read' instructions with zero addressing
-pub fn read<'a, T: Read<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Read' instruction with zero addressing
+pub fn read<T: Read<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Zero,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let address: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        instruction.execute(&mut cpu.borrow_mut(), u16::from_be_bytes([0, address]));
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Zero);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        _ => {
+            instruction.execute(cpu, state.addr());
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the opcode for 'Write' instructions with zero addressing
-pub fn write<'a, T: Write<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Write' instruction with zero addressing
+pub fn write<T: Write<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Zero,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let address: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        instruction.execute(&mut cpu.borrow_mut(), u16::from_be_bytes([0, address]));
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Zero);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        _ => {
+            instruction.execute(cpu, state.addr());
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
-/// Creates the opcode for 'Modify' instructions with zero addressing
-pub fn modify<'a, T: Modify<S> + 'a, S: CPU>(
-    cpu: &'a RefCell<S>,
+/// Runs a cycle of a 'Modify' instruction with zero addressing
+pub fn modify<T: Modify<S>, S: CPU>(
+    cpu: &mut S,
     instruction: T,
-) -> Pin<Box<dyn Generator<Yield = CPUCycle, Return = CPUCycle> + 'a>> {
-    Box::pin(move || {
-        let mut cycle = CPUCycle {
-            instruction: instruction.name(),
-            mode: AddressingMode::Zero,
-            cycle: 0,
-        };
-        yield cycle;
-        cycle.next();
-        let address: u8 = cpu.borrow_mut().get_and_increment_pc();
-        yield cycle;
-        cycle.next();
-        let addr = u16::from_be_bytes([0, address]);
-        let val: u8 = cpu.borrow().get_mem(addr);
-        yield cycle;
-        cycle.next();
-        cpu.borrow_mut().set_mem(addr, val);
-        yield cycle;
-        cycle.next();
-        instruction.execute(&mut cpu.borrow_mut(), addr, val);
-        cycle
-    })
+    state: &mut OpcodeState,
+) -> CycleState<CPUCycle, CPUCycle> {
+    let cycle: CPUCycle = state.next_cycle(instruction.name(), AddressingMode::Zero);
+    match cycle.cycle {
+        0 => {}
+        1 => state.low_byte = cpu.get_and_increment_pc(),
+        2 => state.val = cpu.get_mem(state.addr()),
+        3 => cpu.set_mem(state.addr(), state.val),
+        _ => {
+            instruction.execute(cpu, state.addr(), state.val);
+            return CycleState::Complete(cycle);
+        }
+    }
+    CycleState::Yielded(cycle)
 }
 
 #[cfg(test)]
@@ -84,7 +70,7 @@ mod tests {
     use crate::cpu::variables::a_register::A;
     use crate::state::cpu::{Memory, Registers};
     use crate::state::NES;
-    use std::ops::GeneratorState;
+    use crate::state_machine::CycleState;
 
     #[test]
     fn test_read() {
@@ -93,24 +79,23 @@ mod tests {
         cpu.set_mem(cpu.get_pc(), 0x23);
         cpu.set_mem(0x23, 4);
         cpu.set_a(52);
-        let cpu = RefCell::new(cpu);
         let instruction = ADC;
-        let mut opcode = read(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Zero,
             cycle: 0,
         };
         for _ in 0..2 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_a(), 52);
+            let state = read(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_a(), 52);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_a(), 56);
-        assert_eq!(cpu.borrow().get_pc(), 1);
+        let state = read(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_a(), 56);
+        assert_eq!(cpu.get_pc(), 1);
     }
 
     #[test]
@@ -119,24 +104,23 @@ mod tests {
         cpu.set_pc(0);
         cpu.set_mem(cpu.get_pc(), 0x29);
         cpu.set_mem(0x29, 0b0100_0101);
-        let cpu = RefCell::new(cpu);
         let instruction = ASL;
-        let mut opcode = modify(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Zero,
             cycle: 0,
         };
         for _ in 0..4 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_mem(0x29), 0b0100_0101);
+            let state = modify(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_mem(0x29), 0b0100_0101);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_mem(0x29), 0b1000_1010);
-        assert_eq!(cpu.borrow().get_pc(), 1);
+        let state = modify(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_mem(0x29), 0b1000_1010);
+        assert_eq!(cpu.get_pc(), 1);
     }
 
     #[test]
@@ -146,23 +130,22 @@ mod tests {
         cpu.set_pc(0);
         cpu.set_mem(cpu.get_pc(), 0x10);
         cpu.set_mem(0x10, 0);
-        let cpu = RefCell::new(cpu);
         let instruction = ST(A);
-        let mut opcode = write(&cpu, instruction);
+        let mut opcode = OpcodeState::default();
         let mut cycle = CPUCycle {
             instruction: instruction.name(),
             mode: AddressingMode::Zero,
             cycle: 0,
         };
         for _ in 0..2 {
-            let state = opcode.as_mut().resume(());
-            assert_eq!(state, GeneratorState::Yielded(cycle));
-            assert_eq!(cpu.borrow().get_mem(0x10), 0);
+            let state = write(&mut cpu, instruction, &mut opcode);
+            assert_eq!(state, CycleState::Yielded(cycle));
+            assert_eq!(cpu.get_mem(0x10), 0);
             cycle.next();
         }
-        let state = opcode.as_mut().resume(());
-        assert_eq!(state, GeneratorState::Complete(cycle));
-        assert_eq!(cpu.borrow().get_mem(0x10), 43);
-        assert_eq!(cpu.borrow().get_pc(), 1);
+        let state = write(&mut cpu, instruction, &mut opcode);
+        assert_eq!(state, CycleState::Complete(cycle));
+        assert_eq!(cpu.get_mem(0x10), 43);
+        assert_eq!(cpu.get_pc(), 1);
     }
 }
@@ -4,6 +4,7 @@ pub mod stack_pointer;
 pub mod x_register;
 pub mod y_register;
 
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 use crate::state::CPU;
 
 /// This module contains code representing the 'variables' acted on by instructions.
@@ -46,3 +47,49 @@ pub enum RegisterName {
     S,
     P,
 }
+
+/// Flags, indexed by their bit in the P register
+const FLAGS: [Flag; 8] = [
+    Flag::C,
+    Flag::Z,
+    Flag::I,
+    Flag::D,
+    Flag::B,
+    Flag::U,
+    Flag::V,
+    Flag::N,
+];
+
+impl Snapshot for Flag {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(*self as u8);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        *self = *FLAGS
+            .get(usize::from(reader.read_u8()?))
+            .ok_or(SaveStateError::InvalidData)?;
+        Ok(())
+    }
+}
+
+const REGISTER_NAMES: [RegisterName; 5] = [
+    RegisterName::X,
+    RegisterName::Y,
+    RegisterName::A,
+    RegisterName::S,
+    RegisterName::P,
+];
+
+impl Snapshot for RegisterName {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(*self as u8);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        *self = *REGISTER_NAMES
+            .get(usize::from(reader.read_u8()?))
+            .ok_or(SaveStateError::InvalidData)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,672 @@
+use crate::cpu::{self, InstructionState};
+use crate::movie::{Movie, MovieFrame, MovieStart};
+use crate::ppu::{self, display::Display};
+use crate::rewind::{RewindAudio, RewindBuffer, RewindConfig};
+use crate::state::apu::APU;
+use crate::state::io::{Controller, DataRecorder, Input, InputDevice, Port, Turbo};
+use crate::state::ppu::Cycle;
+use crate::state::snapshot::{
+    self, SaveStateError, SaveStateHeader, Snapshot, StateReader, StateWriter,
+};
+use crate::state::NES;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PPU_CYCLES_PER_CPU_CYCLE: u8 = 3;
+const POST_RENDER_LINE: usize = 240;
+const PRE_RENDER_LINE: usize = 261;
+
+/// A headless NES.
+/// It keeps the CPU, PPU and APU in lockstep and collects their output, so that frontends (and
+/// tests) don't have to drive each component themselves.
+pub struct Emulator {
+    nes: NES,
+    display: Display,
+    audio: Vec<f32>,
+    frame_complete: bool,
+    /// The number of frames run so far
+    frame: u64,
+    input: Input,
+    turbo: Turbo,
+    rewind: Option<RewindBuffer>,
+    /// The number of frames to run ahead of the one shown
+    run_ahead: u32,
+    movie: Option<ActiveMovie>,
+}
+
+/// A movie being recorded or played back
+struct ActiveMovie {
+    movie: Movie,
+    recording: bool,
+    /// The emulator's frame count when the movie's first frame ran
+    start_frame: u64,
+    /// Commands to record with the next frame
+    pending: MovieFrame,
+}
+
+/// The output of a single frame
+pub struct Frame<'a> {
+    /// The pixels of the frame, laid out as described by `Display`
+    pub framebuffer: &'a [u8],
+    /// The audio samples generated during the frame
+    pub audio: &'a [f32],
+}
+
+impl Emulator {
+    /// Boots the NES by jumping to its reset vector
+    pub fn new(mut nes: NES) -> Self {
+        cpu::reset(&mut nes);
+        Emulator {
+            nes,
+            display: Display::new(),
+            audio: Vec::new(),
+            frame_complete: false,
+            frame: 0,
+            input: Input::default(),
+            turbo: Turbo::default(),
+            rewind: None,
+            run_ahead: 0,
+            movie: None,
+        }
+    }
+
+    pub fn nes(&self) -> &NES {
+        &self.nes
+    }
+
+    pub fn nes_mut(&mut self) -> &mut NES {
+        &mut self.nes
+    }
+
+    /// Runs a single CPU cycle, along with the PPU and APU cycles that happen during it
+    pub fn step_cycle(&mut self) -> InstructionState {
+        let state: InstructionState = cpu::cycle(&mut self.nes);
+        for _ in 0..PPU_CYCLES_PER_CPU_CYCLE {
+            let old_scanline: usize = self.nes.get_scanline();
+            if let Some(pixel) = ppu::cycle(&mut self.nes) {
+                self.nes.observe_pixel(&pixel);
+                self.display.set_pixel(pixel);
+            }
+            let scanline: usize = self.nes.get_scanline();
+            if old_scanline != POST_RENDER_LINE && scanline == POST_RENDER_LINE {
+                self.frame_complete = true;
+            }
+            // the PPU gets here whether or not it's rendering
+            if old_scanline != PRE_RENDER_LINE && scanline == PRE_RENDER_LINE {
+                self.nes.start_frame();
+            }
+        }
+        self.nes.apu_cycle();
+        self.nes.devices_cycle();
+        state
+    }
+
+    /// Runs until the current instruction completes
+    pub fn step_instruction(&mut self) {
+        while let InstructionState::Yielded(_) = self.step_cycle() {}
+    }
+
+    /// Runs until the PPU has finished drawing the visible part of a frame
+    pub fn run_frame(&mut self) -> Frame<'_> {
+        self.update_movie();
+        if let Some(rewind) = &mut self.rewind {
+            if rewind.wants_checkpoint(self.frame) {
+                let mut writer = StateWriter::new();
+                self.nes.save(&mut writer);
+                rewind.push(self.frame, writer.into_inner());
+            }
+            rewind.record_input(self.input.with_turbo(self.turbo, self.frame));
+        }
+        self.emulate_frame();
+        if self.run_ahead > 0 {
+            self.show_future_frame();
+        }
+        Frame {
+            framebuffer: self.display.get(),
+            audio: &self.audio,
+        }
+    }
+
+    /// Replaces the frame just drawn with the one `run_ahead` frames later, assuming the input
+    /// doesn't change. Games take a frame or more to react to input, so this hides that lag.
+    /// Only the picture is kept: the NES goes back to where it was, and the audio stays the one of
+    /// the frame that really ran.
+    fn show_future_frame(&mut self) {
+        let mut writer = StateWriter::new();
+        self.nes.save(&mut writer);
+        let state: Vec<u8> = writer.into_inner();
+        let audio: Vec<f32> = std::mem::take(&mut self.audio);
+        let frame: u64 = self.frame;
+        for _ in 0..self.run_ahead {
+            self.emulate_frame();
+        }
+        self.nes
+            .load(&mut StateReader::new(&state))
+            .expect("Could not restore the NES after running ahead!");
+        self.audio = audio;
+        self.frame = frame;
+    }
+
+    /// Sets how many frames `run_frame` runs ahead of the one it shows. 0 disables run-ahead.
+    pub fn set_run_ahead(&mut self, frames: u32) {
+        self.run_ahead = frames;
+    }
+
+    /// Steps back to the frame before the one last shown, replaying it from the nearest snapshot.
+    /// Returns `None` once the history runs out, or if rewinding is disabled.
+    pub fn rewind_frame(&mut self) -> Option<Frame<'_>> {
+        // frame - 1 is the frame being shown, so the one before it is replayed
+        let target: u64 = self.frame.checked_sub(2)?;
+        let rewind: &mut RewindBuffer = self.rewind.as_mut()?;
+        let audio: RewindAudio = rewind.config().audio;
+        let (checkpoint, state, inputs) = rewind.rewind_to(target)?;
+        self.nes
+            .load(&mut StateReader::new(state))
+            .expect("Could not restore a rewind snapshot!");
+        let inputs: Vec<Input> = inputs.to_vec();
+        let input: Input = std::mem::take(&mut self.input);
+        self.frame = checkpoint;
+        for recorded in inputs {
+            self.input = recorded;
+            self.emulate_frame();
+        }
+        // the target frame is the last one replayed, so it's the one that gets shown
+        self.input = input;
+        self.nes.clear_apu_buffer();
+        match audio {
+            RewindAudio::Reversed => self.audio.reverse(),
+            RewindAudio::Silence => self.audio.iter_mut().for_each(|sample| *sample = 0.0),
+        }
+        Some(Frame {
+            framebuffer: self.display.get(),
+            audio: &self.audio,
+        })
+    }
+
+    /// Starts recording history for `rewind_frame`, or stops if `config` is `None`
+    pub fn set_rewind(&mut self, config: Option<RewindConfig>) {
+        self.rewind = config.map(RewindBuffer::new);
+    }
+
+    fn emulate_frame(&mut self) {
+        self.nes
+            .update_input(&self.input.with_turbo(self.turbo, self.frame));
+        self.audio.clear();
+        while !self.frame_complete {
+            self.step_cycle();
+        }
+        self.frame_complete = false;
+        self.frame += 1;
+        self.audio.extend_from_slice(self.nes.get_apu_buffer());
+        self.nes.clear_apu_buffer();
+    }
+
+    /// Sets the input passed to the devices at the start of the next frame
+    pub fn set_input(&mut self, input: Input) {
+        self.input = input;
+    }
+
+    /// Sets how fast turbo buttons fire
+    pub fn set_turbo(&mut self, turbo: Turbo) {
+        self.turbo = turbo;
+    }
+
+    /// Plugs a device into the port, or unplugs it if `device` is `None`.
+    /// Snapshots taken with other devices can't be loaded, so the rewind history is dropped.
+    pub fn set_device(&mut self, port: Port, device: Option<Box<dyn InputDevice>>) {
+        self.nes.set_device(port, device);
+        self.clear_rewind();
+    }
+
+    /// The data recorder, if one is plugged in
+    pub fn data_recorder(&mut self) -> Option<&mut DataRecorder> {
+        self.nes.data_recorder()
+    }
+
+    /// Presses the reset button.
+    /// Resets aren't recorded, so the rewind history before them is dropped.
+    pub fn reset(&mut self) {
+        self.nes.reset();
+        self.clear_rewind();
+        if let Some(active) = &mut self.movie {
+            active.pending.reset = true;
+        }
+    }
+
+    /// Turns the console off and on again
+    pub fn power_cycle(&mut self) {
+        self.nes.power_cycle();
+        self.frame_complete = false;
+        self.clear_rewind();
+        if let Some(active) = &mut self.movie {
+            active.pending.power = true;
+        }
+    }
+
+    /// Records the frame that is about to run, or sets it up from the movie being played back
+    fn update_movie(&mut self) {
+        let active: &mut ActiveMovie = match &mut self.movie {
+            Some(active) => active,
+            None => return,
+        };
+        // rewinding moves the emulator back through the movie
+        let index: usize = (self.frame - active.start_frame) as usize;
+        if active.recording {
+            if active.movie.frames.len() > index {
+                active.movie.frames.truncate(index);
+                active.movie.rerecord_count += 1;
+            }
+            // movies hold the presses turbo buttons make, so they don't depend on the turbo rate
+            let frame = MovieFrame {
+                input: self.input.with_turbo(self.turbo, self.frame),
+                ..std::mem::take(&mut active.pending)
+            };
+            active.movie.frames.push(frame);
+            return;
+        }
+        let frame: MovieFrame = match active.movie.frames.get(index) {
+            Some(frame) => frame.clone(),
+            None => {
+                self.movie = None;
+                return;
+            }
+        };
+        if frame.power {
+            self.power_cycle();
+        } else if frame.reset {
+            self.reset();
+        }
+        self.input = frame.input;
+    }
+
+    /// Starts recording input from the start of the movie, which is either a power cycle or a
+    /// save state
+    pub fn record_movie(&mut self, movie: Movie) -> Result<(), SaveStateError> {
+        self.start_movie(movie, true)
+    }
+
+    /// Plays back a movie from its start. Input set with `set_input` is ignored until it's over.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), SaveStateError> {
+        self.start_movie(movie, false)
+    }
+
+    fn start_movie(&mut self, movie: Movie, recording: bool) -> Result<(), SaveStateError> {
+        self.movie = None;
+        match &movie.start {
+            MovieStart::PowerOn => self.power_cycle(),
+            MovieStart::SaveState(data) => self.load_state(data)?,
+        }
+        self.movie = Some(ActiveMovie {
+            movie,
+            recording,
+            start_frame: self.frame,
+            pending: MovieFrame::default(),
+        });
+        Ok(())
+    }
+
+    /// Stops recording or playing back, and returns the movie
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|active| active.movie)
+    }
+
+    /// Returns true until the movie being played back runs out of frames
+    pub fn is_playing_movie(&self) -> bool {
+        matches!(&self.movie, Some(active) if !active.recording)
+    }
+
+    fn clear_rewind(&mut self) {
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+    }
+
+    /// Serializes the whole machine. The state can be saved at any point, even in the middle of an
+    /// instruction.
+    pub fn save_state(&self) -> Vec<u8> {
+        let header = SaveStateHeader {
+            version: snapshot::VERSION,
+            rom_hash: self.nes.get_rom_hash(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+            thumbnail: self.thumbnail(),
+        };
+        let mut writer = StateWriter::new();
+        header.write(&mut writer);
+        self.nes.save(&mut writer);
+        writer.into_inner()
+    }
+
+    /// Restores a state created by `save_state`.
+    /// The state must come from the same ROM. If it can't be loaded, the NES is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut reader = StateReader::new(data);
+        let header: SaveStateHeader = SaveStateHeader::read(&mut reader)?;
+        let rom_hash: u32 = self.nes.get_rom_hash();
+        if header.rom_hash != rom_hash {
+            return Err(SaveStateError::ROMMismatch {
+                expected: rom_hash,
+                found: header.rom_hash,
+            });
+        }
+
+        let mut backup = StateWriter::new();
+        self.nes.save(&mut backup);
+        let result: Result<(), SaveStateError> =
+            self.nes.load(&mut reader).and_then(|_| reader.finish());
+        if result.is_err() {
+            let backup: Vec<u8> = backup.into_inner();
+            self.nes
+                .load(&mut StateReader::new(&backup))
+                .expect("Could not restore the NES after a failed load!");
+            return result;
+        }
+        self.frame_complete = false;
+        self.clear_rewind();
+        Ok(())
+    }
+
+    /// Downscales the last frame by averaging each 2x2 block of pixels
+    fn thumbnail(&self) -> Vec<u8> {
+        let framebuffer: &[u8] = self.display.get();
+        let mut thumbnail: Vec<u8> = Vec::with_capacity(snapshot::THUMBNAIL_BYTES);
+        for y in 0..snapshot::THUMBNAIL_HEIGHT {
+            for x in 0..snapshot::THUMBNAIL_WIDTH {
+                // pixels are stored as ABGR, and the thumbnail is RGB
+                for channel in (1..Display::BYTES_PER_PIXEL).rev() {
+                    let sum: usize = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .map(|(dx, dy)| {
+                            let index: usize = (2 * x + dx + (2 * y + dy) * Display::WIDTH)
+                                * Display::BYTES_PER_PIXEL;
+                            usize::from(framebuffer[index + channel])
+                        })
+                        .sum();
+                    thumbnail.push((sum / 4) as u8);
+                }
+            }
+        }
+        thumbnail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::{Color, Pixel};
+    use crate::state::cpu::{Memory, Registers};
+    use crate::state::io::{Buttons, Pointer, Zapper};
+
+    #[test]
+    fn test_step_instruction() {
+        let mut emulator = Emulator::new(NES::mock());
+        emulator.nes_mut().set_pc(0);
+        // the mock cartridge is filled with BRK instructions
+        emulator.step_instruction();
+        assert_eq!(emulator.nes().get_pc(), 0);
+        assert_eq!(emulator.nes().get_s(), 0xFD - 3);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut emulator = Emulator::new(NES::mock());
+        emulator.run_frame();
+        assert_eq!(emulator.nes().get_scanline(), POST_RENDER_LINE);
+        let frame = emulator.run_frame();
+        assert_eq!(
+            frame.framebuffer.len(),
+            Display::WIDTH * Display::HEIGHT * 4
+        );
+        // a frame lasts about 29780 CPU cycles, and a sample is output every 40 cycles
+        assert_eq!(frame.audio.len(), 745);
+    }
+
+    #[test]
+    fn test_zapper_without_rendering() {
+        let mut emulator = Emulator::new(NES::mock());
+        emulator.set_device(Port::Two, Some(Box::new(Zapper::new())));
+        emulator.set_input(Input {
+            pointer: Pointer {
+                position: Some((100, 230)),
+                trigger: false,
+            },
+            ..Input::default()
+        });
+        emulator.run_frame();
+        let white = Color {
+            r: 0xFF,
+            g: 0xFF,
+            b: 0xFF,
+        };
+        emulator.nes_mut().observe_pixel(&Pixel {
+            x: 100,
+            y: 230,
+            color: white,
+        });
+        assert_eq!(emulator.nes().get_mem(0x4017) & 0x08, 0);
+        // rendering is off, so no pixels are drawn, but the light from the last frame is gone
+        emulator.run_frame();
+        assert_eq!(emulator.nes().get_mem(0x4017) & 0x08, 0x08);
+    }
+
+    fn save_nes(nes: &NES) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        nes.save(&mut writer);
+        writer.into_inner()
+    }
+
+    #[test]
+    fn test_save_load_state() {
+        let mut emulator = Emulator::new(NES::mock());
+        emulator.run_frame();
+        // stop in the middle of an instruction
+        emulator.step_cycle();
+        let state: Vec<u8> = emulator.save_state();
+        emulator.run_frame();
+        emulator.run_frame();
+        let expected: Vec<u8> = save_nes(emulator.nes());
+
+        emulator.load_state(&state).unwrap();
+        emulator.run_frame();
+        emulator.run_frame();
+        assert_eq!(save_nes(emulator.nes()), expected);
+    }
+
+    #[test]
+    fn test_rewind_frame() {
+        let mut emulator = Emulator::new(NES::mock());
+        emulator.set_rewind(Some(RewindConfig {
+            interval: 3,
+            ..RewindConfig::default()
+        }));
+        let mut states: Vec<Vec<u8>> = Vec::new();
+        for frame in 0..8 {
+            emulator.set_input(Input {
+                buttons: [
+                    Buttons {
+                        start: frame % 3 == 0,
+                        ..Buttons::default()
+                    },
+                    Buttons {
+                        a: frame % 2 == 0,
+                        ..Buttons::default()
+                    },
+                    Buttons::default(),
+                    Buttons::default(),
+                ],
+                ..Input::default()
+            });
+            emulator.run_frame();
+            states.push(save_nes(emulator.nes()));
+        }
+        for frame in (0..7).rev() {
+            let audio: usize = emulator.rewind_frame().unwrap().audio.len();
+            assert!(audio > 0);
+            assert_eq!(save_nes(emulator.nes()), states[frame]);
+        }
+        assert!(emulator.rewind_frame().is_none());
+
+        // playing again after rewinding records a new history
+        emulator.run_frame();
+        emulator.run_frame();
+        emulator.rewind_frame().unwrap();
+        assert_eq!(emulator.frame, 2);
+    }
+
+    #[test]
+    fn test_run_ahead() {
+        let mut emulator = Emulator::new(NES::mock());
+        let mut expected = Emulator::new(NES::mock());
+        emulator.set_run_ahead(2);
+        let audio: Vec<f32> = emulator.run_frame().audio.to_vec();
+        assert_eq!(expected.run_frame().audio, &audio[..]);
+        assert_eq!(save_nes(emulator.nes()), save_nes(expected.nes()));
+        assert_eq!(emulator.frame, 1);
+
+        let framebuffer: Vec<u8> = emulator.run_frame().framebuffer.to_vec();
+        expected.run_frame();
+        let state: Vec<u8> = save_nes(expected.nes());
+        expected.run_frame();
+        assert_eq!(expected.run_frame().framebuffer, &framebuffer[..]);
+        assert_eq!(save_nes(emulator.nes()), state);
+    }
+
+    fn run_frames(emulator: &mut Emulator, frames: u8) {
+        for frame in 0..frames {
+            emulator.set_input(Input {
+                buttons: [
+                    Buttons {
+                        a: frame % 2 == 0,
+                        up: frame % 5 == 0,
+                        ..Buttons::default()
+                    },
+                    Buttons {
+                        select: frame % 3 == 0,
+                        ..Buttons::default()
+                    },
+                    Buttons::default(),
+                    Buttons::default(),
+                ],
+                ..Input::default()
+            });
+            emulator.run_frame();
+        }
+    }
+
+    #[test]
+    fn test_movie() {
+        let mut emulator = Emulator::new(NES::mock());
+        run_frames(&mut emulator, 3);
+        emulator
+            .record_movie(Movie::new("mock.nes", MovieStart::PowerOn))
+            .unwrap();
+        run_frames(&mut emulator, 4);
+        emulator.reset();
+        run_frames(&mut emulator, 3);
+        emulator.power_cycle();
+        run_frames(&mut emulator, 2);
+        let expected: Vec<u8> = save_nes(emulator.nes());
+        let movie: Movie = emulator.stop_movie().unwrap();
+        assert_eq!(movie.frames.len(), 9);
+        assert!(movie.frames[4].reset);
+        assert!(movie.frames[7].power);
+
+        let mut emulator = Emulator::new(NES::mock());
+        run_frames(&mut emulator, 5);
+        emulator.play_movie(movie).unwrap();
+        for _ in 0..9 {
+            assert!(emulator.is_playing_movie());
+            emulator.set_input(Input::default());
+            emulator.run_frame();
+        }
+        assert_eq!(save_nes(emulator.nes()), expected);
+        emulator.run_frame();
+        assert!(!emulator.is_playing_movie());
+    }
+
+    #[test]
+    fn test_turbo() {
+        let mut emulator = Emulator::new(NES::mock());
+        emulator.set_turbo(Turbo { a: 1, b: 3 });
+        emulator
+            .record_movie(Movie::new("mock.nes", MovieStart::PowerOn))
+            .unwrap();
+        let mut input = Input::default();
+        input.buttons[0].turbo_a = true;
+        input.buttons[0].turbo_b = true;
+        input.buttons[0].b = true;
+        input.buttons[1].turbo_b = true;
+        emulator.set_input(input);
+        for _ in 0..6 {
+            emulator.run_frame();
+        }
+        let movie: Movie = emulator.stop_movie().unwrap();
+        let pressed = |player: usize| -> Vec<(bool, bool)> {
+            let buttons = movie.frames.iter().map(|frame| frame.input.buttons[player]);
+            buttons.map(|buttons| (buttons.a, buttons.b)).collect()
+        };
+        let (on, off) = (true, false);
+        assert_eq!(
+            pressed(0),
+            vec![
+                (on, on),
+                (off, on),
+                (on, on),
+                (off, on),
+                (on, on),
+                (off, on)
+            ]
+        );
+        assert_eq!(
+            pressed(1),
+            vec![
+                (off, on),
+                (off, on),
+                (off, on),
+                (off, off),
+                (off, off),
+                (off, off)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_movie_from_save_state() {
+        let mut emulator = Emulator::new(NES::mock());
+        run_frames(&mut emulator, 2);
+        emulator.step_cycle();
+        let state: Vec<u8> = emulator.save_state();
+        emulator
+            .record_movie(Movie::new("mock.nes", MovieStart::SaveState(state)))
+            .unwrap();
+        run_frames(&mut emulator, 4);
+        let expected: Vec<u8> = save_nes(emulator.nes());
+        let movie: Movie = emulator.stop_movie().unwrap();
+
+        let mut emulator = Emulator::new(NES::mock());
+        emulator.play_movie(movie).unwrap();
+        for _ in 0..4 {
+            emulator.run_frame();
+        }
+        assert_eq!(save_nes(emulator.nes()), expected);
+    }
+
+    #[test]
+    fn test_load_invalid_state() {
+        let mut emulator = Emulator::new(NES::mock());
+        emulator.run_frame();
+        let state: Vec<u8> = emulator.save_state();
+        emulator.run_frame();
+        let before: Vec<u8> = save_nes(emulator.nes());
+
+        assert_eq!(
+            emulator.load_state(b"garbage"),
+            Err(SaveStateError::NotASaveState)
+        );
+        assert_eq!(
+            emulator.load_state(&state[..state.len() - 1]),
+            Err(SaveStateError::UnexpectedEOF)
+        );
+        assert_eq!(save_nes(emulator.nes()), before);
+    }
+}
@@ -1,6 +1,10 @@
-#![feature(generators, generator_trait)]
 pub mod bitops;
 pub mod cartridge;
 pub mod cpu;
+pub mod emulator;
+pub mod movie;
 pub mod ppu;
+pub mod rewind;
 pub mod state;
+pub mod state_machine;
+pub mod tape;
@@ -0,0 +1,382 @@
+use crate::state::io::{Buttons, Input};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Input movies, in the text `.fm2` format used by FCEUX.
+/// A movie is the input of every frame, along with the state the NES started in. Playing it back
+/// from the same state reproduces the run exactly.
+///
+/// FCEUX identifies the ROM with an MD5 checksum, which isn't computed here, so movies are
+/// written without one and checksums are ignored when reading them. Movies that start from a save
+/// state embed one of our save states, which FCEUX can't load.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_filename: String,
+    pub guid: String,
+    /// The number of times part of the movie was rewound and recorded again
+    pub rerecord_count: u32,
+    pub comments: Vec<String>,
+    pub start: MovieStart,
+    pub frames: Vec<MovieFrame>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MovieStart {
+    /// The console is power cycled before the first frame
+    PowerOn,
+    /// A state created by `Emulator::save_state` is loaded before the first frame
+    SaveState(Vec<u8>),
+}
+
+/// The input of a single frame
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MovieFrame {
+    /// The reset button is pressed before the frame
+    pub reset: bool,
+    /// The console is power cycled before the frame
+    pub power: bool,
+    pub input: Input,
+}
+
+const FM2_VERSION: u32 = 3;
+
+/// Bits of the command field of an input line
+const COMMAND_RESET: u8 = 1;
+const COMMAND_POWER: u8 = 2;
+
+/// The order buttons are written in an input line
+const BUTTON_LETTERS: &[u8; 8] = b"RLDUTSBA";
+
+impl Movie {
+    pub fn new(rom_filename: &str, start: MovieStart) -> Self {
+        Movie {
+            rom_filename: rom_filename.to_string(),
+            guid: new_guid(),
+            rerecord_count: 0,
+            comments: Vec::new(),
+            start,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Parses a text `.fm2` file
+    pub fn from_fm2(text: &str) -> Result<Self, MovieError> {
+        let mut movie = Movie::new("", MovieStart::PowerOn);
+        movie.guid.clear();
+        for (index, line) in text.lines().enumerate() {
+            let line_number: usize = index + 1;
+            let line: &str = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                movie.frames.push(parse_frame(line, line_number)?);
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (key, value): (&str, &str) = match line.find(' ') {
+                Some(space) => (&line[..space], &line[space + 1..]),
+                None => (line, ""),
+            };
+            let invalid = || MovieError::InvalidLine(line_number);
+            match key {
+                "version" if value != FM2_VERSION.to_string() => {
+                    return Err(MovieError::Unsupported(format!("version {}", value)));
+                }
+                "binary" if value != "0" => {
+                    return Err(MovieError::Unsupported("binary input".to_string()));
+                }
+                "palFlag" if value != "0" => {
+                    return Err(MovieError::Unsupported("PAL".to_string()));
+                }
+                "fourscore" if value != "0" => {
+                    return Err(MovieError::Unsupported("Four Score".to_string()));
+                }
+                // only standard controllers (1) and empty ports (0) are supported
+                "port0" | "port1" if value != "0" && value != "1" => {
+                    return Err(MovieError::Unsupported(format!("{} device {}", key, value)));
+                }
+                "rerecordCount" => movie.rerecord_count = value.parse().map_err(|_| invalid())?,
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "guid" => movie.guid = value.to_string(),
+                "comment" => movie.comments.push(value.to_string()),
+                "savestate" => {
+                    let data: &str = value.strip_prefix("base64:").ok_or_else(invalid)?;
+                    movie.start = MovieStart::SaveState(decode_base64(data).ok_or_else(invalid)?);
+                }
+                _ => {}
+            }
+        }
+        Ok(movie)
+    }
+
+    /// Writes the movie as a text `.fm2` file
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::new();
+        text += &format!("version {}\n", FM2_VERSION);
+        text += "emuVersion 0\n";
+        text += &format!("rerecordCount {}\n", self.rerecord_count);
+        text += "palFlag 0\n";
+        text += &format!("romFilename {}\n", self.rom_filename);
+        text += &format!("guid {}\n", self.guid);
+        text += "fourscore 0\n";
+        text += "microphone 0\n";
+        text += "port0 1\n";
+        text += "port1 1\n";
+        text += "port2 0\n";
+        text += "FDS 0\n";
+        text += "NewPPU 0\n";
+        for comment in &self.comments {
+            text += &format!("comment {}\n", comment);
+        }
+        if let MovieStart::SaveState(data) = &self.start {
+            text += &format!("savestate base64:{}\n", encode_base64(data));
+        }
+        for frame in &self.frames {
+            let commands: u8 = match (frame.reset, frame.power) {
+                (_, true) => COMMAND_POWER,
+                (true, false) => COMMAND_RESET,
+                (false, false) => 0,
+            };
+            text += &format!(
+                "|{}|{}|{}||\n",
+                commands,
+                write_buttons(frame.input.buttons[0]),
+                write_buttons(frame.input.buttons[1])
+            );
+        }
+        text
+    }
+}
+
+/// Parses an input line, like `|0|R......A|........||`
+fn parse_frame(line: &str, line_number: usize) -> Result<MovieFrame, MovieError> {
+    let invalid = || MovieError::InvalidLine(line_number);
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 3 {
+        return Err(invalid());
+    }
+    let commands: u8 = fields[1].trim().parse().map_err(|_| invalid())?;
+    let mut input = Input::default();
+    for (port, field) in fields[2..].iter().take(2).enumerate() {
+        // the field is empty if nothing is plugged into the port
+        if !field.is_empty() {
+            input.buttons[port] = parse_buttons(field).ok_or_else(invalid)?;
+        }
+    }
+    Ok(MovieFrame {
+        reset: commands & COMMAND_RESET != 0,
+        power: commands & COMMAND_POWER != 0,
+        input,
+    })
+}
+
+fn parse_buttons(field: &str) -> Option<Buttons> {
+    let field: &[u8] = field.as_bytes();
+    if field.len() != BUTTON_LETTERS.len() {
+        return None;
+    }
+    // anything but a blank or a dot counts as pressed
+    let pressed = |i: usize| field[i] != b'.' && field[i] != b' ';
+    Some(Buttons {
+        right: pressed(0),
+        left: pressed(1),
+        down: pressed(2),
+        up: pressed(3),
+        start: pressed(4),
+        select: pressed(5),
+        b: pressed(6),
+        a: pressed(7),
+        ..Buttons::default()
+    })
+}
+
+fn write_buttons(buttons: Buttons) -> String {
+    let pressed: [bool; 8] = [
+        buttons.right,
+        buttons.left,
+        buttons.down,
+        buttons.up,
+        buttons.start,
+        buttons.select,
+        buttons.b,
+        buttons.a,
+    ];
+    pressed
+        .iter()
+        .zip(BUTTON_LETTERS.iter())
+        .map(|(&pressed, &letter)| if pressed { letter as char } else { '.' })
+        .collect()
+}
+
+/// GUIDs only need to tell movies apart, so one made from the current time is good enough
+fn new_guid() -> String {
+    let nanos: u128 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos());
+    let hex: String = format!(
+        "{:032X}",
+        nanos.wrapping_mul(0x9E37_79B9_7F4A_7C15_F39C_C060_5CED_C835)
+    );
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes: [u8; 3] = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits: u32 = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text: &[u8] = text.trim_end_matches('=').as_bytes();
+    let mut data: Vec<u8> = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut bit_count: u32 = 0;
+    for &c in text {
+        let value: usize = BASE64_ALPHABET.iter().position(|&letter| letter == c)?;
+        bits = bits << 6 | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            data.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(data)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MovieError {
+    InvalidLine(usize),
+    /// The movie uses a feature this emulator doesn't support
+    Unsupported(String),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::InvalidLine(line) => write!(f, "invalid movie line {}", line),
+            MovieError::Unsupported(feature) => write!(f, "unsupported movie feature: {}", feature),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        for len in 0..8 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 73 + 5) as u8).collect();
+            assert_eq!(decode_base64(&encode_base64(&data)), Some(data));
+        }
+        assert_eq!(encode_base64(b"NES"), "TkVT");
+        assert_eq!(encode_base64(b"NES!"), "TkVTIQ==");
+        assert_eq!(decode_base64("not base64!"), None);
+    }
+
+    #[test]
+    fn test_fm2() {
+        let mut movie = Movie::new("game.nes", MovieStart::SaveState(vec![1, 2, 3, 4]));
+        movie.rerecord_count = 3;
+        movie.comments.push("author someone".to_string());
+        movie.frames = vec![
+            MovieFrame {
+                power: true,
+                ..MovieFrame::default()
+            },
+            MovieFrame {
+                input: Input {
+                    buttons: [
+                        Buttons {
+                            a: true,
+                            right: true,
+                            ..Buttons::default()
+                        },
+                        Buttons {
+                            down: true,
+                            ..Buttons::default()
+                        },
+                        Buttons::default(),
+                        Buttons::default(),
+                    ],
+                    ..Input::default()
+                },
+                ..MovieFrame::default()
+            },
+            MovieFrame {
+                reset: true,
+                input: Input {
+                    buttons: [
+                        Buttons {
+                            start: true,
+                            ..Buttons::default()
+                        },
+                        Buttons::default(),
+                        Buttons::default(),
+                        Buttons::default(),
+                    ],
+                    ..Input::default()
+                },
+                ..MovieFrame::default()
+            },
+        ];
+        let text: String = movie.to_fm2();
+        assert!(text.contains(
+            "\n|2|........|........||\n|0|R......A|..D.....||\n|1|....T...|........||\n"
+        ));
+        assert_eq!(Movie::from_fm2(&text), Ok(movie));
+    }
+
+    #[test]
+    fn test_fceux_fm2() {
+        let text = "version 3\nemuVersion 20604\nport0 1\nport1 1\n\
+                    romChecksum base64:AAAAAAAAAAAAAAAAAAAAAA==\n\
+                    |0|.....S..|........||\n|0|U  D    |......B.||\n";
+        let movie: Movie = Movie::from_fm2(text).unwrap();
+        assert_eq!(movie.start, MovieStart::PowerOn);
+        assert_eq!(movie.frames.len(), 2);
+        assert!(movie.frames[0].input.buttons[0].select);
+        assert!(movie.frames[1].input.buttons[0].right && movie.frames[1].input.buttons[0].up);
+        assert!(!movie.frames[1].input.buttons[0].left);
+        assert!(movie.frames[1].input.buttons[1].b);
+
+        let single_controller: Movie = Movie::from_fm2("version 3\n|0|.......A|||\n").unwrap();
+        assert_eq!(
+            single_controller.frames[0].input.buttons[1],
+            Buttons::default()
+        );
+
+        assert_eq!(
+            Movie::from_fm2("version 3\nfourscore 1\n"),
+            Err(MovieError::Unsupported("Four Score".to_string()))
+        );
+        assert_eq!(
+            Movie::from_fm2("version 3\n|x|........|||\n"),
+            Err(MovieError::InvalidLine(2))
+        );
+    }
+}
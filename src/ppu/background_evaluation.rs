@@ -1,35 +1,71 @@
 use crate::state::ppu::{Background, Memory};
-use std::cell::RefCell;
-use std::ops::Generator;
-
-pub fn evaluate_background<'a, T: Background + Memory>(
-    ppu: &'a RefCell<T>,
-) -> impl Generator<Yield = (), Return = BackgroundTile> + 'a {
-    move || {
-        let nametable_addr: u16 = ppu.borrow().get_nametable_addr();
-        yield;
-        let nametable: u8 = ppu.borrow().get(nametable_addr);
-        yield;
-        let attribute_addr: u16 = ppu.borrow().get_attribute_addr();
-        yield;
-        let attribute: u8 =
-            (ppu.borrow().get(attribute_addr) >> ppu.borrow().get_attribute_shift()) & 0b11;
-        yield;
-        let tile_addr_low: u16 = ppu.borrow().get_background_tile_addr_low(nametable);
-        yield;
-        let pattern_low: u8 = ppu.borrow().get(tile_addr_low);
-        yield;
-        let tile_addr_high: u16 = ppu.borrow().get_background_tile_addr_high(nametable);
-        yield;
-        let pattern_high: u8 = ppu.borrow().get(tile_addr_high);
-        BackgroundTile {
-            attribute,
-            pattern_high,
-            pattern_low,
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::state_machine::CycleState;
+
+/// The number of cycles it takes to fetch a tile
+const FETCH_CYCLES: u8 = 8;
+
+/// The progress of a background tile fetch
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct BackgroundEvaluation {
+    /// The cycle that runs next
+    cycle: u8,
+    /// The address that is read on the next cycle
+    addr: u16,
+    nametable: u8,
+    attribute: u8,
+    pattern_low: u8,
+}
+
+impl Snapshot for BackgroundEvaluation {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.cycle);
+        writer.write_u16(self.addr);
+        writer.write_u8(self.nametable);
+        writer.write_u8(self.attribute);
+        writer.write_u8(self.pattern_low);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.cycle = reader.read_u8()?;
+        if self.cycle >= FETCH_CYCLES {
+            return Err(SaveStateError::InvalidData);
         }
+        self.addr = reader.read_u16()?;
+        self.nametable = reader.read_u8()?;
+        self.attribute = reader.read_u8()?;
+        self.pattern_low = reader.read_u8()?;
+        Ok(())
     }
 }
 
+/// Runs a cycle of a background tile fetch. Each of the four reads takes two cycles: one to set
+/// up the address, and one to read it.
+pub fn evaluate_background<T: Background + Memory>(
+    ppu: &T,
+    state: &mut BackgroundEvaluation,
+) -> CycleState<(), BackgroundTile> {
+    let cycle: u8 = state.cycle;
+    state.cycle += 1;
+    match cycle {
+        0 => state.addr = ppu.get_nametable_addr(),
+        1 => state.nametable = ppu.get(state.addr),
+        2 => state.addr = ppu.get_attribute_addr(),
+        3 => state.attribute = (ppu.get(state.addr) >> ppu.get_attribute_shift()) & 0b11,
+        4 => state.addr = ppu.get_background_tile_addr_low(state.nametable),
+        5 => state.pattern_low = ppu.get(state.addr),
+        6 => state.addr = ppu.get_background_tile_addr_high(state.nametable),
+        _ => {
+            return CycleState::Complete(BackgroundTile {
+                attribute: state.attribute,
+                pattern_high: ppu.get(state.addr),
+                pattern_low: state.pattern_low,
+            })
+        }
+    }
+    CycleState::Yielded(())
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct BackgroundTile {
     pub attribute: u8,
@@ -44,7 +80,17 @@ mod tests {
 
     #[test]
     fn test_evaluate_background_cycles() {
-        let nes = RefCell::new(NES::mock());
-        let mut background_generator = evaluate_background(&nes);
+        let nes = NES::mock();
+        let mut state = BackgroundEvaluation::default();
+        for _ in 0..7 {
+            assert_eq!(
+                evaluate_background(&nes, &mut state),
+                CycleState::Yielded(())
+            );
+        }
+        assert!(matches!(
+            evaluate_background(&nes, &mut state),
+            CycleState::Complete(_)
+        ));
     }
 }
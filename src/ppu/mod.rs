@@ -1,117 +1,163 @@
-mod background_evaluation;
+pub mod background_evaluation;
 pub mod display;
 mod palette;
-mod pipeline;
-mod sprite_evaluation;
+pub mod pipeline;
+pub mod sprite_evaluation;
 
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 use crate::state::PPU;
-use background_evaluation::evaluate_background;
-use palette::NES_COLORS;
-use pipeline::Pipeline;
-use sprite_evaluation::evaluate_sprites;
-use std::cell::RefCell;
-use std::ops::{Generator, GeneratorState};
-use std::pin::Pin;
-
-/// This function decides what happens on each cycle, yielding pixels along the way
-pub fn cycle<'a, T: PPU>(
-    ppu: &'a RefCell<T>,
-) -> impl Generator<Yield = Option<Pixel>, Return = ()> + 'a {
-    move || {
-        let mut pipeline = Pipeline::new();
-        let mut background_generator = evaluate_background(ppu);
-        let mut sprite_generator = evaluate_sprites(ppu);
-        loop {
-            let scanline: usize = ppu.borrow().get_scanline();
-            let tick: usize = ppu.borrow().get_tick();
-            let background_enabled: bool = ppu.borrow().should_render_background();
-            let sprites_enabled: bool = ppu.borrow().should_render_sprites();
-
-            // the zero tick is always idle - nothing happens other than a cycle update
-            if tick == 0 {
-                ppu.borrow_mut().update_cycle();
-                yield None;
-                continue;
-            }
+use crate::state_machine::CycleState;
+use background_evaluation::{evaluate_background, BackgroundEvaluation};
+use palette::get_color;
+use sprite_evaluation::{evaluate_sprites, SpriteEvaluation};
+
+/// The PPU's progress through the current dot, and through the fetches that span several dots.
+/// It is kept in the NES between dots, so that the PPU can be inspected and saved at any point.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RenderState {
+    /// Set once the current dot's pixel has been output, holding whether background and sprite
+    /// rendering were enabled at the time. The rest of the dot runs on the next call.
+    dot_in_progress: Option<(bool, bool)>,
+    background: BackgroundEvaluation,
+    sprites: SpriteEvaluation,
+}
 
-            let mut pixel: Option<Pixel> = None;
-            if should_output_pixel(scanline, tick) && (background_enabled || sprites_enabled) {
-                let fine_x: u8 = ppu.borrow().get_fine_x();
-                if let Some((addr, sprite0)) = pipeline.get_next_palette_addr(fine_x) {
-                    let color: Color = NES_COLORS[usize::from(ppu.borrow().get(addr))];
-                    if sprite0 && background_enabled && sprites_enabled && tick != 256 {
-                        ppu.borrow_mut().trigger_sprite_zero();
-                    }
-                    pixel = Some(Pixel {
-                        x: tick - 1,
-                        y: scanline,
-                        color,
-                    });
-                }
-            }
-            yield pixel;
+impl Snapshot for RenderState {
+    fn save(&self, writer: &mut StateWriter) {
+        let (background_enabled, sprites_enabled) = self.dot_in_progress.unwrap_or_default();
+        writer.write_bool(self.dot_in_progress.is_some());
+        writer.write_bool(background_enabled);
+        writer.write_bool(sprites_enabled);
+        self.background.save(writer);
+        self.sprites.save(writer);
+    }
 
-            if should_reload_background(scanline, tick) {
-                background_generator = evaluate_background(ppu);
-            }
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        let dot_in_progress: bool = reader.read_bool()?;
+        let background_enabled: bool = reader.read_bool()?;
+        let sprites_enabled: bool = reader.read_bool()?;
+        self.dot_in_progress = if dot_in_progress {
+            Some((background_enabled, sprites_enabled))
+        } else {
+            None
+        };
+        self.background.load(reader)?;
+        self.sprites.load(reader)
+    }
+}
 
-            if should_reload_sprites(scanline, tick) {
-                sprite_generator = evaluate_sprites(ppu);
-            }
+/// This function decides what happens on each cycle, returning a pixel if one is output.
+/// A dot's pixel is output first, and the rest of the dot runs at the start of the next call.
+pub fn cycle<T: PPU>(ppu: &mut T) -> Option<Pixel> {
+    let mut state: RenderState = ppu.take_render_state();
+    if let Some((background_enabled, sprites_enabled)) = state.dot_in_progress.take() {
+        finish_dot(ppu, &mut state, background_enabled, sprites_enabled);
+    }
 
-            if should_run_background(scanline, tick) {
-                pipeline.advance_background();
-            }
+    let tick: usize = ppu.get_tick();
+    let background_enabled: bool = ppu.should_render_background();
+    let sprites_enabled: bool = ppu.should_render_sprites();
+    // the zero tick is always idle - nothing happens other than a cycle update
+    let pixel: Option<Pixel> = if tick == 0 {
+        ppu.update_cycle();
+        None
+    } else {
+        state.dot_in_progress = Some((background_enabled, sprites_enabled));
+        output_pixel(ppu, background_enabled, sprites_enabled)
+    };
+    ppu.set_render_state(state);
+    pixel
+}
 
-            if should_run_background(scanline, tick) && background_enabled {
-                match Pin::new(&mut background_generator).resume(()) {
-                    GeneratorState::Complete(tile) => {
-                        pipeline.load_background_tile(tile);
-                        ppu.borrow_mut().increment_x();
-                        background_generator = evaluate_background(ppu);
-                    }
-                    _ => {}
-                }
-            }
+/// Runs everything in a dot after its pixel has been output
+fn finish_dot<T: PPU>(
+    ppu: &mut T,
+    state: &mut RenderState,
+    background_enabled: bool,
+    sprites_enabled: bool,
+) {
+    let scanline: usize = ppu.get_scanline();
+    let tick: usize = ppu.get_tick();
+
+    if should_reload_background(scanline, tick) {
+        state.background = BackgroundEvaluation::default();
+    }
 
-            if should_run_sprites(scanline, tick) {
-                pipeline.advance_sprites();
-            }
+    if should_reload_sprites(scanline, tick) {
+        state.sprites = SpriteEvaluation::default();
+    }
 
-            if should_run_sprites(scanline, tick) && (background_enabled || sprites_enabled) {
-                match Pin::new(&mut sprite_generator).resume(()) {
-                    GeneratorState::Complete(sprites) => {
-                        pipeline.load_sprites(sprites);
-                    }
-                    _ => {}
-                }
-            }
+    if should_run_background(scanline, tick) {
+        ppu.get_pipeline_mut().advance_background();
+    }
 
-            if should_increment_y(scanline, tick) && (background_enabled || sprites_enabled) {
-                ppu.borrow_mut().increment_y();
-            }
+    if should_run_background(scanline, tick) && background_enabled {
+        if let CycleState::Complete(tile) = evaluate_background(ppu, &mut state.background) {
+            ppu.get_pipeline_mut().load_background_tile(tile);
+            ppu.increment_x();
+            state.background = BackgroundEvaluation::default();
+        }
+    }
 
-            if should_reset_x(scanline, tick) && (background_enabled || sprites_enabled) {
-                ppu.borrow_mut().reset_x();
-            }
+    if should_run_sprites(scanline, tick) {
+        ppu.get_pipeline_mut().advance_sprites();
+    }
 
-            if should_reset_y(scanline, tick) && (background_enabled || sprites_enabled) {
-                ppu.borrow_mut().reset_y();
-            }
+    if should_run_sprites(scanline, tick) && (background_enabled || sprites_enabled) {
+        if let CycleState::Complete(sprites) = evaluate_sprites(ppu, &mut state.sprites) {
+            ppu.get_pipeline_mut().load_sprites(sprites);
+        }
+    }
 
-            if should_set_vblank(scanline, tick) {
-                ppu.borrow_mut().start_vblank();
-            }
+    if should_increment_y(scanline, tick) && (background_enabled || sprites_enabled) {
+        ppu.increment_y();
+    }
 
-            if should_clear_flags(scanline, tick) {
-                ppu.borrow_mut().end_vlbank();
-                ppu.borrow_mut().clear_sprite_zero();
-                ppu.borrow_mut().clear_sprite_overflow();
-            }
+    if should_reset_x(scanline, tick) && (background_enabled || sprites_enabled) {
+        ppu.reset_x();
+    }
 
-            ppu.borrow_mut().update_cycle();
+    if should_reset_y(scanline, tick) && (background_enabled || sprites_enabled) {
+        ppu.reset_y();
+    }
+
+    if should_set_vblank(scanline, tick) {
+        ppu.start_vblank();
+    }
+
+    if should_clear_flags(scanline, tick) {
+        ppu.end_vlbank();
+        ppu.clear_sprite_zero();
+        ppu.clear_sprite_overflow();
+    }
+
+    ppu.update_cycle();
+}
+
+/// Outputs the pixel for the current dot, if there is one
+fn output_pixel<T: PPU>(
+    ppu: &mut T,
+    background_enabled: bool,
+    sprites_enabled: bool,
+) -> Option<Pixel> {
+    let scanline: usize = ppu.get_scanline();
+    let tick: usize = ppu.get_tick();
+    let mut pixel: Option<Pixel> = None;
+    if should_output_pixel(scanline, tick) && (background_enabled || sprites_enabled) {
+        let fine_x: u8 = ppu.get_fine_x();
+        if let Some((addr, sprite0)) = ppu.get_pipeline().get_next_palette_addr(fine_x) {
+            let color: Color = get_color(ppu.get(addr), ppu.is_greyscale(), ppu.get_emphasis());
+            if sprite0 && background_enabled && sprites_enabled && tick != 256 {
+                ppu.trigger_sprite_zero();
+            }
+            pixel = Some(Pixel {
+                x: tick - 1,
+                y: scanline,
+                color,
+            });
         }
     }
+    pixel
 }
 
 fn on_render_line(scanline: usize) -> bool {
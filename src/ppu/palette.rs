@@ -1,8 +1,12 @@
 use super::Color;
+use crate::bitops::BitOps;
 
 pub const PALETTE_BACKGROUND_BASE: u16 = 0x3F00;
 pub const PALETTE_SPRITE_BASE: u16 = 0x3F10;
 
+/// Emphasizing a color dims its other two components to about this many 256ths
+const EMPHASIS_ATTENUATION: u16 = 209;
+
 /// The NES color lookup table. This is hardcoded for now.
 pub const NES_COLORS: [Color; 0x40] = [
     Color {
@@ -246,3 +250,63 @@ pub const NES_COLORS: [Color; 0x40] = [
     Color { r: 0, g: 0, b: 0 },
     Color { r: 0, g: 0, b: 0 },
 ];
+
+/// The color of a palette entry (0 to 0x3F), after PPUMASK's greyscale and emphasis bits are
+/// applied. Each of the 64 colors can be emphasized in 8 ways, for 512 colors in all.
+pub fn get_color(palette_entry: u8, greyscale: bool, emphasis: u8) -> Color {
+    // greyscale keeps only the brightness, leaving the grey in the first column
+    let palette_entry: u8 = if greyscale {
+        palette_entry & 0x30
+    } else {
+        palette_entry
+    };
+    let mut color: Color = NES_COLORS[usize::from(palette_entry)];
+    let dim = |component: &mut u8| {
+        *component = (u16::from(*component) * EMPHASIS_ATTENUATION / 256) as u8;
+    };
+    if emphasis.is_bit_set(0) {
+        dim(&mut color.g);
+        dim(&mut color.b);
+    }
+    if emphasis.is_bit_set(1) {
+        dim(&mut color.r);
+        dim(&mut color.b);
+    }
+    if emphasis.is_bit_set(2) {
+        dim(&mut color.r);
+        dim(&mut color.g);
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_color() {
+        assert_eq!(get_color(0x21, false, 0), NES_COLORS[0x21]);
+        assert_eq!(get_color(0x21, true, 0), NES_COLORS[0x20]);
+        assert_eq!(get_color(0x0F, true, 0), NES_COLORS[0x00]);
+
+        let white: Color = NES_COLORS[0x30];
+        let dim = |component: u8| (u16::from(component) * EMPHASIS_ATTENUATION / 256) as u8;
+        assert_eq!(
+            get_color(0x30, false, 0b001),
+            Color {
+                r: white.r,
+                g: dim(white.g),
+                b: dim(white.b),
+            }
+        );
+        // each component is dimmed by every other color's emphasis
+        assert_eq!(
+            get_color(0x30, false, 0b110),
+            Color {
+                r: dim(dim(white.r)),
+                g: dim(white.g),
+                b: dim(white.b),
+            }
+        );
+    }
+}
@@ -2,6 +2,7 @@ use super::background_evaluation::BackgroundTile;
 use super::palette::PALETTE_BACKGROUND_BASE;
 use super::sprite_evaluation::Sprite;
 use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 
 pub struct Pipeline {
     sprites: Option<Vec<Sprite>>,
@@ -111,6 +112,68 @@ impl Pipeline {
     }
 }
 
+impl Snapshot for Pipeline {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.sprites.is_some());
+        if let Some(sprites) = &self.sprites {
+            writer.write_usize(sprites.len());
+            for sprite in sprites {
+                sprite.save(writer);
+            }
+        }
+        save_option(writer, self.background_shift_high, StateWriter::write_u16);
+        save_option(writer, self.background_shift_low, StateWriter::write_u16);
+        save_option(
+            writer,
+            self.background_attribute_current,
+            StateWriter::write_u8,
+        );
+        save_option(
+            writer,
+            self.background_attribute_next,
+            StateWriter::write_u8,
+        );
+        writer.write_u8(self.background_shift_count);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.sprites = if reader.read_bool()? {
+            let len: usize = reader.read_usize()?;
+            // secondary OAM only holds 8 sprites
+            if len > 8 {
+                return Err(SaveStateError::InvalidData);
+            }
+            let mut sprites: Vec<Sprite> = vec![Sprite::default(); len];
+            for sprite in &mut sprites {
+                sprite.load(reader)?;
+            }
+            Some(sprites)
+        } else {
+            None
+        };
+        self.background_shift_high = load_option(reader, StateReader::read_u16)?;
+        self.background_shift_low = load_option(reader, StateReader::read_u16)?;
+        self.background_attribute_current = load_option(reader, StateReader::read_u8)?;
+        self.background_attribute_next = load_option(reader, StateReader::read_u8)?;
+        self.background_shift_count = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+fn save_option<T: Default>(writer: &mut StateWriter, val: Option<T>, f: fn(&mut StateWriter, T)) {
+    writer.write_bool(val.is_some());
+    f(writer, val.unwrap_or_default());
+}
+
+fn load_option<'a, T>(
+    reader: &mut StateReader<'a>,
+    f: fn(&mut StateReader<'a>) -> Result<T, SaveStateError>,
+) -> Result<Option<T>, SaveStateError> {
+    let is_some: bool = reader.read_bool()?;
+    let val: T = f(reader)?;
+    Ok(if is_some { Some(val) } else { None })
+}
+
 fn map2<T, U, V, F: Fn(T, U) -> V>(a: Option<T>, b: Option<U>, f: F) -> Option<V> {
     match a {
         Some(x) => match b {
@@ -2,138 +2,394 @@ use super::palette::PALETTE_SPRITE_BASE;
 use crate::bitops::BitOps;
 use crate::state::ppu::oam::OAM;
 use crate::state::ppu::{Cycle, Memory, Sprites};
-use std::cell::RefCell;
-use std::ops::Generator;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::state_machine::CycleState;
 
 const SECONDARY_OAM_SIZE: usize = 32;
+const MAX_SPRITES: usize = 8;
 
-pub fn evaluate_sprites<'a, T: Cycle + Sprites + Memory>(
-    ppu: &'a RefCell<T>,
-) -> impl Generator<Yield = (), Return = Vec<Sprite>> + 'a {
-    move || {
-        let mut secondary_oam: [u8; SECONDARY_OAM_SIZE] = [0xFF; SECONDARY_OAM_SIZE];
-        let mut cycle_count: u32 = 0;
-
-        // stall for the first 64 cycles
-        for _ in 0..64 {
-            yield;
-            cycle_count += 1;
+/// The progress of sprite evaluation, which runs over a whole scanline
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteEvaluation {
+    step: SpriteEvaluationStep,
+    secondary_oam: [u8; SECONDARY_OAM_SIZE],
+    cycle_count: u32,
+    secondary_oam_index: usize,
+    sprite_count: usize,
+    sprite_index: u8,
+    attribute_index: u8,
+    /// The OAM byte read on the previous cycle, which is copied to secondary OAM on the next one
+    oam_byte: u8,
+    /// Whether the sprite whose Y coordinate was just read is on the scanline
+    in_range: bool,
+    /// The secondary OAM entry being fetched
+    fetch_index: usize,
+    y_coordinate: u8,
+    tile_index: u8,
+    y_offset: u8,
+    tile_addr: u16,
+    sprite: Sprite,
+    sprites: Vec<Sprite>,
+}
+
+/// What sprite evaluation does on its next cycle
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SpriteEvaluationStep {
+    /// Stall for the first 64 cycles
+    Clear,
+    /// Read a sprite's Y coordinate, or move on once secondary OAM is full
+    Evaluate,
+    CopyY,
+    CheckY,
+    CopyTile,
+    ReadAttributes,
+    CopyAttributes,
+    ReadX,
+    CopyX,
+    NextSprite,
+    /// Look for sprite overflow in the rest of OAM
+    Overflow,
+    CheckOverflow,
+    /// Stall until cycle 256
+    WaitForFetch,
+    /// Read the next sprite's Y coordinate from secondary OAM, if there is one
+    Fetch,
+    FetchTile,
+    FetchAttributes,
+    FetchX,
+    FetchLowAddr,
+    FetchLow,
+    FetchHighAddr,
+    FetchHigh,
+    NextFetch,
+    /// Stall until cycle 340
+    WaitForEnd,
+    Done,
+}
+
+const SPRITE_EVALUATION_STEPS: [SpriteEvaluationStep; 24] = [
+    SpriteEvaluationStep::Clear,
+    SpriteEvaluationStep::Evaluate,
+    SpriteEvaluationStep::CopyY,
+    SpriteEvaluationStep::CheckY,
+    SpriteEvaluationStep::CopyTile,
+    SpriteEvaluationStep::ReadAttributes,
+    SpriteEvaluationStep::CopyAttributes,
+    SpriteEvaluationStep::ReadX,
+    SpriteEvaluationStep::CopyX,
+    SpriteEvaluationStep::NextSprite,
+    SpriteEvaluationStep::Overflow,
+    SpriteEvaluationStep::CheckOverflow,
+    SpriteEvaluationStep::WaitForFetch,
+    SpriteEvaluationStep::Fetch,
+    SpriteEvaluationStep::FetchTile,
+    SpriteEvaluationStep::FetchAttributes,
+    SpriteEvaluationStep::FetchX,
+    SpriteEvaluationStep::FetchLowAddr,
+    SpriteEvaluationStep::FetchLow,
+    SpriteEvaluationStep::FetchHighAddr,
+    SpriteEvaluationStep::FetchHigh,
+    SpriteEvaluationStep::NextFetch,
+    SpriteEvaluationStep::WaitForEnd,
+    SpriteEvaluationStep::Done,
+];
+
+impl Default for SpriteEvaluation {
+    fn default() -> Self {
+        SpriteEvaluation {
+            step: SpriteEvaluationStep::Clear,
+            secondary_oam: [0xFF; SECONDARY_OAM_SIZE],
+            cycle_count: 0,
+            secondary_oam_index: 0,
+            sprite_count: 0,
+            sprite_index: 0,
+            attribute_index: 0,
+            oam_byte: 0,
+            in_range: false,
+            fetch_index: 0,
+            y_coordinate: 0,
+            tile_index: 0,
+            y_offset: 0,
+            tile_addr: 0,
+            sprite: Sprite::default(),
+            sprites: Vec::new(),
         }
-        let mut secondary_oam_index: usize = 0;
-        let mut sprite_count: usize = 0;
-        let mut sprite_index: u8 = 0;
+    }
+}
 
-        // read at most 8 sprites into secondary OAM
-        while (0..OAM::SPRITE_COUNT).contains(&sprite_index)
-            && (0..SECONDARY_OAM_SIZE).contains(&secondary_oam_index)
+impl Snapshot for SpriteEvaluation {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.step as u8);
+        writer.write_bytes(&self.secondary_oam);
+        writer.write_u32(self.cycle_count);
+        writer.write_usize(self.secondary_oam_index);
+        writer.write_usize(self.sprite_count);
+        writer.write_u8(self.sprite_index);
+        writer.write_u8(self.attribute_index);
+        writer.write_u8(self.oam_byte);
+        writer.write_bool(self.in_range);
+        writer.write_usize(self.fetch_index);
+        writer.write_u8(self.y_coordinate);
+        writer.write_u8(self.tile_index);
+        writer.write_u8(self.y_offset);
+        writer.write_u16(self.tile_addr);
+        self.sprite.save(writer);
+        writer.write_usize(self.sprites.len());
+        for sprite in &self.sprites {
+            sprite.save(writer);
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.step = *SPRITE_EVALUATION_STEPS
+            .get(usize::from(reader.read_u8()?))
+            .ok_or(SaveStateError::InvalidData)?;
+        reader.read_bytes(&mut self.secondary_oam)?;
+        self.cycle_count = reader.read_u32()?;
+        self.secondary_oam_index = reader.read_usize()?;
+        self.sprite_count = reader.read_usize()?;
+        self.sprite_index = reader.read_u8()?;
+        self.attribute_index = reader.read_u8()?;
+        self.oam_byte = reader.read_u8()?;
+        self.in_range = reader.read_bool()?;
+        self.fetch_index = reader.read_usize()?;
+        self.y_coordinate = reader.read_u8()?;
+        self.tile_index = reader.read_u8()?;
+        self.y_offset = reader.read_u8()?;
+        self.tile_addr = reader.read_u16()?;
+        self.sprite.load(reader)?;
+        let len: usize = reader.read_usize()?;
+        // OAM and secondary OAM are indexed with these, so they have to stay within bounds
+        if self.secondary_oam_index > SECONDARY_OAM_SIZE
+            || !self
+                .secondary_oam_index
+                .is_multiple_of(usize::from(OAM::BYTES_PER_SPRITE))
+            || self.sprite_index > OAM::SPRITE_COUNT
+            || self.sprite_count > MAX_SPRITES
+            || self.fetch_index > self.sprite_count
+            || self.attribute_index >= OAM::BYTES_PER_SPRITE
+            || len > MAX_SPRITES
         {
-            let y_coordinate: u8 = ppu.borrow().read_oam(sprite_index, 0);
-            yield;
-            let sprite_range = usize::from(y_coordinate)
-                ..(usize::from(y_coordinate) + usize::from(ppu.borrow().get_sprite_height()));
-            secondary_oam[secondary_oam_index] = y_coordinate;
-            yield;
-            cycle_count += 2;
-            if sprite_range.contains(&ppu.borrow().get_scanline()) {
-                let tile_index: u8 = ppu.borrow().read_oam(sprite_index, 1);
-                yield;
-                secondary_oam[secondary_oam_index + 1] = tile_index;
-                yield;
-                let attributes: u8 = ppu.borrow().read_oam(sprite_index, 2);
-                yield;
-                secondary_oam[secondary_oam_index + 2] = attributes;
-                yield;
-                let x_coordinate: u8 = ppu.borrow().read_oam(sprite_index, 3);
-                yield;
-                secondary_oam[secondary_oam_index + 3] = x_coordinate;
-                yield;
-                secondary_oam_index += usize::from(OAM::BYTES_PER_SPRITE);
-                sprite_count += 1;
-                cycle_count += 6;
-            }
-            sprite_index += 1;
+            return Err(SaveStateError::InvalidData);
+        }
+        self.sprites = vec![Sprite::default(); len];
+        for sprite in &mut self.sprites {
+            sprite.load(reader)?;
         }
+        Ok(())
+    }
+}
 
-        // check for sprite overflow
-        let mut attribute_index: u8 = 0;
-        while (0..OAM::SPRITE_COUNT).contains(&sprite_index) {
-            let y_coordinate: u8 = ppu.borrow().read_oam(sprite_index, attribute_index);
-            yield;
-            cycle_count += 1;
-            let sprite_range = usize::from(y_coordinate)
-                ..(usize::from(y_coordinate) + usize::from(ppu.borrow().get_sprite_height()));
-            if sprite_range.contains(&ppu.borrow().get_scanline()) {
-                ppu.borrow_mut().trigger_sprite_overflow();
-                // the next three entries of OAM are read, but the results are discarded
-                // so we only include the attribute_index and sprite_index increments
-                for _ in 0..3 {
-                    if attribute_index == 3 {
-                        sprite_index += 1;
-                    }
-                    attribute_index = (attribute_index + 1) % 4;
+/// Runs a cycle of sprite evaluation. Once the whole scanline has been evaluated, the sprites
+/// found on the next one are returned.
+pub fn evaluate_sprites<T: Cycle + Sprites + Memory>(
+    ppu: &mut T,
+    state: &mut SpriteEvaluation,
+) -> CycleState<(), Vec<Sprite>> {
+    loop {
+        state.step = match state.step {
+            SpriteEvaluationStep::Clear => {
+                if state.cycle_count < 64 {
+                    state.cycle_count += 1;
+                    return CycleState::Yielded(());
                 }
-            } else {
-                attribute_index = (attribute_index + 1) % 4;
-                sprite_index += 1;
+                state.secondary_oam_index = 0;
+                state.sprite_count = 0;
+                state.sprite_index = 0;
+                SpriteEvaluationStep::Evaluate
             }
-        }
 
-        // stall until cycle 256
-        while cycle_count < 255 {
-            yield;
-            cycle_count += 1;
-        }
+            // read at most 8 sprites into secondary OAM
+            SpriteEvaluationStep::Evaluate => {
+                if (0..OAM::SPRITE_COUNT).contains(&state.sprite_index)
+                    && (0..SECONDARY_OAM_SIZE).contains(&state.secondary_oam_index)
+                {
+                    state.oam_byte = ppu.read_oam(state.sprite_index, 0);
+                    state.step = SpriteEvaluationStep::CopyY;
+                    return CycleState::Yielded(());
+                }
+                state.attribute_index = 0;
+                SpriteEvaluationStep::Overflow
+            }
+            SpriteEvaluationStep::CopyY => {
+                state.in_range = is_on_scanline(ppu, state.oam_byte);
+                state.secondary_oam[state.secondary_oam_index] = state.oam_byte;
+                state.step = SpriteEvaluationStep::CheckY;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::CheckY => {
+                state.cycle_count += 2;
+                if state.in_range {
+                    state.oam_byte = ppu.read_oam(state.sprite_index, 1);
+                    state.step = SpriteEvaluationStep::CopyTile;
+                    return CycleState::Yielded(());
+                }
+                state.sprite_index += 1;
+                SpriteEvaluationStep::Evaluate
+            }
+            SpriteEvaluationStep::CopyTile => {
+                state.secondary_oam[state.secondary_oam_index + 1] = state.oam_byte;
+                state.step = SpriteEvaluationStep::ReadAttributes;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::ReadAttributes => {
+                state.oam_byte = ppu.read_oam(state.sprite_index, 2);
+                state.step = SpriteEvaluationStep::CopyAttributes;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::CopyAttributes => {
+                state.secondary_oam[state.secondary_oam_index + 2] = state.oam_byte;
+                state.step = SpriteEvaluationStep::ReadX;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::ReadX => {
+                state.oam_byte = ppu.read_oam(state.sprite_index, 3);
+                state.step = SpriteEvaluationStep::CopyX;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::CopyX => {
+                state.secondary_oam[state.secondary_oam_index + 3] = state.oam_byte;
+                state.step = SpriteEvaluationStep::NextSprite;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::NextSprite => {
+                state.secondary_oam_index += usize::from(OAM::BYTES_PER_SPRITE);
+                state.sprite_count += 1;
+                state.cycle_count += 6;
+                state.sprite_index += 1;
+                SpriteEvaluationStep::Evaluate
+            }
 
-        // fetch sprite data
-        let mut result: Vec<Sprite> = Vec::new();
-        for i in 0..sprite_count {
-            let y_coordinate: u8 = secondary_oam[i * usize::from(OAM::BYTES_PER_SPRITE)];
-            yield;
-            let tile_index: u8 = secondary_oam[i * usize::from(OAM::BYTES_PER_SPRITE) + 1];
-            yield;
-            let attributes: u8 = secondary_oam[i * usize::from(OAM::BYTES_PER_SPRITE) + 2];
-            let vertical_flip: bool = attributes.is_bit_set(7);
-            let horizontal_flip: bool = attributes.is_bit_set(6);
-            yield;
-            let x_coordinate: u8 = secondary_oam[i * usize::from(OAM::BYTES_PER_SPRITE) + 3];
-            yield;
-            let mut y_offset: u8 = ppu.borrow().get_scanline() as u8 - y_coordinate;
-            if vertical_flip {
-                y_offset = u8::from(ppu.borrow().get_sprite_height()) - 1 - y_offset;
-            }
-            let low_tile_addr: u16 = ppu.borrow().get_sprite_tile_addr_low(tile_index, y_offset);
-            yield;
-            let mut pattern_low: u8 = ppu.borrow().get(low_tile_addr);
-            if horizontal_flip {
-                pattern_low = pattern_low.reverse_bits();
-            }
-            yield;
-            let high_tile_addr: u16 = ppu.borrow().get_sprite_tile_addr_high(tile_index, y_offset);
-            yield;
-            let mut pattern_high: u8 = ppu.borrow().get(high_tile_addr);
-            if horizontal_flip {
-                pattern_high = pattern_high.reverse_bits();
-            }
-            yield;
-            cycle_count += 8;
-            result.push(Sprite {
-                x_coordinate,
-                attributes,
-                pattern_low,
-                pattern_high,
-                ..Default::default()
-            })
-        }
+            // look for a ninth sprite on the scanline. The PPU increments the byte index along
+            // with the sprite index, so it reads the other bytes of sprites as Y coordinates,
+            // and the overflow flag can be set by sprites that aren't on the scanline, or missed
+            // by ones that are.
+            SpriteEvaluationStep::Overflow => {
+                if (0..OAM::SPRITE_COUNT).contains(&state.sprite_index) {
+                    state.oam_byte = ppu.read_oam(state.sprite_index, state.attribute_index);
+                    state.step = SpriteEvaluationStep::CheckOverflow;
+                    return CycleState::Yielded(());
+                }
+                SpriteEvaluationStep::WaitForFetch
+            }
+            SpriteEvaluationStep::CheckOverflow => {
+                // secondary OAM is full, so the write on this cycle is a read instead
+                state.cycle_count += 2;
+                if is_on_scanline(ppu, state.oam_byte) {
+                    ppu.trigger_sprite_overflow();
+                    // the rest of the sprite is read, and then OAM is only read without being
+                    // checked, so nothing else happens until the fetches
+                    state.step = SpriteEvaluationStep::WaitForFetch;
+                    return CycleState::Yielded(());
+                }
+                state.sprite_index += 1;
+                state.attribute_index = (state.attribute_index + 1) % OAM::BYTES_PER_SPRITE;
+                state.step = SpriteEvaluationStep::Overflow;
+                return CycleState::Yielded(());
+            }
+
+            // stall until cycle 256
+            SpriteEvaluationStep::WaitForFetch => {
+                if state.cycle_count < 255 {
+                    state.cycle_count += 1;
+                    return CycleState::Yielded(());
+                }
+                state.sprites.clear();
+                state.fetch_index = 0;
+                SpriteEvaluationStep::Fetch
+            }
+
+            // fetch sprite data
+            SpriteEvaluationStep::Fetch => {
+                if state.fetch_index < state.sprite_count {
+                    state.y_coordinate = read_secondary_oam(state, 0);
+                    state.step = SpriteEvaluationStep::FetchTile;
+                    return CycleState::Yielded(());
+                }
+                SpriteEvaluationStep::WaitForEnd
+            }
+            SpriteEvaluationStep::FetchTile => {
+                state.tile_index = read_secondary_oam(state, 1);
+                state.step = SpriteEvaluationStep::FetchAttributes;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::FetchAttributes => {
+                state.sprite = Sprite {
+                    attributes: read_secondary_oam(state, 2),
+                    ..Default::default()
+                };
+                state.step = SpriteEvaluationStep::FetchX;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::FetchX => {
+                state.sprite.x_coordinate = read_secondary_oam(state, 3);
+                state.step = SpriteEvaluationStep::FetchLowAddr;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::FetchLowAddr => {
+                state.y_offset = ppu.get_scanline() as u8 - state.y_coordinate;
+                if state.sprite.is_vertically_flipped() {
+                    state.y_offset = u8::from(ppu.get_sprite_height()) - 1 - state.y_offset;
+                }
+                state.tile_addr = ppu.get_sprite_tile_addr_low(state.tile_index, state.y_offset);
+                state.step = SpriteEvaluationStep::FetchLow;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::FetchLow => {
+                state.sprite.pattern_low = ppu.get(state.tile_addr);
+                if state.sprite.is_horizontally_flipped() {
+                    state.sprite.pattern_low = state.sprite.pattern_low.reverse_bits();
+                }
+                state.step = SpriteEvaluationStep::FetchHighAddr;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::FetchHighAddr => {
+                state.tile_addr = ppu.get_sprite_tile_addr_high(state.tile_index, state.y_offset);
+                state.step = SpriteEvaluationStep::FetchHigh;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::FetchHigh => {
+                state.sprite.pattern_high = ppu.get(state.tile_addr);
+                if state.sprite.is_horizontally_flipped() {
+                    state.sprite.pattern_high = state.sprite.pattern_high.reverse_bits();
+                }
+                state.step = SpriteEvaluationStep::NextFetch;
+                return CycleState::Yielded(());
+            }
+            SpriteEvaluationStep::NextFetch => {
+                state.cycle_count += 8;
+                state.sprites.push(state.sprite);
+                state.fetch_index += 1;
+                SpriteEvaluationStep::Fetch
+            }
 
-        // stall until cycle 340
-        while cycle_count < 339 {
-            yield;
-            cycle_count += 1;
+            // stall until cycle 340
+            SpriteEvaluationStep::WaitForEnd => {
+                if state.cycle_count < 339 {
+                    state.cycle_count += 1;
+                    return CycleState::Yielded(());
+                }
+                state.step = SpriteEvaluationStep::Done;
+                return CycleState::Complete(std::mem::take(&mut state.sprites));
+            }
+            // evaluation is restarted on the next scanline
+            SpriteEvaluationStep::Done => return CycleState::Yielded(()),
         }
-        result
     }
 }
 
+/// Whether a sprite with the given Y coordinate is on the current scanline
+fn is_on_scanline<T: Cycle + Sprites>(ppu: &T, y_coordinate: u8) -> bool {
+    let sprite_range = usize::from(y_coordinate)
+        ..(usize::from(y_coordinate) + usize::from(ppu.get_sprite_height()));
+    sprite_range.contains(&ppu.get_scanline())
+}
+
+/// Reads a byte of the secondary OAM entry being fetched
+fn read_secondary_oam(state: &SpriteEvaluation, offset: usize) -> u8 {
+    state.secondary_oam[state.fetch_index * usize::from(OAM::BYTES_PER_SPRITE) + offset]
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Default)]
 pub struct Sprite {
     x_coordinate: u8,
@@ -143,11 +399,38 @@ pub struct Sprite {
     shift_count: u8,
 }
 
+impl Snapshot for Sprite {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.x_coordinate);
+        writer.write_u8(self.attributes);
+        writer.write_u8(self.pattern_low);
+        writer.write_u8(self.pattern_high);
+        writer.write_u8(self.shift_count);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.x_coordinate = reader.read_u8()?;
+        self.attributes = reader.read_u8()?;
+        self.pattern_low = reader.read_u8()?;
+        self.pattern_high = reader.read_u8()?;
+        self.shift_count = reader.read_u8()?;
+        Ok(())
+    }
+}
+
 impl Sprite {
     fn get_palette_index(&self) -> u8 {
         ((self.pattern_high >> 7) << 1) | (self.pattern_low >> 7)
     }
 
+    fn is_vertically_flipped(&self) -> bool {
+        self.attributes.is_bit_set(7)
+    }
+
+    fn is_horizontally_flipped(&self) -> bool {
+        self.attributes.is_bit_set(6)
+    }
+
     pub fn is_front_priority(&self) -> bool {
         !self.attributes.is_bit_set(5)
     }
@@ -176,3 +459,77 @@ impl Sprite {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ppu::{DebugRegisters, MappedRegisters};
+    use crate::state::NES;
+
+    const SCANLINE: u8 = 100;
+
+    /// Fills OAM with sprites at the given Y coordinates, with the other bytes set to `other`
+    fn nes_with_sprites(y_coordinates: &[u8], other: u8) -> NES {
+        let mut nes = NES::mock();
+        // OAM can only be written outside of rendering
+        while nes.get_scanline() != 241 {
+            nes.update_cycle();
+        }
+        nes.set_oam_addr(0);
+        for sprite in 0..usize::from(OAM::SPRITE_COUNT) {
+            nes.set_oam_data(*y_coordinates.get(sprite).unwrap_or(&0xFF));
+            for _ in 1..OAM::BYTES_PER_SPRITE {
+                nes.set_oam_data(other);
+            }
+        }
+        while nes.get_scanline() != usize::from(SCANLINE) {
+            nes.update_cycle();
+        }
+        nes
+    }
+
+    /// Evaluates a scanline, returning the cycle the overflow flag was set on
+    fn overflow_cycle(nes: &mut NES) -> Option<usize> {
+        let mut state = SpriteEvaluation::default();
+        let mut overflow_cycle: Option<usize> = None;
+        for cycle in 1..=340 {
+            if let CycleState::Complete(_) = evaluate_sprites(nes, &mut state) {
+                break;
+            }
+            if overflow_cycle.is_none() && nes.get_2002().is_bit_set(5) {
+                overflow_cycle = Some(cycle);
+            }
+        }
+        overflow_cycle
+    }
+
+    #[test]
+    fn test_sprite_overflow() {
+        let mut nes = nes_with_sprites(&[SCANLINE; 8], 0xFF);
+        assert_eq!(overflow_cycle(&mut nes), None);
+
+        let mut nes = nes_with_sprites(&[SCANLINE; 9], 0xFF);
+        assert_eq!(overflow_cycle(&mut nes), Some(130));
+
+        // sprites 8 to 11 are each checked with a different byte, so sprite 12 is the next one
+        // checked with its Y coordinate, two cycles per sprite later
+        let mut y_coordinates: Vec<u8> = vec![SCANLINE; 8];
+        y_coordinates.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, SCANLINE]);
+        let mut nes = nes_with_sprites(&y_coordinates, 0xFF);
+        assert_eq!(overflow_cycle(&mut nes), Some(138));
+    }
+
+    #[test]
+    fn test_sprite_overflow_diagonal_scan() {
+        // sprites 9 to 11 are on the scanline, but their tile, attributes and X coordinate are
+        // checked instead of their Y coordinate
+        let mut y_coordinates: Vec<u8> = vec![SCANLINE; 8];
+        y_coordinates.extend_from_slice(&[0xFF, SCANLINE, SCANLINE, SCANLINE]);
+        let mut nes = nes_with_sprites(&y_coordinates, 0xFF);
+        assert_eq!(overflow_cycle(&mut nes), None);
+
+        // sprite 9's tile index looks like it's on the scanline
+        let mut nes = nes_with_sprites(&[SCANLINE; 8], SCANLINE);
+        assert_eq!(overflow_cycle(&mut nes), Some(132));
+    }
+}
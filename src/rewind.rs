@@ -0,0 +1,317 @@
+use crate::state::io::Input;
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+
+/// What to play while rewinding
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RewindAudio {
+    /// Each frame's audio, played backwards
+    Reversed,
+    Silence,
+}
+
+impl FromStr for RewindAudio {
+    type Err = ParseRewindAudioError;
+
+    /// Parses `reversed` or `silence`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reversed" => Ok(RewindAudio::Reversed),
+            "silence" => Ok(RewindAudio::Silence),
+            _ => Err(ParseRewindAudioError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseRewindAudioError(String);
+
+impl fmt::Display for ParseRewindAudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid rewind audio '{}' (expected reversed or silence)",
+            self.0
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RewindConfig {
+    /// Number of frames between snapshots. Frames in between are replayed from the recorded input
+    /// when rewinding, so larger intervals use less memory but more CPU time.
+    pub interval: u32,
+    /// The oldest snapshots are dropped when the buffer grows past this many bytes
+    pub memory_limit: usize,
+    pub audio: RewindAudio,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        RewindConfig {
+            interval: 4,
+            memory_limit: 64 * 1024 * 1024,
+            audio: RewindAudio::Reversed,
+        }
+    }
+}
+
+/// A snapshot of the NES at the start of a frame, along with the input of every frame recorded
+/// since then
+struct Checkpoint {
+    frame: u64,
+    /// The newest checkpoint holds a full snapshot. The others hold the difference between their
+    /// snapshot and the next one, which is usually small.
+    state: Vec<u8>,
+    inputs: Vec<Input>,
+}
+
+impl Checkpoint {
+    fn size(&self) -> usize {
+        self.state.len() + self.inputs.len() * mem::size_of::<Input>()
+    }
+}
+
+/// A ring buffer of the NES's recent history
+pub struct RewindBuffer {
+    config: RewindConfig,
+    checkpoints: VecDeque<Checkpoint>,
+    size: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(config: RewindConfig) -> Self {
+        RewindBuffer {
+            config,
+            checkpoints: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    pub fn config(&self) -> RewindConfig {
+        self.config
+    }
+
+    /// Returns true if a snapshot should be taken at the start of the frame
+    pub fn wants_checkpoint(&self, frame: u64) -> bool {
+        frame.is_multiple_of(u64::from(self.config.interval.max(1)))
+    }
+
+    /// Adds a snapshot taken at the start of the frame, dropping the oldest ones if the buffer is
+    /// over its memory limit
+    pub fn push(&mut self, frame: u64, state: Vec<u8>) {
+        if let Some(newest) = self.checkpoints.back_mut() {
+            let delta: Vec<u8> = encode_delta(&state, &newest.state);
+            self.size -= newest.state.len();
+            self.size += delta.len();
+            newest.state = delta;
+        }
+        let checkpoint = Checkpoint {
+            frame,
+            state,
+            inputs: Vec::new(),
+        };
+        self.size += checkpoint.size();
+        self.checkpoints.push_back(checkpoint);
+        // the newest checkpoint is always kept, even if it doesn't fit on its own
+        while self.size > self.config.memory_limit && self.checkpoints.len() > 1 {
+            if let Some(oldest) = self.checkpoints.pop_front() {
+                self.size -= oldest.size();
+            }
+        }
+    }
+
+    /// Records the input of the frame that is about to run
+    pub fn record_input(&mut self, input: Input) {
+        if let Some(newest) = self.checkpoints.back_mut() {
+            newest.inputs.push(input);
+            self.size += mem::size_of::<Input>();
+        }
+    }
+
+    /// Forgets everything recorded after the end of `frame`. Returns the newest remaining
+    /// snapshot, and the input needed to replay it up to that point.
+    /// Nothing is forgotten if the history doesn't go back that far.
+    pub fn rewind_to(&mut self, frame: u64) -> Option<(u64, &[u8], &[Input])> {
+        if self.checkpoints.front()?.frame > frame {
+            return None;
+        }
+        while self.checkpoints.back()?.frame > frame {
+            if let Some(newest) = self.checkpoints.pop_back() {
+                self.size -= newest.size();
+                let previous: &mut Checkpoint = self.checkpoints.back_mut()?;
+                let state: Vec<u8> = decode_delta(&newest.state, &previous.state);
+                self.size -= previous.state.len();
+                self.size += state.len();
+                previous.state = state;
+            }
+        }
+        let newest: &mut Checkpoint = self.checkpoints.back_mut()?;
+        let replayed: usize = (frame - newest.frame) as usize + 1;
+        if newest.inputs.len() > replayed {
+            self.size -= (newest.inputs.len() - replayed) * mem::size_of::<Input>();
+            newest.inputs.truncate(replayed);
+        }
+        Some((newest.frame, &newest.state, &newest.inputs))
+    }
+
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.size = 0;
+    }
+
+    /// The number of bytes used by the snapshots and input
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Encodes `state` as runs of bytes copied from `reference`, followed by runs of literal bytes.
+/// Consecutive snapshots mostly differ in a few scattered bytes, so this is much smaller than the
+/// snapshot itself.
+fn encode_delta(reference: &[u8], state: &[u8]) -> Vec<u8> {
+    let mut delta: Vec<u8> = Vec::new();
+    write_varint(&mut delta, state.len());
+    let is_same = |i: usize| reference.get(i) == Some(&state[i]);
+    let mut i: usize = 0;
+    while i < state.len() {
+        let same_start: usize = i;
+        while i < state.len() && is_same(i) {
+            i += 1;
+        }
+        let literal_start: usize = i;
+        while i < state.len() && !is_same(i) {
+            i += 1;
+        }
+        write_varint(&mut delta, literal_start - same_start);
+        write_varint(&mut delta, i - literal_start);
+        delta.extend_from_slice(&state[literal_start..i]);
+    }
+    delta
+}
+
+/// Reverses `encode_delta`
+fn decode_delta(reference: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut delta: &[u8] = delta;
+    let len: usize = read_varint(&mut delta);
+    let mut state: Vec<u8> = Vec::with_capacity(len);
+    while state.len() < len {
+        let same: usize = read_varint(&mut delta);
+        let literal: usize = read_varint(&mut delta);
+        let start: usize = state.len();
+        state.extend_from_slice(&reference[start..start + same]);
+        state.extend_from_slice(&delta[..literal]);
+        delta = &delta[literal..];
+    }
+    state
+}
+
+/// Writes 7 bits at a time, with the top bit set on every byte but the last
+fn write_varint(data: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        data.push((val as u8) | 0x80);
+        val >>= 7;
+    }
+    data.push(val as u8);
+}
+
+fn read_varint(data: &mut &[u8]) -> usize {
+    let mut val: usize = 0;
+    let mut shift: u32 = 0;
+    loop {
+        let byte: u8 = data[0];
+        *data = &data[1..];
+        val |= usize::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return val;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::io::Buttons;
+
+    #[test]
+    fn test_delta() {
+        let reference: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut state: Vec<u8> = reference.clone();
+        state[0] = 0xFF;
+        state[500] = 0;
+        state[501] = 0;
+        state.extend_from_slice(&[1, 2, 3]);
+        let delta: Vec<u8> = encode_delta(&reference, &state);
+        assert!(delta.len() < 20);
+        assert_eq!(decode_delta(&reference, &delta), state);
+
+        let shorter: Vec<u8> = reference[..10].to_vec();
+        assert_eq!(
+            decode_delta(&reference, &encode_delta(&reference, &shorter)),
+            shorter
+        );
+        assert_eq!(
+            decode_delta(&shorter, &encode_delta(&shorter, &reference)),
+            reference
+        );
+    }
+
+    fn push_frames(buffer: &mut RewindBuffer, frames: std::ops::Range<u64>) {
+        for frame in frames {
+            if buffer.wants_checkpoint(frame) {
+                buffer.push(frame, vec![frame as u8; 100]);
+            }
+            let buttons = Buttons {
+                a: frame % 2 == 0,
+                ..Buttons::default()
+            };
+            buffer.record_input(Input {
+                buttons: [
+                    buttons,
+                    Buttons::default(),
+                    Buttons::default(),
+                    Buttons::default(),
+                ],
+                ..Input::default()
+            });
+        }
+    }
+
+    #[test]
+    fn test_rewind_to() {
+        let mut buffer = RewindBuffer::new(RewindConfig::default());
+        push_frames(&mut buffer, 0..10);
+        let (frame, state, inputs) = buffer.rewind_to(6).unwrap();
+        assert_eq!(frame, 4);
+        assert_eq!(state, &[4; 100][..]);
+        assert_eq!(inputs.len(), 3);
+        assert!(inputs[0].buttons[0].a);
+        assert!(!inputs[1].buttons[0].a);
+        assert!(inputs[2].buttons[0].a);
+
+        let (frame, state, inputs) = buffer.rewind_to(3).unwrap();
+        assert_eq!(frame, 0);
+        assert_eq!(state, &[0; 100][..]);
+        assert_eq!(inputs.len(), 4);
+        assert_eq!(buffer.size(), 100 + 4 * mem::size_of::<Input>());
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        // every snapshot differs completely, so only about two fit
+        let memory_limit: usize = 2 * (110 + mem::size_of::<Input>());
+        let mut buffer = RewindBuffer::new(RewindConfig {
+            interval: 1,
+            memory_limit,
+            audio: RewindAudio::Silence,
+        });
+        push_frames(&mut buffer, 0..100);
+        assert!(buffer.size() <= memory_limit);
+        assert!(buffer.rewind_to(90).is_none());
+        let (frame, _, _) = buffer.rewind_to(98).unwrap();
+        assert_eq!(frame, 98);
+    }
+}
@@ -1,6 +1,4 @@
 use super::APU;
-use crate::state::cpu::Interrupt;
-use crate::state::cpu::Memory;
 use crate::state::NES;
 
 impl<'a> APU<'a> for NES {
@@ -33,12 +31,6 @@ impl<'a> APU<'a> for NES {
         self.apu.triangle.clock();
         self.apu.dmc.clock();
 
-        // check for DMA
-        if self.apu.dmc.is_dma_active() {
-            let val = self.get_mem(self.apu.dmc.cur_addr);
-            self.apu.dmc.load_buffer(val);
-        }
-
         // quarter frame
         if self.apu.frame_counter.is_quarter_frame() {
             self.apu.half_frame();
@@ -48,12 +40,5 @@ impl<'a> APU<'a> for NES {
         if self.apu.frame_counter.is_half_frame() {
             self.apu.half_frame();
         }
-
-        // check for interrupt
-        if self.apu.frame_counter.irq_triggered || self.apu.dmc.irq_triggered {
-            self.trigger_irq();
-            self.apu.frame_counter.irq_triggered = false;
-            self.apu.dmc.irq_triggered = false;
-        }
     }
 }
@@ -1,5 +1,6 @@
 use super::timer::Timer;
 use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 
 const DMC_RATE: [u16; 0x10] = [
     428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
@@ -10,7 +11,6 @@ pub struct DMC {
     timer: Timer,
     irq_enable: bool,
     pub irq_pending: bool,
-    pub irq_triggered: bool,
     loop_flag: bool,
     shift_register: u8,
     bits_remaining: usize,
@@ -26,7 +26,16 @@ pub struct DMC {
 
 impl DMC {
     pub fn new() -> Self {
-        Default::default()
+        DMC {
+            bits_remaining: 8,
+            sample_empty: true,
+            ..Default::default()
+        }
+    }
+
+    /// Only the lowest bit of the output level survives a reset
+    pub fn reset(&mut self) {
+        self.output_level &= 1;
     }
 
     pub fn clock(&mut self) {
@@ -64,14 +73,13 @@ impl DMC {
     pub fn load_buffer(&mut self, val: u8) {
         self.sample_buffer = val;
         self.sample_empty = false;
-        self.cur_addr = (self.cur_addr + 1) | 0x8000;
+        self.cur_addr = self.cur_addr.wrapping_add(1) | 0x8000;
         self.cur_length -= 1;
         if self.cur_length == 0 && self.loop_flag {
             self.cur_length = self.sample_length;
             self.cur_addr = self.sample_addr;
         } else if self.cur_length == 0 && self.irq_enable {
             self.irq_pending = true;
-            self.irq_triggered = true;
         }
     }
 
@@ -96,3 +104,40 @@ impl DMC {
         self.sample_length = (u16::from(val) * 16) + 1;
     }
 }
+
+impl Snapshot for DMC {
+    fn save(&self, writer: &mut StateWriter) {
+        self.timer.save(writer);
+        writer.write_bool(self.irq_enable);
+        writer.write_bool(self.irq_pending);
+        writer.write_bool(self.loop_flag);
+        writer.write_u8(self.shift_register);
+        writer.write_usize(self.bits_remaining);
+        writer.write_u8(self.output_level);
+        writer.write_bool(self.silent);
+        writer.write_u8(self.sample_buffer);
+        writer.write_bool(self.sample_empty);
+        writer.write_u16(self.sample_addr);
+        writer.write_u16(self.cur_addr);
+        writer.write_u16(self.sample_length);
+        writer.write_u16(self.cur_length);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.timer.load(reader)?;
+        self.irq_enable = reader.read_bool()?;
+        self.irq_pending = reader.read_bool()?;
+        self.loop_flag = reader.read_bool()?;
+        self.shift_register = reader.read_u8()?;
+        self.bits_remaining = reader.read_usize()?;
+        self.output_level = reader.read_u8()?;
+        self.silent = reader.read_bool()?;
+        self.sample_buffer = reader.read_u8()?;
+        self.sample_empty = reader.read_bool()?;
+        self.sample_addr = reader.read_u16()?;
+        self.cur_addr = reader.read_u16()?;
+        self.sample_length = reader.read_u16()?;
+        self.cur_length = reader.read_u16()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+
 const INITIAL_DECAY_LEVEL: u8 = 15;
 
 #[derive(Default)]
@@ -58,3 +60,24 @@ impl Envelope {
         self.start = val;
     }
 }
+
+impl Snapshot for Envelope {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.decay_level);
+        writer.write_u8(self.divider);
+        writer.write_u8(self.current_divider);
+        writer.write_bool(self.start);
+        writer.write_bool(self.envelope_loop);
+        writer.write_bool(self.constant_volume);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.decay_level = reader.read_u8()?;
+        self.divider = reader.read_u8()?;
+        self.current_divider = reader.read_u8()?;
+        self.start = reader.read_bool()?;
+        self.envelope_loop = reader.read_bool()?;
+        self.constant_volume = reader.read_bool()?;
+        Ok(())
+    }
+}
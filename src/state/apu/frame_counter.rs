@@ -1,10 +1,10 @@
 use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 use std::cell::Cell;
 
 pub struct FrameCounter {
     cpu_cycle: u32,
     irq_pending: Cell<bool>,
-    pub irq_triggered: bool,
     irq_inhibit: bool,
     mode: FrameCounterMode,
 }
@@ -14,7 +14,6 @@ impl FrameCounter {
         FrameCounter {
             cpu_cycle: 0,
             irq_pending: Cell::new(false),
-            irq_triggered: false,
             irq_inhibit: true,
             mode: FrameCounterMode::FourStep,
         }
@@ -44,6 +43,10 @@ impl FrameCounter {
         (self.cpu_cycle % 40) == 0
     }
 
+    pub fn is_irq_pending(&self) -> bool {
+        self.irq_pending.get()
+    }
+
     pub fn get_irq_pending(&self) -> bool {
         let result = self.irq_pending.get();
         self.irq_pending.set(false);
@@ -57,11 +60,16 @@ impl FrameCounter {
         }
         if self.mode == FrameCounterMode::FourStep && !self.irq_inhibit {
             self.irq_pending.set(true);
-            self.irq_triggered = true;
         }
         self.cpu_cycle = 0;
     }
 
+    /// Restarts the sequence, keeping the mode that was last written to $4017
+    pub fn reset(&mut self) {
+        self.cpu_cycle = 0;
+        self.irq_pending.set(false);
+    }
+
     pub fn set(&mut self, val: u8) {
         self.mode = if val.is_bit_set(7) {
             FrameCounterMode::FiveStep
@@ -80,3 +88,24 @@ enum FrameCounterMode {
     FourStep,
     FiveStep,
 }
+
+impl Snapshot for FrameCounter {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.cpu_cycle);
+        writer.write_bool(self.irq_pending.get());
+        writer.write_bool(self.irq_inhibit);
+        writer.write_bool(self.mode == FrameCounterMode::FiveStep);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.cpu_cycle = reader.read_u32()?;
+        self.irq_pending.set(reader.read_bool()?);
+        self.irq_inhibit = reader.read_bool()?;
+        self.mode = if reader.read_bool()? {
+            FrameCounterMode::FiveStep
+        } else {
+            FrameCounterMode::FourStep
+        };
+        Ok(())
+    }
+}
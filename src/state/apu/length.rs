@@ -1,3 +1,5 @@
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+
 const LENGTH_TABLE: [u8; 0x20] = [
     10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
     192, 24, 72, 26, 16, 28, 32, 30,
@@ -41,3 +43,16 @@ impl LengthCounter {
         }
     }
 }
+
+impl Snapshot for LengthCounter {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.val);
+        writer.write_bool(self.enabled);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.val = reader.read_u8()?;
+        self.enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
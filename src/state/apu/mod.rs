@@ -1,3 +1,4 @@
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 mod apu_impl;
 mod dmc;
 mod envelope;
@@ -50,6 +51,13 @@ impl APUState {
         }
     }
 
+    /// Resets the APU, which silences every channel as if $4015 was cleared
+    pub fn reset(&mut self) {
+        self.set_status(0);
+        self.frame_counter.reset();
+        self.dmc.reset();
+    }
+
     pub fn set_status(&mut self, val: u8) {
         if !val.is_bit_set(4) {
             self.dmc.cur_length = 0;
@@ -101,3 +109,26 @@ impl APUState {
         self.noise.length_counter.decrement();
     }
 }
+
+/// The sample buffer isn't saved, since it's drained every frame
+impl Snapshot for APUState {
+    fn save(&self, writer: &mut StateWriter) {
+        self.pulse1.save(writer);
+        self.pulse2.save(writer);
+        self.triangle.save(writer);
+        self.noise.save(writer);
+        self.dmc.save(writer);
+        self.frame_counter.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.pulse1.load(reader)?;
+        self.pulse2.load(reader)?;
+        self.triangle.load(reader)?;
+        self.noise.load(reader)?;
+        self.dmc.load(reader)?;
+        self.frame_counter.load(reader)?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use super::envelope::Envelope;
 use super::length::LengthCounter;
 use super::timer::Timer;
 use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 
 const NOISE_RATE: [u16; 0x10] = [
     4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
@@ -62,3 +63,22 @@ impl Noise {
         self.envelope.set_start(true);
     }
 }
+
+impl Snapshot for Noise {
+    fn save(&self, writer: &mut StateWriter) {
+        self.envelope.save(writer);
+        self.timer.save(writer);
+        self.length_counter.save(writer);
+        writer.write_u16(self.shift_register);
+        writer.write_bool(self.mode);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.envelope.load(reader)?;
+        self.timer.load(reader)?;
+        self.length_counter.load(reader)?;
+        self.shift_register = reader.read_u16()?;
+        self.mode = reader.read_bool()?;
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use super::envelope::Envelope;
 use super::length::LengthCounter;
 use super::timer::Timer;
 use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 
 const DUTY_LENGTH: usize = 8;
 
@@ -138,6 +139,37 @@ impl Negation {
     }
 }
 
+impl Snapshot for Pulse {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.duty);
+        writer.write_usize(self.duty_index);
+        self.length_counter.save(writer);
+        self.envelope.save(writer);
+        self.timer.save(writer);
+        writer.write_bool(self.sweep_enable);
+        writer.write_bool(self.sweep_reload);
+        writer.write_u8(self.sweep_counter);
+        writer.write_u8(self.sweep_period);
+        writer.write_bool(self.sweep_negate);
+        writer.write_u8(self.sweep_shift);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_bytes(&mut self.duty)?;
+        self.duty_index = reader.read_usize()? % DUTY_LENGTH;
+        self.length_counter.load(reader)?;
+        self.envelope.load(reader)?;
+        self.timer.load(reader)?;
+        self.sweep_enable = reader.read_bool()?;
+        self.sweep_reload = reader.read_bool()?;
+        self.sweep_counter = reader.read_u8()?;
+        self.sweep_period = reader.read_u8()?;
+        self.sweep_negate = reader.read_bool()?;
+        self.sweep_shift = reader.read_u8()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
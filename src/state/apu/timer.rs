@@ -1,3 +1,5 @@
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+
 #[derive(Default)]
 pub struct Timer {
     current: u16,
@@ -30,3 +32,16 @@ impl Timer {
         }
     }
 }
+
+impl Snapshot for Timer {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.current);
+        writer.write_u16(self.period);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.current = reader.read_u16()?;
+        self.period = reader.read_u16()?;
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use super::length::LengthCounter;
 use super::timer::Timer;
 use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 
 const TRIANGLE_SEQUENCE_LENGTH: usize = 32;
 
@@ -80,3 +81,26 @@ impl Triangle {
         self.linear_reload_flag = true;
     }
 }
+
+impl Snapshot for Triangle {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_usize(self.sequence_index);
+        self.timer.save(writer);
+        self.length_counter.save(writer);
+        writer.write_bool(self.linear_control);
+        writer.write_u8(self.linear_counter);
+        writer.write_u8(self.linear_reload_val);
+        writer.write_bool(self.linear_reload_flag);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.sequence_index = reader.read_usize()? % TRIANGLE_SEQUENCE_LENGTH;
+        self.timer.load(reader)?;
+        self.length_counter.load(reader)?;
+        self.linear_control = reader.read_bool()?;
+        self.linear_counter = reader.read_u8()?;
+        self.linear_reload_val = reader.read_u8()?;
+        self.linear_reload_flag = reader.read_bool()?;
+        Ok(())
+    }
+}
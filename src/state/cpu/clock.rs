@@ -0,0 +1,31 @@
+use super::Clock;
+use crate::state::NES;
+
+impl Clock for NES {
+    fn advance_cycle(&mut self) {
+        self.cpu.cycle += 1;
+    }
+
+    fn get_cycle(&self) -> u64 {
+        self.cpu.cycle
+    }
+
+    fn is_odd_cycle(&self) -> bool {
+        self.cpu.cycle % 2 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_cycle() {
+        let mut cpu = NES::mock();
+        assert_eq!(cpu.get_cycle(), 0);
+        assert!(!cpu.is_odd_cycle());
+        cpu.advance_cycle();
+        assert_eq!(cpu.get_cycle(), 1);
+        assert!(cpu.is_odd_cycle());
+    }
+}
@@ -0,0 +1,91 @@
+use super::{HaltedCycle, DMCDMA};
+use crate::state::NES;
+
+impl DMCDMA for NES {
+    fn is_dmc_dma_pending(&self) -> bool {
+        self.apu.dmc.is_dma_active()
+    }
+
+    fn get_dmc_dma_addr(&self) -> u16 {
+        self.apu.dmc.cur_addr
+    }
+
+    fn load_dmc_sample(&mut self, val: u8) {
+        self.apu.dmc.load_buffer(val);
+    }
+
+    fn start_halt(&mut self) {
+        self.cpu.halted = Some(HaltedCycle {
+            a: self.cpu.a,
+            x: self.cpu.x,
+            y: self.cpu.y,
+            pc: self.cpu.pc,
+            s: self.cpu.s,
+            p: self.cpu.p,
+            pending_interrupt: self.cpu.pending_interrupt,
+            nmi_pending: self.cpu.nmi_pending,
+        });
+        self.cpu.halt_addr.set(None);
+    }
+
+    fn finish_halt(&mut self) -> Option<u16> {
+        if let Some(halted) = self.cpu.halted.take() {
+            self.cpu.a = halted.a;
+            self.cpu.x = halted.x;
+            self.cpu.y = halted.y;
+            self.cpu.pc = halted.pc;
+            self.cpu.s = halted.s;
+            self.cpu.p = halted.p;
+            self.cpu.pending_interrupt = halted.pending_interrupt;
+            self.cpu.nmi_pending = halted.nmi_pending;
+        }
+        self.cpu.halt_addr.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::cpu::{Interrupt, InterruptState, Memory, Registers};
+
+    #[test]
+    fn test_halt() {
+        let mut cpu = NES::mock();
+        cpu.start_halt();
+        assert_eq!(cpu.finish_halt(), None);
+        cpu.start_halt();
+        cpu.get_mem(0x0123);
+        assert_eq!(cpu.finish_halt(), Some(0x0123));
+        cpu.start_halt();
+        cpu.dummy_read(0x0456);
+        assert_eq!(cpu.finish_halt(), Some(0x0456));
+        // writes are skipped while halting
+        cpu.set_mem(0x0123, 4);
+        cpu.start_halt();
+        cpu.set_mem(0x0123, 5);
+        assert_eq!(cpu.finish_halt(), None);
+        assert_eq!(cpu.get_mem(0x0123), 4);
+        // and so is everything else the cycle did
+        cpu.set_pc(0x1234);
+        cpu.cpu.nmi_pending = true;
+        cpu.start_halt();
+        cpu.set_pc(0x5678);
+        cpu.clear_nmi();
+        cpu.finish_halt();
+        assert_eq!(cpu.get_pc(), 0x1234);
+        assert_eq!(cpu.poll_interrupts(), InterruptState::NMI);
+    }
+
+    #[test]
+    fn test_dmc_dma_pending() {
+        let mut cpu = NES::mock();
+        assert!(!cpu.is_dmc_dma_pending());
+        cpu.set_mem(0x4012, 0x01);
+        cpu.set_mem(0x4013, 0x00);
+        cpu.set_mem(0x4015, 0b1_0000);
+        assert!(cpu.is_dmc_dma_pending());
+        assert_eq!(cpu.get_dmc_dma_addr(), 0xC040);
+        cpu.load_dmc_sample(0xAB);
+        assert!(!cpu.is_dmc_dma_pending());
+    }
+}
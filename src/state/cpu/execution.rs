@@ -0,0 +1,13 @@
+use super::Execution;
+use crate::cpu::ExecutionState;
+use crate::state::NES;
+
+impl Execution for NES {
+    fn get_execution_state(&self) -> ExecutionState {
+        self.cpu.execution
+    }
+
+    fn set_execution_state(&mut self, state: ExecutionState) {
+        self.cpu.execution = state;
+    }
+}
@@ -1,22 +1,140 @@
-use super::{Interrupt, InterruptState};
+use super::{IRQSource, Interrupt, InterruptState};
+use crate::bitops::BitOps;
 use crate::cpu::variables::Flag;
 use crate::state::cpu::Registers;
+use crate::state::ppu::VBlank;
 use crate::state::NES;
 
 impl Interrupt for NES {
+    fn poll_interrupts(&self) -> InterruptState {
+        if self.cpu.reset_pending {
+            InterruptState::Reset
+        } else if self.cpu.nmi_pending {
+            InterruptState::NMI
+        } else if self.is_irq_asserted() && !self.is_flag_set(Flag::I) {
+            InterruptState::IRQ
+        } else {
+            InterruptState::None
+        }
+    }
+
     fn get_pending_interrupt(&self) -> InterruptState {
         self.cpu.pending_interrupt
     }
-    fn trigger_nmi(&mut self) {
-        self.cpu.pending_interrupt = InterruptState::NMI;
+    fn set_pending_interrupt(&mut self, interrupt: InterruptState) {
+        self.cpu.pending_interrupt = interrupt;
     }
-    fn trigger_irq(&mut self) {
-        if self.cpu.pending_interrupt == InterruptState::NMI && !self.is_flag_set(Flag::I) {
-            return;
+
+    fn detect_nmi_edge(&mut self) {
+        if self.cpu.nmi_edge {
+            self.cpu.nmi_pending = true;
         }
-        self.cpu.pending_interrupt = InterruptState::IRQ;
+        let nmi_line = self.is_nmi_asserted();
+        self.cpu.nmi_edge = nmi_line && !self.cpu.nmi_line;
+        self.cpu.nmi_line = nmi_line;
+    }
+    fn clear_nmi(&mut self) {
+        self.cpu.nmi_pending = false;
+    }
+    fn trigger_reset(&mut self) {
+        self.cpu.reset_pending = true;
+    }
+    fn clear_reset(&mut self) {
+        self.cpu.reset_pending = false;
+    }
+
+    fn is_irq_asserted(&self) -> bool {
+        // the APU and the cartridge hold their own interrupt flags, which are acknowledged through
+        // their registers
+        let mut sources: u8 = self.cpu.irq_sources;
+        sources.assign_bit(
+            IRQSource::FrameCounter as usize,
+            self.apu.frame_counter.is_irq_pending(),
+        );
+        sources.assign_bit(IRQSource::DMC as usize, self.apu.dmc.irq_pending);
+        if self.cartridge.as_cpu_mapper().is_irq_asserted() {
+            sources.set_bit(IRQSource::Mapper as usize);
+        }
+        sources != 0
+    }
+    fn assert_irq(&mut self, source: IRQSource) {
+        self.cpu.irq_sources.set_bit(source as usize);
     }
-    fn clear_interrupt(&mut self) {
-        self.cpu.pending_interrupt = InterruptState::None;
+    fn acknowledge_irq(&mut self, source: IRQSource) {
+        self.cpu.irq_sources.clear_bit(source as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::cpu::Memory;
+
+    #[test]
+    fn test_irq_sources() {
+        let mut cpu = NES::mock();
+        assert_eq!(cpu.is_irq_asserted(), false);
+        cpu.assert_irq(IRQSource::FDS);
+        cpu.assert_irq(IRQSource::Mapper);
+        cpu.acknowledge_irq(IRQSource::FDS);
+        assert_eq!(cpu.is_irq_asserted(), true);
+        cpu.acknowledge_irq(IRQSource::Mapper);
+        assert_eq!(cpu.is_irq_asserted(), false);
+    }
+
+    #[test]
+    fn test_dmc_irq() {
+        let mut cpu = NES::mock();
+        cpu.set_mem(0x4010, 0b1000_0000);
+        cpu.apu.dmc.irq_pending = true;
+        assert_eq!(cpu.is_irq_asserted(), true);
+        // writing to $4015 acknowledges the DMC interrupt
+        cpu.set_mem(0x4015, 0);
+        assert_eq!(cpu.is_irq_asserted(), false);
+    }
+
+    #[test]
+    fn test_nmi_edge() {
+        let mut cpu = NES::mock();
+        cpu.start_vblank();
+        cpu.detect_nmi_edge();
+        // enabling NMI output during vblank produces a rising edge
+        cpu.set_mem(0x2000, 0b1000_0000);
+        cpu.detect_nmi_edge();
+        cpu.detect_nmi_edge();
+        assert_eq!(cpu.poll_interrupts(), InterruptState::NMI);
+        cpu.clear_nmi();
+        // the line stays high, so there's no new edge
+        cpu.detect_nmi_edge();
+        cpu.detect_nmi_edge();
+        assert_eq!(cpu.poll_interrupts(), InterruptState::None);
+        // toggling NMI output produces another one
+        cpu.set_mem(0x2000, 0);
+        cpu.detect_nmi_edge();
+        cpu.set_mem(0x2000, 0b1000_0000);
+        cpu.detect_nmi_edge();
+        cpu.detect_nmi_edge();
+        assert_eq!(cpu.poll_interrupts(), InterruptState::NMI);
+    }
+
+    #[test]
+    fn test_poll_interrupts() {
+        let mut cpu = NES::mock();
+        cpu.assert_irq(IRQSource::FDS);
+        cpu.assign_flag(Flag::I, true);
+        assert_eq!(cpu.poll_interrupts(), InterruptState::None);
+        cpu.assign_flag(Flag::I, false);
+        assert_eq!(cpu.poll_interrupts(), InterruptState::IRQ);
+        cpu.set_mem(0x2000, 0b1000_0000);
+        cpu.start_vblank();
+        cpu.detect_nmi_edge();
+        assert_eq!(cpu.poll_interrupts(), InterruptState::IRQ);
+        cpu.detect_nmi_edge();
+        assert_eq!(cpu.poll_interrupts(), InterruptState::NMI);
+        cpu.trigger_reset();
+        assert_eq!(cpu.poll_interrupts(), InterruptState::Reset);
+        cpu.clear_reset();
+        cpu.clear_nmi();
+        assert_eq!(cpu.poll_interrupts(), InterruptState::IRQ);
     }
 }
@@ -1,5 +1,7 @@
 use super::{Memory, Registers};
-use crate::state::ppu::MappedRegisters;
+use crate::state::hooks::{AccessKind, BusAccess};
+use crate::state::io::{Beam, Port};
+use crate::state::ppu::{Cycle, MappedRegisters};
 use crate::state::NES;
 
 impl Memory for NES {
@@ -10,6 +12,42 @@ impl Memory for NES {
     }
 
     fn get_mem(&self, addr: u16) -> u8 {
+        let val = self.read_cpu_bus(addr);
+        self.cpu.halt_addr.set(Some(addr));
+        self.notify_cpu_access(addr, val, AccessKind::Read);
+        val
+    }
+
+    fn dummy_read(&self, addr: u16) {
+        let val = self.read_cpu_bus(addr);
+        self.cpu.halt_addr.set(Some(addr));
+        self.notify_cpu_access(addr, val, AccessKind::DummyRead);
+    }
+
+    fn set_mem(&mut self, addr: u16, val: u8) {
+        self.cpu.halt_addr.set(None);
+        if self.cpu.halted.is_some() {
+            return;
+        }
+        self.write_cpu_bus(addr, val);
+        self.notify_cpu_access(addr, val, AccessKind::Write);
+    }
+}
+
+impl NES {
+    #[inline]
+    fn notify_cpu_access(&self, addr: u16, val: u8, kind: AccessKind) {
+        if self.hooks.is_active() {
+            self.hooks.notify_cpu(BusAccess {
+                addr,
+                val,
+                cycle: self.cpu.cycle,
+                kind,
+            });
+        }
+    }
+
+    fn read_cpu_bus(&self, addr: u16) -> u8 {
         self.cpu.open_bus.set(match addr {
             0..=0x1FFF => self.cpu.internal_ram[usize::from(addr % 0x800)],
             0x2000..=0x3FFF => match (addr - 0x2000) % 8 {
@@ -24,14 +62,24 @@ impl Memory for NES {
                 _ => unreachable!(),
             },
             0x4015 => self.apu.get_status() | self.cpu.open_bus.get(),
-            0x4016 => self.io.read() | self.cpu.open_bus.get(),
+            // controllers only drive the low bits, the rest are open bus
+            0x4016 => self.io.read(Port::One, self.beam()) | (self.cpu.open_bus.get() & 0xE0),
+            0x4017 => self.io.read(Port::Two, self.beam()) | (self.cpu.open_bus.get() & 0xE0),
             0x4020..=0xFFFF => self.cartridge.as_cpu_mapper().get(addr),
             _ => self.cpu.open_bus.get(),
         });
         self.cpu.open_bus.get()
     }
 
-    fn set_mem(&mut self, addr: u16, val: u8) {
+    /// Where the PPU is drawing, for light guns
+    fn beam(&self) -> Beam {
+        Beam {
+            scanline: self.get_scanline(),
+            dot: self.get_tick(),
+        }
+    }
+
+    fn write_cpu_bus(&mut self, addr: u16, val: u8) {
         match addr {
             0..=0x1FFF => self.cpu.internal_ram[usize::from(addr % 0x800)] = val,
             0x2000..=0x3FFF => match (addr - 0x2000) % 8 {
@@ -1,3 +1,6 @@
+mod clock;
+mod dmcdma;
+mod execution;
 mod interrupt;
 mod memory;
 mod oamdma;
@@ -5,6 +8,9 @@ mod registers;
 mod stack;
 
 use crate::cpu::variables::Flag;
+use crate::cpu::ExecutionState;
+use crate::state::power_on::RAMFiller;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 use std::cell::Cell;
 
 /// Trait representing CPU registers
@@ -37,6 +43,10 @@ pub trait Memory {
     fn get_mem(&self, addr: u16) -> u8;
     fn set_mem(&mut self, addr: u16, val: u8);
 
+    /// Reads a byte and throws it away. The CPU makes these reads on cycles where it has nothing
+    /// useful to do, but they can still have side effects on memory-mapped registers.
+    fn dummy_read(&self, addr: u16);
+
     /// Gets the byte at the address specified by the PC, then increments the PC
     fn get_and_increment_pc(&mut self) -> u8;
 }
@@ -59,16 +69,64 @@ pub trait OAMDMA {
     fn untrigger_oam_dma(&mut self);
     fn get_oam_dma(&self) -> u8;
     fn write_oam(&mut self, offset: usize, val: u8);
-    fn toggle_odd_even(&mut self);
+}
+
+/// Trait for DMC DMA-related behaviour.
+/// The DMC fetches its samples by halting the CPU and taking over its bus for a few cycles.
+pub trait DMCDMA {
+    fn is_dmc_dma_pending(&self) -> bool;
+    fn get_dmc_dma_addr(&self) -> u16;
+    fn load_dmc_sample(&mut self, val: u8);
+
+    /// Starts a cycle the CPU might be halted on. The CPU can only be halted on a read cycle, so
+    /// writes are skipped, and the registers and interrupt state are saved until `finish_halt`.
+    fn start_halt(&mut self);
+
+    /// Puts back the registers and interrupt state saved by `start_halt`. Returns the address of
+    /// the read the CPU was halted on, or `None` if the cycle didn't read.
+    fn finish_halt(&mut self) -> Option<u16>;
+}
+
+/// Trait for keeping track of CPU cycles
+pub trait Clock {
+    /// Advances the CPU by a single cycle
+    fn advance_cycle(&mut self);
+
+    /// Returns the number of cycles executed since power-on
+    fn get_cycle(&self) -> u64;
     fn is_odd_cycle(&self) -> bool;
 }
 
 /// Trait for interrupt-related behaviour
 pub trait Interrupt {
+    /// Returns the interrupt the CPU would service if it polled its interrupt lines now.
+    /// NMI takes priority, and IRQ is masked by the I flag.
+    fn poll_interrupts(&self) -> InterruptState;
+
+    /// The interrupt that will be serviced once the current instruction completes
     fn get_pending_interrupt(&self) -> InterruptState;
-    fn trigger_nmi(&mut self);
-    fn trigger_irq(&mut self);
-    fn clear_interrupt(&mut self);
+    fn set_pending_interrupt(&mut self, interrupt: InterruptState);
+
+    /// Samples the NMI line at the end of a CPU cycle. A rising edge becomes visible to
+    /// `poll_interrupts` one cycle later.
+    fn detect_nmi_edge(&mut self);
+    fn clear_nmi(&mut self);
+
+    /// RESET takes priority over every other interrupt.
+    fn trigger_reset(&mut self);
+    fn clear_reset(&mut self);
+
+    /// The IRQ line is level-triggered: it is held low as long as any source asserts it.
+    /// A source stays asserted until it is acknowledged.
+    fn is_irq_asserted(&self) -> bool;
+    fn assert_irq(&mut self, source: IRQSource);
+    fn acknowledge_irq(&mut self, source: IRQSource);
+}
+
+/// Trait for the CPU's progress through the current instruction, interrupt or DMA
+pub trait Execution {
+    fn get_execution_state(&self) -> ExecutionState;
+    fn set_execution_state(&mut self, state: ExecutionState);
 }
 
 /// Represents the CPU's internal state
@@ -81,25 +139,83 @@ pub struct CPUState {
     p: u8,
     internal_ram: [u8; 0x800],
     open_bus: Cell<u8>,
-    odd_cycle: bool,
+    halt_addr: Cell<Option<u16>>,
+    /// Set while running a cycle the CPU might be halted on
+    halted: Option<HaltedCycle>,
+    cycle: u64,
     oam_dma: u8,
     oam_dma_triggered: bool,
     pending_interrupt: InterruptState,
+    reset_pending: bool,
+    nmi_line: bool,
+    nmi_edge: bool,
+    nmi_pending: bool,
+    irq_sources: u8,
+    execution: ExecutionState,
+}
+
+/// What a cycle the CPU is halted on can change besides memory, so that it can be undone
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct HaltedCycle {
+    a: u8,
+    x: u8,
+    y: u8,
+    pc: u16,
+    s: u8,
+    p: u8,
+    pending_interrupt: InterruptState,
+    nmi_pending: bool,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum InterruptState {
+    Reset,
     NMI,
     IRQ,
     None,
 }
 
+impl Snapshot for InterruptState {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(match self {
+            InterruptState::None => 0,
+            InterruptState::Reset => 1,
+            InterruptState::NMI => 2,
+            InterruptState::IRQ => 3,
+        });
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        *self = match reader.read_u8()? {
+            0 => InterruptState::None,
+            1 => InterruptState::Reset,
+            2 => InterruptState::NMI,
+            3 => InterruptState::IRQ,
+            _ => return Err(SaveStateError::InvalidData),
+        };
+        Ok(())
+    }
+}
+
+/// Devices that can pull the IRQ line low
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IRQSource {
+    FrameCounter,
+    DMC,
+    Mapper,
+    FDS,
+}
+
 impl CPUState {
     #[cfg(test)]
     pub fn mock() -> Self {
         Self::new()
     }
 
+    pub fn fill_ram(&mut self, filler: &mut RAMFiller) {
+        filler.fill(&mut self.internal_ram);
+    }
+
     pub fn new() -> Self {
         CPUState {
             a: 0,
@@ -110,10 +226,68 @@ impl CPUState {
             p: 0b0010_0100,
             internal_ram: [0; 0x800],
             open_bus: Cell::new(0),
-            odd_cycle: false,
+            halt_addr: Cell::new(None),
+            halted: None,
+            cycle: 0,
             oam_dma: 0,
             oam_dma_triggered: false,
             pending_interrupt: InterruptState::None,
+            reset_pending: false,
+            nmi_line: false,
+            nmi_edge: false,
+            nmi_pending: false,
+            irq_sources: 0,
+            execution: ExecutionState::Fetch,
         }
     }
 }
+
+impl Snapshot for CPUState {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.a);
+        writer.write_u8(self.x);
+        writer.write_u8(self.y);
+        writer.write_u16(self.pc);
+        writer.write_u8(self.s);
+        writer.write_u8(self.p);
+        writer.write_bytes(&self.internal_ram);
+        writer.write_u8(self.open_bus.get());
+        writer.write_bool(self.halt_addr.get().is_some());
+        writer.write_u16(self.halt_addr.get().unwrap_or(0));
+        writer.write_u64(self.cycle);
+        writer.write_u8(self.oam_dma);
+        writer.write_bool(self.oam_dma_triggered);
+        self.pending_interrupt.save(writer);
+        writer.write_bool(self.reset_pending);
+        writer.write_bool(self.nmi_line);
+        writer.write_bool(self.nmi_edge);
+        writer.write_bool(self.nmi_pending);
+        writer.write_u8(self.irq_sources);
+        self.execution.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.a = reader.read_u8()?;
+        self.x = reader.read_u8()?;
+        self.y = reader.read_u8()?;
+        self.pc = reader.read_u16()?;
+        self.s = reader.read_u8()?;
+        self.p = reader.read_u8()?;
+        reader.read_bytes(&mut self.internal_ram)?;
+        self.open_bus.set(reader.read_u8()?);
+        let halted: bool = reader.read_bool()?;
+        let halt_addr: u16 = reader.read_u16()?;
+        self.halt_addr
+            .set(if halted { Some(halt_addr) } else { None });
+        self.cycle = reader.read_u64()?;
+        self.oam_dma = reader.read_u8()?;
+        self.oam_dma_triggered = reader.read_bool()?;
+        self.pending_interrupt.load(reader)?;
+        self.reset_pending = reader.read_bool()?;
+        self.nmi_line = reader.read_bool()?;
+        self.nmi_edge = reader.read_bool()?;
+        self.nmi_pending = reader.read_bool()?;
+        self.irq_sources = reader.read_u8()?;
+        self.execution.load(reader)
+    }
+}
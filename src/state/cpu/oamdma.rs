@@ -15,14 +15,6 @@ impl OAMDMA for NES {
         self.ppu.oam.memory[offset.wrapping_add(usize::from(self.ppu.oam.addr)) % OAM::SIZE] = val;
     }
 
-    fn toggle_odd_even(&mut self) {
-        self.cpu.odd_cycle = !self.cpu.odd_cycle;
-    }
-
-    fn is_odd_cycle(&self) -> bool {
-        self.cpu.odd_cycle
-    }
-
     fn untrigger_oam_dma(&mut self) {
         self.cpu.oam_dma_triggered = false;
     }
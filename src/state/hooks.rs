@@ -0,0 +1,173 @@
+//! This module lets code outside of the core observe traffic on the CPU and PPU buses, which is
+//! useful for building profilers, watchpoints and cheat engines. Subscribers are notified after
+//! every read and write. When nothing is subscribed, the only cost paid by the core is a single
+//! boolean check per access.
+
+use super::NES;
+use std::cell::RefCell;
+
+/// Represents a single access made on either the CPU or the PPU bus
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BusAccess {
+    pub addr: u16,
+    pub val: u8,
+    /// The CPU cycle (for CPU accesses) or the PPU dot (for PPU accesses) the access was made on
+    pub cycle: u64,
+    pub kind: AccessKind,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AccessKind {
+    Read,
+    /// A read whose value is thrown away by the CPU
+    DummyRead,
+    Write,
+}
+
+/// Trait implemented by anything that wants to observe bus traffic.
+/// Both methods do nothing by default, so subscribers only need to implement the ones they need.
+pub trait BusSubscriber {
+    fn on_cpu_access(&mut self, _access: BusAccess) {}
+    fn on_ppu_access(&mut self, _access: BusAccess) {}
+}
+
+/// Identifies a subscriber so that it can be removed later
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SubscriberId(usize);
+
+pub trait Hooks {
+    fn subscribe(&mut self, subscriber: Box<dyn BusSubscriber>) -> SubscriberId;
+
+    /// Removes a subscriber, handing it back to the caller
+    fn unsubscribe(&mut self, id: SubscriberId) -> Option<Box<dyn BusSubscriber>>;
+}
+
+/// Holds the subscribers attached to the NES
+pub struct HookState {
+    active: bool,
+    next_id: usize,
+    subscribers: RefCell<Vec<(SubscriberId, Box<dyn BusSubscriber>)>>,
+}
+
+impl Hooks for NES {
+    fn subscribe(&mut self, subscriber: Box<dyn BusSubscriber>) -> SubscriberId {
+        let id = SubscriberId(self.hooks.next_id);
+        self.hooks.next_id += 1;
+        self.hooks.subscribers.get_mut().push((id, subscriber));
+        self.hooks.active = true;
+        id
+    }
+
+    fn unsubscribe(&mut self, id: SubscriberId) -> Option<Box<dyn BusSubscriber>> {
+        let subscribers = self.hooks.subscribers.get_mut();
+        let index = subscribers.iter().position(|(x, _)| *x == id)?;
+        let (_, subscriber) = subscribers.remove(index);
+        self.hooks.active = !subscribers.is_empty();
+        Some(subscriber)
+    }
+}
+
+impl HookState {
+    #[cfg(test)]
+    pub fn mock() -> Self {
+        Self::new()
+    }
+
+    pub fn new() -> Self {
+        HookState {
+            active: false,
+            next_id: 0,
+            subscribers: RefCell::new(Vec::new()),
+        }
+    }
+
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn notify_cpu(&self, access: BusAccess) {
+        for (_, subscriber) in self.subscribers.borrow_mut().iter_mut() {
+            subscriber.on_cpu_access(access);
+        }
+    }
+
+    pub fn notify_ppu(&self, access: BusAccess) {
+        for (_, subscriber) in self.subscribers.borrow_mut().iter_mut() {
+            subscriber.on_ppu_access(access);
+        }
+    }
+}
+
+impl Default for HookState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::cpu::Memory;
+    use crate::state::ppu::Memory as PPUMemory;
+    use std::rc::Rc;
+
+    struct Recorder {
+        cpu: Rc<RefCell<Vec<BusAccess>>>,
+        ppu: Rc<RefCell<Vec<BusAccess>>>,
+    }
+
+    impl BusSubscriber for Recorder {
+        fn on_cpu_access(&mut self, access: BusAccess) {
+            self.cpu.borrow_mut().push(access);
+        }
+        fn on_ppu_access(&mut self, access: BusAccess) {
+            self.ppu.borrow_mut().push(access);
+        }
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let mut nes = NES::mock();
+        let cpu = Rc::new(RefCell::new(Vec::new()));
+        let ppu = Rc::new(RefCell::new(Vec::new()));
+        let id = nes.subscribe(Box::new(Recorder {
+            cpu: cpu.clone(),
+            ppu: ppu.clone(),
+        }));
+        nes.set_mem(0x0010, 7);
+        nes.get_mem(0x0010);
+        nes.dummy_read(0x0010);
+        nes.set(0x2000, 3);
+        assert_eq!(
+            *cpu.borrow(),
+            vec![
+                BusAccess {
+                    addr: 0x0010,
+                    val: 7,
+                    cycle: 0,
+                    kind: AccessKind::Write
+                },
+                BusAccess {
+                    addr: 0x0010,
+                    val: 7,
+                    cycle: 0,
+                    kind: AccessKind::Read
+                },
+                BusAccess {
+                    addr: 0x0010,
+                    val: 7,
+                    cycle: 0,
+                    kind: AccessKind::DummyRead
+                },
+            ]
+        );
+        assert_eq!(ppu.borrow().len(), 1);
+        assert_eq!(ppu.borrow()[0].kind, AccessKind::Write);
+
+        assert!(nes.unsubscribe(id).is_some());
+        assert!(!nes.hooks.is_active());
+        nes.get_mem(0x0010);
+        assert_eq!(cpu.borrow().len(), 3);
+    }
+}
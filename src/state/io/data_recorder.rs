@@ -0,0 +1,174 @@
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::tape::Tape;
+
+/// The NTSC CPU's clock rate, which the tape's position is measured in
+const CPU_FREQUENCY: u64 = 1_789_773;
+
+/// How loud recorded audio is
+const RECORDING_LEVEL: i16 = i16::MAX / 2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TapeState {
+    Stopped,
+    Playing,
+    Recording,
+}
+
+/// The Famicom data recorder, a cassette recorder that plugs into the Family BASIC keyboard.
+/// Games write to it through OUT2 ($4016 bit 2), and read from it through D1 of $4016.
+/// Playing and recording always start from the beginning of the tape.
+pub struct DataRecorder {
+    tape: Tape,
+    state: TapeState,
+    /// CPU cycles since the tape started playing or recording
+    cycles: u64,
+    output: bool,
+}
+
+impl DataRecorder {
+    pub fn new() -> Self {
+        DataRecorder {
+            tape: Tape::new(),
+            state: TapeState::Stopped,
+            cycles: 0,
+            output: false,
+        }
+    }
+
+    /// Replaces the tape, stopping it
+    pub fn insert(&mut self, tape: Tape) {
+        self.tape = tape;
+        self.state = TapeState::Stopped;
+    }
+
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    pub fn state(&self) -> TapeState {
+        self.state
+    }
+
+    pub fn play(&mut self) {
+        self.state = TapeState::Playing;
+        self.cycles = 0;
+    }
+
+    /// Records over the whole tape
+    pub fn record(&mut self) {
+        self.tape.samples.clear();
+        self.state = TapeState::Recording;
+        self.cycles = 0;
+    }
+
+    pub fn stop(&mut self) {
+        self.state = TapeState::Stopped;
+    }
+
+    /// The sample under the tape head
+    fn position(&self) -> usize {
+        (self.cycles * u64::from(self.tape.sample_rate) / CPU_FREQUENCY) as usize
+    }
+
+    /// Advances the tape by a CPU cycle
+    pub(super) fn cpu_cycle(&mut self) {
+        if self.state == TapeState::Stopped {
+            return;
+        }
+        self.cycles += 1;
+        let position: usize = self.position();
+        match self.state {
+            TapeState::Playing if position >= self.tape.samples.len() => self.stop(),
+            TapeState::Recording if position >= self.tape.samples.len() => {
+                let level: i16 = if self.output {
+                    RECORDING_LEVEL
+                } else {
+                    -RECORDING_LEVEL
+                };
+                self.tape.samples.push(level);
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn set_output(&mut self, output: bool) {
+        self.output = output;
+    }
+
+    /// The level of the audio being played, which is only ever high while the tape is playing
+    pub(super) fn input(&self) -> bool {
+        self.state == TapeState::Playing
+            && self
+                .tape
+                .samples
+                .get(self.position())
+                .is_some_and(|&sample| sample > 0)
+    }
+}
+
+impl Default for DataRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The tape itself isn't saved. Loading a state while recording erases everything recorded after
+/// it.
+impl Snapshot for DataRecorder {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(match self.state {
+            TapeState::Stopped => 0,
+            TapeState::Playing => 1,
+            TapeState::Recording => 2,
+        });
+        writer.write_u64(self.cycles);
+        writer.write_bool(self.output);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.state = match reader.read_u8()? {
+            0 => TapeState::Stopped,
+            1 => TapeState::Playing,
+            2 => TapeState::Recording,
+            _ => return Err(SaveStateError::InvalidData),
+        };
+        self.cycles = reader.read_u64()?;
+        self.output = reader.read_bool()?;
+        if self.state == TapeState::Recording {
+            let position: usize = self.position();
+            self.tape.samples.truncate(position + 1);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_play() {
+        let mut recorder = DataRecorder::new();
+        recorder.record();
+        // a square wave with a period of 1000 CPU cycles
+        for cycle in 0..10_000 {
+            recorder.set_output(cycle % 1000 < 500);
+            recorder.cpu_cycle();
+        }
+        recorder.stop();
+        let samples: usize = recorder.tape().samples.len();
+        assert!((246..=247).contains(&samples));
+
+        recorder.play();
+        let mut played: Vec<bool> = Vec::new();
+        while recorder.state() == TapeState::Playing {
+            played.push(recorder.input());
+            recorder.cpu_cycle();
+        }
+        assert!((10_000..=10_050).contains(&played.len()));
+        let highs: usize = played[..8000].iter().filter(|&&high| high).count();
+        assert!((3950..=4050).contains(&highs));
+        assert!(played[100]);
+        assert!(!played[600]);
+    }
+}
@@ -0,0 +1,162 @@
+use super::data_recorder::DataRecorder;
+use super::keyboard::Keys;
+use super::power_pad::PowerPadSide;
+use super::standard::{Buttons, Turbo};
+use super::vaus::VausModel;
+use super::Port;
+use crate::ppu::Pixel;
+use crate::state::snapshot::Snapshot;
+
+/// Trait implemented by anything that can be plugged into a controller port or the expansion port.
+/// The CPU talks to devices through $4016 and $4017: writes to $4016 set the OUT0-OUT2 lines of
+/// every device, and reads shift data out of them one bit at a time.
+/// Devices save their own shift registers and latches, but not the input they were given.
+pub trait InputDevice: Snapshot {
+    /// Receives the OUT0-OUT2 bits of a write to $4016. Bit 0 is the strobe.
+    fn write(&mut self, out: u8);
+    /// Returns the D0-D4 bits of a read from $4016 (`Port::One`) or $4017 (`Port::Two`).
+    /// Devices in a controller port are only read through that port's register, while devices in
+    /// the expansion port are read through both.
+    /// `beam` is where the PPU is drawing, for devices that sense light from the screen.
+    fn read(&self, register: Port, beam: Beam) -> u8;
+    /// Receives the frontend's input, once per frame
+    fn update(&mut self, input: &Input);
+    /// Returns to the power-on state
+    fn reset(&mut self);
+    fn kind(&self) -> DeviceKind;
+
+    /// Returns true if the device wants to see the pixels the PPU outputs
+    fn senses_light(&self) -> bool {
+        false
+    }
+
+    /// Receives every pixel the PPU outputs, if `senses_light` returns true
+    fn observe_pixel(&mut self, _pixel: &Pixel) {}
+
+    /// Called whenever the PPU starts a new frame, if `senses_light` returns true
+    fn start_frame(&mut self) {}
+
+    /// Runs alongside the CPU, for devices that keep time
+    fn cpu_cycle(&mut self) {}
+
+    /// The data recorder plugged into the device, if there is one
+    fn data_recorder(&mut self) -> Option<&mut DataRecorder> {
+        None
+    }
+}
+
+/// The position of the PPU in the frame
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Beam {
+    pub scanline: usize,
+    pub dot: usize,
+}
+
+/// Identifies the kind of device in a port, so that save states can check it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DeviceKind {
+    StandardController,
+    Zapper,
+    FourScore,
+    FamicomFourPlayers,
+    Vaus(VausModel),
+    FamilyBasicKeyboard,
+    PowerPad(PowerPadSide),
+}
+
+impl DeviceKind {
+    /// 0 is reserved for empty ports
+    pub(super) fn tag(self) -> u8 {
+        match self {
+            DeviceKind::StandardController => 1,
+            DeviceKind::Zapper => 2,
+            DeviceKind::FourScore => 3,
+            DeviceKind::FamicomFourPlayers => 4,
+            DeviceKind::Vaus(VausModel::Nes) => 5,
+            DeviceKind::Vaus(VausModel::Famicom) => 6,
+            DeviceKind::FamilyBasicKeyboard => 7,
+            DeviceKind::PowerPad(PowerPadSide::A) => 8,
+            DeviceKind::PowerPad(PowerPadSide::B) => 9,
+        }
+    }
+}
+
+/// The devices a game expects to be plugged in, from the NES 2.0 header's default expansion
+/// device field
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExpansionDevice {
+    StandardControllers,
+    /// A Four Score in both controller ports
+    FourScore,
+    /// A Famicom four player adapter in the expansion port
+    FamicomFourPlayers,
+    /// A Zapper in the second controller port
+    Zapper,
+    /// An Arkanoid controller in the second controller port for the NES model, or the expansion
+    /// port for the Famicom model
+    Vaus(VausModel),
+    /// A Family BASIC keyboard and data recorder in the expansion port
+    FamilyBasicKeyboard,
+    /// A Power Pad in the second controller port
+    PowerPad(PowerPadSide),
+}
+
+impl ExpansionDevice {
+    /// Returns `None` for devices that aren't supported, or when the field is unspecified
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0x01 => Some(ExpansionDevice::StandardControllers),
+            0x02 => Some(ExpansionDevice::FourScore),
+            0x03 => Some(ExpansionDevice::FamicomFourPlayers),
+            0x08 => Some(ExpansionDevice::Zapper),
+            0x0B => Some(ExpansionDevice::PowerPad(PowerPadSide::A)),
+            0x0C => Some(ExpansionDevice::PowerPad(PowerPadSide::B)),
+            0x0F => Some(ExpansionDevice::Vaus(VausModel::Nes)),
+            0x10 => Some(ExpansionDevice::Vaus(VausModel::Famicom)),
+            0x23 => Some(ExpansionDevice::FamilyBasicKeyboard),
+            _ => None,
+        }
+    }
+}
+
+/// Everything the frontend feeds the input devices for a frame.
+/// Each device picks the parts it needs.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Input {
+    /// The buttons held on each standard controller, by player
+    pub buttons: [Buttons; 4],
+    pub pointer: Pointer,
+    pub paddle: Paddle,
+    /// The keys held on a computer keyboard
+    pub keys: Keys,
+    /// The squares stepped on on a floor mat, in rows of four from the top left as the player sees
+    /// them
+    pub mat: [bool; 12],
+}
+
+impl Input {
+    /// Returns the input with turbo buttons turned into presses for the frame
+    pub fn with_turbo(&self, turbo: Turbo, frame: u64) -> Input {
+        let mut input: Input = self.clone();
+        for buttons in &mut input.buttons {
+            *buttons = turbo.apply(*buttons, frame);
+        }
+        input
+    }
+}
+
+/// Something aimed at the screen, like a mouse standing in for a light gun
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Pointer {
+    /// The pixel being aimed at, or `None` when aiming away from the screen
+    pub position: Option<(usize, usize)>,
+    pub trigger: bool,
+}
+
+/// A knob or slider, like the one on the Arkanoid controller
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Paddle {
+    /// From 0.0 at the far left to 1.0 at the far right
+    pub position: f32,
+    pub button: bool,
+}
@@ -0,0 +1,240 @@
+use super::device::{Beam, DeviceKind, Input, InputDevice};
+use super::standard::StandardController;
+use super::Port;
+use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use std::cell::Cell;
+
+/// Length of a Four Score report: two controllers and a signature
+const FOUR_SCORE_REPORT_BITS: usize = 24;
+
+/// One half of an NES Four Score, which plugs into both controller ports. Each half reports two
+/// controllers, followed by a signature that tells games the Four Score is there.
+pub struct FourScore {
+    /// The players whose controllers are reported, in order
+    players: [usize; 2],
+    signature: u8,
+    strobe_on: bool,
+    held: u32,
+    report: u32,
+    index: Cell<usize>,
+}
+
+impl FourScore {
+    /// The half plugged into port one reports players 1 and 3, and the half plugged into port two
+    /// reports players 2 and 4
+    pub fn new(port: Port) -> Self {
+        let (players, signature) = match port {
+            Port::Two => ([1, 3], 0x04),
+            _ => ([0, 2], 0x08),
+        };
+        FourScore {
+            players,
+            signature,
+            strobe_on: false,
+            held: 0,
+            report: 0,
+            index: Cell::new(0),
+        }
+    }
+}
+
+impl InputDevice for FourScore {
+    fn write(&mut self, out: u8) {
+        self.strobe_on = out.is_bit_set(0);
+        if self.strobe_on {
+            self.report = self.held;
+            self.index.set(0);
+        }
+    }
+
+    fn read(&self, _register: Port, _beam: Beam) -> u8 {
+        if self.index.get() >= FOUR_SCORE_REPORT_BITS {
+            return 1;
+        }
+        let result: u8 = ((self.report >> self.index.get()) & 1) as u8;
+        if !self.strobe_on {
+            self.index.set(self.index.get() + 1);
+        }
+        result
+    }
+
+    fn update(&mut self, input: &Input) {
+        let buttons = self
+            .players
+            .iter()
+            .flat_map(|&player| input.buttons[player].to_report().to_vec());
+        self.held = buttons
+            .enumerate()
+            .fold(u32::from(self.signature) << 16, |held, (i, pressed)| {
+                held | (u32::from(pressed) << i)
+            });
+        if self.strobe_on {
+            self.report = self.held;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.strobe_on = false;
+        self.report = 0;
+        self.index.set(0);
+    }
+
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::FourScore
+    }
+}
+
+/// The held buttons come from the frontend, so only the shift register is saved
+impl Snapshot for FourScore {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.strobe_on);
+        writer.write_u32(self.report);
+        writer.write_usize(self.index.get());
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.strobe_on = reader.read_bool()?;
+        self.report = reader.read_u32()?;
+        self.index.set(reader.read_usize()?);
+        Ok(())
+    }
+}
+
+/// A Famicom four player adapter, which plugs into the expansion port. Players 3 and 4 are
+/// reported on D1 of $4016 and $4017, the same way standard controllers report on D0.
+pub struct FamicomFourPlayers {
+    controllers: [StandardController; 2],
+}
+
+impl FamicomFourPlayers {
+    pub fn new() -> Self {
+        FamicomFourPlayers {
+            controllers: [StandardController::new(2), StandardController::new(3)],
+        }
+    }
+}
+
+impl Default for FamicomFourPlayers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDevice for FamicomFourPlayers {
+    fn write(&mut self, out: u8) {
+        for controller in &mut self.controllers {
+            controller.write(out);
+        }
+    }
+
+    fn read(&self, register: Port, beam: Beam) -> u8 {
+        let controller: &StandardController = match register {
+            Port::Two => &self.controllers[1],
+            _ => &self.controllers[0],
+        };
+        controller.read(register, beam) << 1
+    }
+
+    fn update(&mut self, input: &Input) {
+        for controller in &mut self.controllers {
+            controller.update(input);
+        }
+    }
+
+    fn reset(&mut self) {
+        for controller in &mut self.controllers {
+            controller.reset();
+        }
+    }
+
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::FamicomFourPlayers
+    }
+}
+
+impl Snapshot for FamicomFourPlayers {
+    fn save(&self, writer: &mut StateWriter) {
+        for controller in &self.controllers {
+            controller.save(writer);
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for controller in &mut self.controllers {
+            controller.load(reader)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::cpu::Memory;
+    use crate::state::io::{Buttons, Controller};
+    use crate::state::NES;
+
+    fn four_players() -> Input {
+        let mut input = Input::default();
+        input.buttons[0].a = true;
+        input.buttons[1].b = true;
+        input.buttons[2].select = true;
+        input.buttons[3] = Buttons {
+            start: true,
+            right: true,
+            ..Buttons::default()
+        };
+        input
+    }
+
+    fn read_bits(nes: &NES, addr: u16, bit: u8, count: usize) -> Vec<u8> {
+        (0..count).map(|_| (nes.get_mem(addr) >> bit) & 1).collect()
+    }
+
+    #[test]
+    fn test_four_score() {
+        let mut nes = NES::mock();
+        nes.set_device(Port::One, Some(Box::new(FourScore::new(Port::One))));
+        nes.set_device(Port::Two, Some(Box::new(FourScore::new(Port::Two))));
+        nes.update_input(&four_players());
+        nes.set_mem(0x4016, 1);
+        nes.set_mem(0x4016, 0);
+        assert_eq!(
+            read_bits(&nes, 0x4016, 0, 26),
+            vec![
+                1, 0, 0, 0, 0, 0, 0, 0, // player 1
+                0, 0, 1, 0, 0, 0, 0, 0, // player 3
+                0, 0, 0, 1, 0, 0, 0, 0, // signature
+                1, 1,
+            ]
+        );
+        assert_eq!(
+            read_bits(&nes, 0x4017, 0, 24),
+            vec![
+                0, 1, 0, 0, 0, 0, 0, 0, // player 2
+                0, 0, 0, 1, 0, 0, 0, 1, // player 4
+                0, 0, 1, 0, 0, 0, 0, 0, // signature
+            ]
+        );
+    }
+
+    #[test]
+    fn test_famicom_four_players() {
+        let mut nes = NES::mock();
+        nes.set_device(Port::Expansion, Some(Box::new(FamicomFourPlayers::new())));
+        nes.update_input(&four_players());
+        nes.set_mem(0x4016, 1);
+        nes.set_mem(0x4016, 0);
+        // every read clocks all the devices on that register, so read each one's bits together
+        let reads: Vec<u8> = (0..8).map(|_| nes.get_mem(0x4016)).collect();
+        let bits = |reads: &[u8], bit: u8| -> Vec<u8> {
+            reads.iter().map(|read| (read >> bit) & 1).collect()
+        };
+        assert_eq!(bits(&reads, 0), vec![1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bits(&reads, 1), vec![0, 0, 1, 0, 0, 0, 0, 0]);
+        let reads: Vec<u8> = (0..8).map(|_| nes.get_mem(0x4017)).collect();
+        assert_eq!(bits(&reads, 0), vec![0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bits(&reads, 1), vec![0, 0, 0, 1, 0, 0, 0, 1]);
+    }
+}
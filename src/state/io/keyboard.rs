@@ -0,0 +1,289 @@
+use super::data_recorder::DataRecorder;
+use super::device::{Beam, DeviceKind, Input, InputDevice};
+use super::Port;
+use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+/// The keys of the Family BASIC keyboard
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Key {
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    Num0,
+    Num1,
+    Num2,
+    Num3,
+    Num4,
+    Num5,
+    Num6,
+    Num7,
+    Num8,
+    Num9,
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    Minus,
+    Caret,
+    Yen,
+    At,
+    LeftBracket,
+    RightBracket,
+    Semicolon,
+    Colon,
+    Comma,
+    Period,
+    Slash,
+    Underscore,
+    Return,
+    Stop,
+    Kana,
+    LeftShift,
+    RightShift,
+    Ctr,
+    Esc,
+    Grph,
+    Space,
+    ClrHome,
+    Ins,
+    Del,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// The set of keys held down on the keyboard
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Keys(u128);
+
+impl Keys {
+    pub fn press(&mut self, key: Key) {
+        self.0 |= 1 << key as u32;
+    }
+
+    pub fn is_pressed(self, key: Key) -> bool {
+        self.0 & (1 << key as u32) != 0
+    }
+}
+
+/// The keys in each row of the matrix. Each row is read in two halves of four keys, which are
+/// reported on D1-D4 of $4017.
+const MATRIX: [[[Key; 4]; 2]; 9] = [
+    [
+        [Key::RightBracket, Key::LeftBracket, Key::Return, Key::F8],
+        [Key::Stop, Key::Yen, Key::RightShift, Key::Kana],
+    ],
+    [
+        [Key::Semicolon, Key::Colon, Key::At, Key::F7],
+        [Key::Caret, Key::Minus, Key::Slash, Key::Underscore],
+    ],
+    [
+        [Key::K, Key::L, Key::O, Key::F6],
+        [Key::Num0, Key::P, Key::Comma, Key::Period],
+    ],
+    [
+        [Key::J, Key::U, Key::I, Key::F5],
+        [Key::Num8, Key::Num9, Key::N, Key::M],
+    ],
+    [
+        [Key::H, Key::G, Key::Y, Key::F4],
+        [Key::Num6, Key::Num7, Key::V, Key::B],
+    ],
+    [
+        [Key::D, Key::R, Key::T, Key::F3],
+        [Key::Num4, Key::Num5, Key::C, Key::F],
+    ],
+    [
+        [Key::A, Key::S, Key::W, Key::F2],
+        [Key::Num3, Key::E, Key::Z, Key::X],
+    ],
+    [
+        [Key::Ctr, Key::Q, Key::Esc, Key::F1],
+        [Key::Num2, Key::Num1, Key::Grph, Key::LeftShift],
+    ],
+    [
+        [Key::Left, Key::Right, Key::Up, Key::ClrHome],
+        [Key::Ins, Key::Del, Key::Space, Key::Down],
+    ],
+];
+
+/// The Family BASIC keyboard, which plugs into the expansion port, with a data recorder plugged
+/// into it.
+/// Writes to $4016 scan the key matrix: OUT0 returns to the first row, OUT1 selects which half of
+/// the row is read, and switching back to the first half moves on to the next row. OUT2 enables
+/// the keyboard, and is also the data recorder's output.
+pub struct FamilyBasicKeyboard {
+    enabled: bool,
+    row: usize,
+    column: usize,
+    keys: Keys,
+    data_recorder: DataRecorder,
+}
+
+impl FamilyBasicKeyboard {
+    pub fn new() -> Self {
+        FamilyBasicKeyboard {
+            enabled: false,
+            row: 0,
+            column: 0,
+            keys: Keys::default(),
+            data_recorder: DataRecorder::new(),
+        }
+    }
+}
+
+impl Default for FamilyBasicKeyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDevice for FamilyBasicKeyboard {
+    fn write(&mut self, out: u8) {
+        let column: usize = usize::from(out.is_bit_set(1));
+        self.enabled = out.is_bit_set(2);
+        self.data_recorder.set_output(self.enabled);
+        if self.enabled {
+            if self.column == 1 && column == 0 {
+                // there's a tenth row with no keys
+                self.row = (self.row + 1) % 10;
+            }
+            if out.is_bit_set(0) {
+                self.row = 0;
+            }
+        }
+        self.column = column;
+    }
+
+    fn read(&self, register: Port, _beam: Beam) -> u8 {
+        match register {
+            Port::One => u8::from(self.data_recorder.input()) << 1,
+            _ if !self.enabled => 0,
+            _ => match MATRIX.get(self.row) {
+                // pressed keys read as 0
+                Some(row) => row[self.column]
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &key)| !self.keys.is_pressed(key))
+                    .fold(0, |result, (i, _)| result | (2 << i)),
+                None => 0x1E,
+            },
+        }
+    }
+
+    fn update(&mut self, input: &Input) {
+        self.keys = input.keys;
+    }
+
+    fn reset(&mut self) {
+        self.enabled = false;
+        self.row = 0;
+        self.column = 0;
+    }
+
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::FamilyBasicKeyboard
+    }
+
+    fn cpu_cycle(&mut self) {
+        self.data_recorder.cpu_cycle();
+    }
+
+    fn data_recorder(&mut self) -> Option<&mut DataRecorder> {
+        Some(&mut self.data_recorder)
+    }
+}
+
+/// The held keys come from the frontend, so only the scanning state and the data recorder are
+/// saved
+impl Snapshot for FamilyBasicKeyboard {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_usize(self.row);
+        writer.write_usize(self.column);
+        self.data_recorder.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.enabled = reader.read_bool()?;
+        self.row = reader.read_usize()?;
+        self.column = reader.read_usize()?;
+        if self.row >= 10 || self.column >= 2 {
+            return Err(SaveStateError::InvalidData);
+        }
+        self.data_recorder.load(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::cpu::Memory;
+    use crate::state::io::Controller;
+    use crate::state::NES;
+
+    /// Scans the whole matrix the way Family BASIC does, returning the key bits of each half row
+    fn scan(nes: &mut NES) -> Vec<u8> {
+        nes.set_mem(0x4016, 0x05);
+        (0..10)
+            .flat_map(|_| {
+                nes.set_mem(0x4016, 0x04);
+                let first: u8 = nes.get_mem(0x4017) & 0x1E;
+                nes.set_mem(0x4016, 0x06);
+                let second: u8 = nes.get_mem(0x4017) & 0x1E;
+                vec![first, second]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matrix() {
+        let mut nes = NES::mock();
+        nes.set_device(Port::Expansion, Some(Box::new(FamilyBasicKeyboard::new())));
+        let mut input = Input::default();
+        input.keys.press(Key::Return);
+        input.keys.press(Key::A);
+        input.keys.press(Key::X);
+        input.keys.press(Key::Down);
+        nes.update_input(&input);
+        let mut expected: Vec<u8> = vec![0x1E; 20];
+        expected[0] = 0x16;
+        expected[12] = 0x1C;
+        expected[13] = 0x0E;
+        expected[17] = 0x0E;
+        assert_eq!(scan(&mut nes), expected);
+
+        // a disabled keyboard reads as 0
+        nes.set_mem(0x4016, 0);
+        assert_eq!(nes.get_mem(0x4017) & 0x1E, 0);
+    }
+}
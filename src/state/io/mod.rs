@@ -1,34 +1,106 @@
+mod data_recorder;
+mod device;
+mod four_players;
+mod keyboard;
+mod power_pad;
+mod standard;
+mod vaus;
+mod zapper;
+
 use super::NES;
-use crate::bitops::BitOps;
-use sdl2::keyboard::{KeyboardState, Scancode};
-use std::cell::Cell;
+use crate::ppu::Pixel;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+pub use data_recorder::{DataRecorder, TapeState};
+pub use device::{Beam, DeviceKind, ExpansionDevice, Input, InputDevice, Paddle, Pointer};
+pub use four_players::{FamicomFourPlayers, FourScore};
+pub use keyboard::{FamilyBasicKeyboard, Key, Keys};
+pub use power_pad::{PowerPad, PowerPadSide};
+pub use standard::{Buttons, StandardController, Turbo};
+pub use vaus::{Vaus, VausModel};
+pub use zapper::Zapper;
 
 pub trait Controller {
-    fn update_controller(&mut self, keyboard: KeyboardState);
+    /// Passes the frontend's input to every device that is plugged in
+    fn update_input(&mut self, input: &Input);
+    /// Plugs a device into the port, replacing the one that was there
+    fn set_device(&mut self, port: Port, device: Option<Box<dyn InputDevice>>);
+    /// Shows a pixel the PPU has output to every device that senses light
+    fn observe_pixel(&mut self, pixel: &Pixel);
+    /// Tells every device that senses light that the PPU has started a new frame
+    fn start_frame(&mut self);
+    /// Runs the devices that keep time for a CPU cycle
+    fn devices_cycle(&mut self);
+    /// The first data recorder plugged in, if there is one
+    fn data_recorder(&mut self) -> Option<&mut DataRecorder>;
+}
+
+/// The ports input devices can be plugged into
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Port {
+    /// The first controller port, read through $4016
+    One,
+    /// The second controller port, read through $4017
+    Two,
+    /// The Famicom's expansion port, read through both $4016 and $4017
+    Expansion,
+}
+
+impl Port {
+    pub const ALL: [Port; 3] = [Port::One, Port::Two, Port::Expansion];
+
+    fn index(self) -> usize {
+        match self {
+            Port::One => 0,
+            Port::Two => 1,
+            Port::Expansion => 2,
+        }
+    }
 }
 
 /// Represents internal IO state
 pub struct IOState {
-    strobe_on: bool,
-    buttons: [bool; 8],
-    index: Cell<usize>,
+    ports: [Option<Box<dyn InputDevice>>; 3],
 }
 
 impl Controller for NES {
-    fn update_controller(&mut self, keyboard: KeyboardState) {
-        if !self.io.strobe_on {
-            return;
+    fn update_input(&mut self, input: &Input) {
+        for device in self.io.ports.iter_mut().flatten() {
+            device.update(input);
+        }
+    }
+
+    fn set_device(&mut self, port: Port, device: Option<Box<dyn InputDevice>>) {
+        self.io.ports[port.index()] = device;
+    }
+
+    fn observe_pixel(&mut self, pixel: &Pixel) {
+        for device in self.io.ports.iter_mut().flatten() {
+            if device.senses_light() {
+                device.observe_pixel(pixel);
+            }
+        }
+    }
+
+    fn start_frame(&mut self) {
+        for device in self.io.ports.iter_mut().flatten() {
+            if device.senses_light() {
+                device.start_frame();
+            }
+        }
+    }
+
+    fn devices_cycle(&mut self) {
+        for device in self.io.ports.iter_mut().flatten() {
+            device.cpu_cycle();
         }
-        self.io.buttons = [
-            keyboard.is_scancode_pressed(Scancode::Z),
-            keyboard.is_scancode_pressed(Scancode::X),
-            keyboard.is_scancode_pressed(Scancode::Space),
-            keyboard.is_scancode_pressed(Scancode::Return),
-            keyboard.is_scancode_pressed(Scancode::Up),
-            keyboard.is_scancode_pressed(Scancode::Down),
-            keyboard.is_scancode_pressed(Scancode::Left),
-            keyboard.is_scancode_pressed(Scancode::Right),
-        ];
+    }
+
+    fn data_recorder(&mut self) -> Option<&mut DataRecorder> {
+        self.io
+            .ports
+            .iter_mut()
+            .flatten()
+            .find_map(|device| device.data_recorder())
     }
 }
 
@@ -38,30 +110,139 @@ impl IOState {
         Self::new()
     }
 
+    /// A standard controller is plugged into each controller port, and the expansion port is
+    /// empty
     pub fn new() -> Self {
         IOState {
-            strobe_on: false,
-            buttons: [false; 8],
-            index: Cell::new(0),
+            ports: [
+                Some(Box::new(StandardController::new(0))),
+                Some(Box::new(StandardController::new(1))),
+                None,
+            ],
+        }
+    }
+
+    /// Returns to the power-on state, keeping the devices plugged in
+    pub fn power_cycle(&mut self) {
+        for device in self.ports.iter_mut().flatten() {
+            device.reset();
         }
     }
 
+    /// $4016 sets the OUT lines of every device
     pub fn write(&mut self, val: u8) {
-        self.strobe_on = val.is_bit_set(0);
-        if self.strobe_on {
-            self.index.set(0);
+        for device in self.ports.iter_mut().flatten() {
+            device.write(val & 0x07);
         }
     }
 
-    pub fn read(&self) -> u8 {
-        if self.index.get() >= 8 {
-            return 1;
+    /// Reads the controller port's register ($4016 or $4017). The expansion port shares both.
+    pub fn read(&self, register: Port, beam: Beam) -> u8 {
+        [register, Port::Expansion]
+            .iter()
+            .filter_map(|port| self.ports[port.index()].as_ref())
+            .fold(0, |result, device| result | device.read(register, beam))
+            & 0x1F
+    }
+}
+
+/// The devices themselves are chosen by the frontend, so a state can only be loaded with the same
+/// devices plugged in
+impl Snapshot for IOState {
+    fn save(&self, writer: &mut StateWriter) {
+        for device in &self.ports {
+            match device {
+                Some(device) => {
+                    writer.write_u8(device.kind().tag());
+                    device.save(writer);
+                }
+                None => writer.write_u8(0),
+            }
         }
-        let result: u8 = if self.buttons[self.index.get()] { 1 } else { 0 };
-        self.index.set(self.index.get() + 1);
-        if self.strobe_on {
-            self.index.set(0);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for device in &mut self.ports {
+            let tag: u8 = reader.read_u8()?;
+            match device {
+                Some(device) if tag == device.kind().tag() => device.load(reader)?,
+                None if tag == 0 => {}
+                _ => return Err(SaveStateError::InvalidData),
+            }
         }
-        result
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::cpu::Memory;
+
+    #[test]
+    fn test_buttons() {
+        let mut nes = NES::mock();
+        nes.update_input(&Input {
+            buttons: [
+                Buttons {
+                    a: true,
+                    start: true,
+                    ..Buttons::default()
+                },
+                Buttons {
+                    b: true,
+                    right: true,
+                    ..Buttons::default()
+                },
+                Buttons::default(),
+                Buttons::default(),
+            ],
+            ..Input::default()
+        });
+        nes.set_mem(0x4016, 1);
+        nes.set_mem(0x4016, 0);
+        let report: Vec<u8> = (0..8)
+            .map(|_| nes.io.read(Port::One, Beam::default()))
+            .collect();
+        assert_eq!(report, vec![1, 0, 0, 1, 0, 0, 0, 0]);
+        let report: Vec<u8> = (0..8).map(|_| nes.get_mem(0x4017) & 1).collect();
+        assert_eq!(report, vec![0, 1, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_disconnected() {
+        let mut nes = NES::mock();
+        nes.set_device(Port::Two, None);
+        nes.set_mem(0x4016, 1);
+        nes.set_mem(0x4016, 0);
+        assert_eq!(nes.io.read(Port::Two, Beam::default()), 0);
+    }
+
+    #[test]
+    fn test_expansion_port() {
+        let mut nes = NES::mock();
+        nes.set_device(Port::One, None);
+        nes.set_device(
+            Port::Expansion,
+            Some(Box::new(StandardController::expansion(0))),
+        );
+        nes.update_input(&Input {
+            buttons: [
+                Buttons {
+                    a: true,
+                    ..Buttons::default()
+                },
+                Buttons::default(),
+                Buttons::default(),
+                Buttons::default(),
+            ],
+            ..Input::default()
+        });
+        nes.set_mem(0x4016, 1);
+        nes.set_mem(0x4016, 0);
+        // reading $4017 doesn't clock the expansion port's controller
+        assert_eq!(nes.io.read(Port::Two, Beam::default()), 0);
+        assert_eq!(nes.io.read(Port::One, Beam::default()), 0b10);
+        assert_eq!(nes.io.read(Port::One, Beam::default()), 0);
     }
 }
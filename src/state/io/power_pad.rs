@@ -0,0 +1,161 @@
+use super::device::{Beam, DeviceKind, Input, InputDevice};
+use super::Port;
+use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use std::cell::Cell;
+
+/// The buttons reported on D3, numbered from 0 as on side B
+const D3_ORDER: [usize; 8] = [1, 0, 4, 8, 5, 9, 10, 6];
+/// The buttons reported on D4, after which it reports 1s like D3
+const D4_ORDER: [usize; 4] = [3, 2, 11, 7];
+
+/// Which side of the mat is face up
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PowerPadSide {
+    /// Eight unnumbered buttons. This side mirrors side B's grid left to right, without the corner
+    /// buttons.
+    A,
+    /// Twelve numbered buttons in a 3x4 grid
+    B,
+}
+
+/// The Power Pad, a floor mat that plugs into the second controller port. Strobing it latches its
+/// twelve buttons into two shift registers, which are read on D3 and D4 of $4017.
+pub struct PowerPad {
+    side: PowerPadSide,
+    strobe_on: bool,
+    held: [bool; 12],
+    d3: Cell<u8>,
+    d4: Cell<u8>,
+}
+
+impl PowerPad {
+    pub fn new(side: PowerPadSide) -> Self {
+        PowerPad {
+            side,
+            strobe_on: false,
+            held: [false; 12],
+            d3: Cell::new(0xFF),
+            d4: Cell::new(0xFF),
+        }
+    }
+
+    fn latch(&self) {
+        let pack = |order: &[usize]| {
+            order.iter().enumerate().fold(0, |result, (i, &button)| {
+                result | (u8::from(self.held[button]) << i)
+            })
+        };
+        self.d3.set(pack(&D3_ORDER));
+        self.d4.set(pack(&D4_ORDER) | 0xF0);
+    }
+}
+
+impl InputDevice for PowerPad {
+    fn write(&mut self, out: u8) {
+        self.strobe_on = out.is_bit_set(0);
+        if self.strobe_on {
+            self.latch();
+        }
+    }
+
+    fn read(&self, _register: Port, _beam: Beam) -> u8 {
+        let result: u8 = ((self.d4.get() & 1) << 4) | ((self.d3.get() & 1) << 3);
+        if self.strobe_on {
+            self.latch();
+        } else {
+            self.d3.set((self.d3.get() >> 1) | 0x80);
+            self.d4.set((self.d4.get() >> 1) | 0x80);
+        }
+        result
+    }
+
+    fn update(&mut self, input: &Input) {
+        for (i, held) in self.held.iter_mut().enumerate() {
+            let (row, column) = (i / 4, i % 4);
+            *held = match self.side {
+                PowerPadSide::B => input.mat[i],
+                // side A has no corners
+                PowerPadSide::A if column == 0 || column == 3 => {
+                    row == 1 && input.mat[row * 4 + 3 - column]
+                }
+                PowerPadSide::A => input.mat[row * 4 + 3 - column],
+            };
+        }
+        if self.strobe_on {
+            self.latch();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.strobe_on = false;
+        self.d3.set(0xFF);
+        self.d4.set(0xFF);
+    }
+
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::PowerPad(self.side)
+    }
+}
+
+/// The held buttons come from the frontend, so only the shift registers are saved
+impl Snapshot for PowerPad {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.strobe_on);
+        writer.write_u8(self.d3.get());
+        writer.write_u8(self.d4.get());
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.strobe_on = reader.read_bool()?;
+        self.d3.set(reader.read_u8()?);
+        self.d4.set(reader.read_u8()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::cpu::Memory;
+    use crate::state::io::Controller;
+    use crate::state::NES;
+
+    /// Steps on the squares, numbered from 1 in rows of four as seen from the player
+    fn step_on(nes: &mut NES, squares: &[usize]) {
+        let mut input = Input::default();
+        for &square in squares {
+            input.mat[square - 1] = true;
+        }
+        nes.update_input(&input);
+    }
+
+    fn read(nes: &mut NES) -> Vec<u8> {
+        nes.set_mem(0x4016, 1);
+        nes.set_mem(0x4016, 0);
+        (0..9).map(|_| nes.get_mem(0x4017) & 0x18).collect()
+    }
+
+    #[test]
+    fn test_side_a() {
+        let mut nes = NES::mock();
+        nes.set_device(Port::Two, Some(Box::new(PowerPad::new(PowerPadSide::A))));
+        // the corners aren't there, and the rest are mirrored
+        step_on(&mut nes, &[1, 2, 8, 10]);
+        assert_eq!(
+            read(&mut nes),
+            vec![0x00, 0x10, 0x08, 0x00, 0x10, 0x10, 0x18, 0x10, 0x18]
+        );
+    }
+
+    #[test]
+    fn test_side_b() {
+        let mut nes = NES::mock();
+        nes.set_device(Port::Two, Some(Box::new(PowerPad::new(PowerPadSide::B))));
+        step_on(&mut nes, &[1, 3, 10, 12]);
+        assert_eq!(
+            read(&mut nes),
+            vec![0x00, 0x18, 0x10, 0x00, 0x10, 0x18, 0x10, 0x10, 0x18]
+        );
+    }
+}
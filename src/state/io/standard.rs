@@ -0,0 +1,172 @@
+use super::device::{Beam, DeviceKind, Input, InputDevice};
+use super::Port;
+use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use std::cell::Cell;
+
+/// The buttons of a standard controller.
+/// Frontends translate whatever input they have into this.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Buttons {
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    /// Presses A repeatedly while held
+    pub turbo_a: bool,
+    /// Presses B repeatedly while held
+    pub turbo_b: bool,
+}
+
+impl Buttons {
+    /// Returns the buttons in the order they are reported by the controller
+    pub(super) fn to_report(self) -> [bool; 8] {
+        [
+            self.a,
+            self.b,
+            self.select,
+            self.start,
+            self.up,
+            self.down,
+            self.left,
+            self.right,
+        ]
+    }
+}
+
+/// How fast turbo buttons press and release their button
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Turbo {
+    /// The number of frames turbo A keeps A pressed, and then released, for
+    pub a: u32,
+    /// The number of frames turbo B keeps B pressed, and then released, for
+    pub b: u32,
+}
+
+impl Turbo {
+    /// Returns the buttons with turbo A and B turned into presses of A and B during the frame.
+    /// Turbo follows the frame count rather than the clock, so it replays the same way every time.
+    pub fn apply(self, buttons: Buttons, frame: u64) -> Buttons {
+        let pressed = |rate: u32| (frame / u64::from(rate.max(1))).is_multiple_of(2);
+        Buttons {
+            a: buttons.a || (buttons.turbo_a && pressed(self.a)),
+            b: buttons.b || (buttons.turbo_b && pressed(self.b)),
+            turbo_a: false,
+            turbo_b: false,
+            ..buttons
+        }
+    }
+}
+
+impl Default for Turbo {
+    fn default() -> Self {
+        Turbo { a: 2, b: 2 }
+    }
+}
+
+/// A standard controller, which reports its buttons one at a time through a shift register
+pub struct StandardController {
+    /// Which of the frontend's controllers this one follows
+    player: usize,
+    /// Set when the controller is plugged into the expansion port
+    expansion: bool,
+    strobe_on: bool,
+    held: Buttons,
+    buttons: [bool; 8],
+    index: Cell<usize>,
+}
+
+impl StandardController {
+    pub fn new(player: usize) -> Self {
+        StandardController {
+            player,
+            expansion: false,
+            strobe_on: false,
+            held: Buttons::default(),
+            buttons: [false; 8],
+            index: Cell::new(0),
+        }
+    }
+
+    /// A controller plugged into the Famicom's expansion port. It's read on D1 of $4016, and
+    /// reading $4017 leaves it alone.
+    pub fn expansion(player: usize) -> Self {
+        StandardController {
+            expansion: true,
+            ..Self::new(player)
+        }
+    }
+
+    /// Returns the next button in the shift register
+    fn shift_out(&self) -> u8 {
+        if self.index.get() >= 8 {
+            return 1;
+        }
+        let result: u8 = if self.buttons[self.index.get()] { 1 } else { 0 };
+        self.index.set(self.index.get() + 1);
+        if self.strobe_on {
+            self.index.set(0);
+        }
+        result
+    }
+}
+
+impl InputDevice for StandardController {
+    fn write(&mut self, out: u8) {
+        self.strobe_on = out.is_bit_set(0);
+        if self.strobe_on {
+            // the shift register reloads from the buttons while strobe is on
+            self.buttons = self.held.to_report();
+            self.index.set(0);
+        }
+    }
+
+    fn read(&self, register: Port, _beam: Beam) -> u8 {
+        match (self.expansion, register) {
+            (false, _) => self.shift_out(),
+            (true, Port::One) => self.shift_out() << 1,
+            (true, _) => 0,
+        }
+    }
+
+    fn update(&mut self, input: &Input) {
+        self.held = input.buttons.get(self.player).copied().unwrap_or_default();
+        if self.strobe_on {
+            self.buttons = self.held.to_report();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.strobe_on = false;
+        self.buttons = [false; 8];
+        self.index.set(0);
+    }
+
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::StandardController
+    }
+}
+
+/// The held buttons come from the frontend, so only the shift register is saved
+impl Snapshot for StandardController {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.strobe_on);
+        for &button in &self.buttons {
+            writer.write_bool(button);
+        }
+        writer.write_usize(self.index.get());
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.strobe_on = reader.read_bool()?;
+        for button in &mut self.buttons {
+            *button = reader.read_bool()?;
+        }
+        self.index.set(reader.read_usize()?);
+        Ok(())
+    }
+}
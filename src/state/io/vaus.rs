@@ -0,0 +1,157 @@
+use super::device::{Beam, DeviceKind, Input, InputDevice};
+use super::Port;
+use crate::bitops::BitOps;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+use std::cell::Cell;
+
+/// The potentiometer's value with the knob turned all the way left
+const MIN_POSITION: u8 = 0x62;
+/// The potentiometer's value with the knob turned all the way right
+const MAX_POSITION: u8 = 0xF2;
+
+/// Which version of the Arkanoid controller, which decides how it's wired
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VausModel {
+    /// Plugs into the second controller port. The potentiometer is read on D4 of $4017, and the
+    /// button on D3.
+    Nes,
+    /// Plugs into the expansion port. The potentiometer is read on D1 of $4017, and the button on
+    /// D1 of $4016.
+    Famicom,
+}
+
+/// The Arkanoid controller. Strobing it latches the potentiometer's position, which is then shifted
+/// out inverted, most significant bit first. The button is read directly.
+pub struct Vaus {
+    model: VausModel,
+    strobe_on: bool,
+    position: u8,
+    button: bool,
+    /// The inverted position, shifting left with ones filling in behind
+    shift: Cell<u8>,
+}
+
+impl Vaus {
+    pub fn new(model: VausModel) -> Self {
+        Vaus {
+            model,
+            strobe_on: false,
+            position: MIN_POSITION,
+            button: false,
+            shift: Cell::new(0xFF),
+        }
+    }
+
+    /// Returns the next bit of the potentiometer's position
+    fn shift_out(&self) -> u8 {
+        let bit: u8 = self.shift.get() >> 7;
+        if self.strobe_on {
+            self.shift.set(!self.position);
+        } else {
+            self.shift.set((self.shift.get() << 1) | 1);
+        }
+        bit
+    }
+}
+
+impl InputDevice for Vaus {
+    fn write(&mut self, out: u8) {
+        self.strobe_on = out.is_bit_set(0);
+        if self.strobe_on {
+            self.shift.set(!self.position);
+        }
+    }
+
+    fn read(&self, register: Port, _beam: Beam) -> u8 {
+        let button: u8 = u8::from(self.button);
+        match (self.model, register) {
+            (VausModel::Nes, _) => (self.shift_out() << 4) | (button << 3),
+            (VausModel::Famicom, Port::Two) => self.shift_out() << 1,
+            (VausModel::Famicom, _) => button << 1,
+        }
+    }
+
+    fn update(&mut self, input: &Input) {
+        let range: f32 = f32::from(MAX_POSITION - MIN_POSITION);
+        let offset: f32 = (input.paddle.position.clamp(0.0, 1.0) * range).round();
+        self.position = MIN_POSITION + offset as u8;
+        self.button = input.paddle.button;
+    }
+
+    fn reset(&mut self) {
+        self.strobe_on = false;
+        self.shift.set(0xFF);
+    }
+
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::Vaus(self.model)
+    }
+}
+
+/// The knob and button come from the frontend, so only the shift register is saved
+impl Snapshot for Vaus {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.strobe_on);
+        writer.write_u8(self.shift.get());
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.strobe_on = reader.read_bool()?;
+        self.shift.set(reader.read_u8()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::cpu::Memory;
+    use crate::state::io::{Controller, Paddle};
+    use crate::state::NES;
+
+    fn paddle(position: f32) -> Input {
+        Input {
+            paddle: Paddle {
+                position,
+                button: true,
+            },
+            ..Input::default()
+        }
+    }
+
+    /// Reads the position a game would see, by reading 8 bits and inverting them
+    fn read_position(nes: &mut NES, addr: u16, bit: u8) -> u8 {
+        nes.set_mem(0x4016, 1);
+        nes.set_mem(0x4016, 0);
+        !(0..8).fold(0, |result, _| {
+            (result << 1) | ((nes.get_mem(addr) >> bit) & 1)
+        })
+    }
+
+    #[test]
+    fn test_nes() {
+        let mut nes = NES::mock();
+        nes.set_device(Port::Two, Some(Box::new(Vaus::new(VausModel::Nes))));
+        nes.update_input(&paddle(0.0));
+        assert_eq!(read_position(&mut nes, 0x4017, 4), MIN_POSITION);
+        assert_eq!(nes.get_mem(0x4017) & 0x18, 0x18);
+        nes.update_input(&paddle(1.0));
+        assert_eq!(read_position(&mut nes, 0x4017, 4), MAX_POSITION);
+        nes.update_input(&paddle(0.5));
+        assert_eq!(read_position(&mut nes, 0x4017, 4), 0xAA);
+    }
+
+    #[test]
+    fn test_famicom() {
+        let mut nes = NES::mock();
+        nes.set_device(
+            Port::Expansion,
+            Some(Box::new(Vaus::new(VausModel::Famicom))),
+        );
+        nes.update_input(&paddle(0.25));
+        assert_eq!(read_position(&mut nes, 0x4017, 1), 0x86);
+        // the button is on $4016, which doesn't shift the position out
+        assert_eq!(nes.get_mem(0x4016) & 0x02, 0x02);
+        assert_eq!(nes.get_mem(0x4017) & 0x02, 0x02);
+    }
+}
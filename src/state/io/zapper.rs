@@ -0,0 +1,166 @@
+use super::device::{Beam, DeviceKind, Input, InputDevice};
+use super::Port;
+use crate::ppu::display::Display;
+use crate::ppu::Pixel;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+/// How far from the aim the Zapper's sensor can see, in pixels
+const SENSOR_RADIUS: usize = 3;
+
+/// The sensor keeps reporting light for a while after the beam has passed, which games rely on
+const LIGHT_SCANLINES: usize = 20;
+
+/// Pixels at least this bright trigger the sensor
+const BRIGHTNESS_THRESHOLD: u32 = 0xC0;
+
+/// The Zapper light gun. It reports whether its sensor sees light on D3 (0 when it does) and
+/// whether the trigger is pulled on D4.
+pub struct Zapper {
+    aim: Option<(usize, usize)>,
+    trigger: bool,
+    /// For each scanline of the current frame, the first dot where the sensor saw light
+    light: [Option<usize>; Display::HEIGHT],
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Zapper {
+            aim: None,
+            trigger: false,
+            light: [None; Display::HEIGHT],
+        }
+    }
+
+    fn sees_light(&self, beam: Beam) -> bool {
+        let first: usize = beam.scanline.saturating_sub(LIGHT_SCANLINES);
+        let last: usize = beam.scanline.min(Display::HEIGHT - 1);
+        (first..=last).any(|scanline| match self.light[scanline] {
+            // the beam hasn't reached that pixel yet
+            Some(x) if scanline == beam.scanline => x < beam.dot,
+            Some(_) => true,
+            None => false,
+        })
+    }
+}
+
+impl Default for Zapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputDevice for Zapper {
+    fn write(&mut self, _out: u8) {}
+
+    fn read(&self, _register: Port, beam: Beam) -> u8 {
+        let light: u8 = if self.sees_light(beam) { 0 } else { 1 << 3 };
+        let trigger: u8 = if self.trigger { 1 << 4 } else { 0 };
+        light | trigger
+    }
+
+    fn update(&mut self, input: &Input) {
+        self.aim = input.pointer.position;
+        self.trigger = input.pointer.trigger;
+    }
+
+    fn reset(&mut self) {
+        self.light = [None; Display::HEIGHT];
+    }
+
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::Zapper
+    }
+
+    fn senses_light(&self) -> bool {
+        true
+    }
+
+    fn observe_pixel(&mut self, pixel: &Pixel) {
+        let (x, y) = match self.aim {
+            Some(aim) => aim,
+            None => return,
+        };
+        if pixel.x.abs_diff(x) > SENSOR_RADIUS || pixel.y.abs_diff(y) > SENSOR_RADIUS {
+            return;
+        }
+        let color = pixel.color;
+        let brightness: u32 =
+            (299 * u32::from(color.r) + 587 * u32::from(color.g) + 114 * u32::from(color.b)) / 1000;
+        if brightness >= BRIGHTNESS_THRESHOLD && self.light[pixel.y].is_none() {
+            self.light[pixel.y] = Some(pixel.x);
+        }
+    }
+
+    fn start_frame(&mut self) {
+        self.light = [None; Display::HEIGHT];
+    }
+}
+
+/// The aim and trigger come from the frontend, so only the light seen this frame is saved
+impl Snapshot for Zapper {
+    fn save(&self, writer: &mut StateWriter) {
+        for light in self.light.iter() {
+            writer.write_u16(light.map_or(u16::MAX, |x| x as u16));
+        }
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        for light in self.light.iter_mut() {
+            *light = match reader.read_u16()? {
+                u16::MAX => None,
+                x if usize::from(x) < Display::WIDTH => Some(usize::from(x)),
+                _ => return Err(SaveStateError::InvalidData),
+            };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::Color;
+    use crate::state::io::Pointer;
+
+    fn draw(zapper: &mut Zapper, x: usize, y: usize, brightness: u8) {
+        zapper.observe_pixel(&Pixel {
+            x,
+            y,
+            color: Color {
+                r: brightness,
+                g: brightness,
+                b: brightness,
+            },
+        });
+    }
+
+    fn read(zapper: &Zapper, scanline: usize, dot: usize) -> u8 {
+        zapper.read(Port::Two, Beam { scanline, dot })
+    }
+
+    #[test]
+    fn test_light() {
+        let mut zapper = Zapper::new();
+        zapper.update(&Input {
+            pointer: Pointer {
+                position: Some((100, 50)),
+                trigger: true,
+            },
+            ..Input::default()
+        });
+        draw(&mut zapper, 50, 50, 0xFF);
+        draw(&mut zapper, 101, 49, 0x20);
+        draw(&mut zapper, 102, 51, 0xFF);
+        // no light, trigger pulled
+        assert_eq!(read(&zapper, 40, 0), 0x18);
+        assert_eq!(read(&zapper, 51, 100), 0x18);
+        // light
+        assert_eq!(read(&zapper, 51, 103), 0x10);
+        assert_eq!(read(&zapper, 51 + LIGHT_SCANLINES, 0), 0x10);
+        assert_eq!(read(&zapper, 52 + LIGHT_SCANLINES, 0), 0x18);
+
+        // a new frame starts in the dark
+        zapper.start_frame();
+        assert_eq!(read(&zapper, 60, 0), 0x18);
+    }
+}
@@ -1,13 +1,19 @@
 pub mod apu;
 pub mod cpu;
+pub mod hooks;
 pub mod io;
+pub mod power_on;
 pub mod ppu;
+pub mod snapshot;
 
 use crate::cartridge::Mapper;
 use apu::APUState;
-use cpu::CPUState;
+use cpu::{CPUState, Interrupt, Registers};
+use hooks::HookState;
 use io::IOState;
+use power_on::PowerOnState;
 use ppu::PPUState;
+use snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 
 #[cfg(test)]
 use crate::cartridge::mapper0::Mapper0;
@@ -29,11 +35,24 @@ use crate::cartridge::mapper0::Mapper0;
 /// argument the `CPU` trait and not the `NES` struct.
 
 /// Trait representing the CPU
-pub trait CPU: cpu::Registers + cpu::Memory + cpu::Stack + cpu::OAMDMA + cpu::Interrupt {}
+pub trait CPU:
+    cpu::Registers
+    + cpu::Memory
+    + cpu::Stack
+    + cpu::OAMDMA
+    + cpu::DMCDMA
+    + cpu::Interrupt
+    + cpu::Clock
+    + cpu::Execution
+{
+}
 impl CPU for NES {}
 
 /// Trait representing the PPU
-pub trait PPU: ppu::Memory + ppu::Cycle + ppu::VBlank + ppu::Sprites + ppu::Background {}
+pub trait PPU:
+    ppu::Memory + ppu::Cycle + ppu::VBlank + ppu::Sprites + ppu::Background + ppu::Rendering
+{
+}
 impl PPU for NES {}
 
 /// The struct holding all of the NES's internal state.
@@ -42,6 +61,8 @@ pub struct NES {
     ppu: PPUState,
     apu: APUState,
     io: IOState,
+    hooks: HookState,
+    power_on_state: PowerOnState,
     cartridge: Box<dyn Mapper>,
 }
 
@@ -53,17 +74,147 @@ impl NES {
             ppu: PPUState::mock(),
             apu: APUState::mock(),
             io: IOState::mock(),
+            hooks: HookState::mock(),
+            power_on_state: PowerOnState::Zeros,
             cartridge: Box::new(Mapper0::mock()),
         }
     }
 
     pub fn new(cartridge: Box<dyn Mapper>) -> Self {
-        NES {
+        Self::with_power_on_state(cartridge, PowerOnState::Zeros)
+    }
+
+    /// Creates a NES whose RAM is initialized according to `power_on_state`.
+    /// The same state is used again whenever the console is power cycled.
+    pub fn with_power_on_state(cartridge: Box<dyn Mapper>, power_on_state: PowerOnState) -> Self {
+        let mut nes = NES {
             cpu: CPUState::new(),
             ppu: PPUState::new(),
             apu: APUState::new(),
             io: IOState::new(),
+            hooks: HookState::new(),
+            power_on_state,
             cartridge,
+        };
+        nes.fill_ram();
+        nes
+    }
+
+    /// Fills internal RAM, PRG-RAM, OAM, palette RAM and the nametables
+    fn fill_ram(&mut self) {
+        let mut filler = self.power_on_state.filler();
+        self.cpu.fill_ram(&mut filler);
+        self.ppu.fill_ram(&mut filler);
+        if let Some(prg_ram) = self.cartridge.as_cpu_mapper_mut().get_prg_ram_mut() {
+            filler.fill(prg_ram);
+        }
+    }
+
+    /// Presses the reset button.
+    /// The PPU, APU and mapper are reset immediately, while the CPU runs its RESET sequence once
+    /// the current instruction completes.
+    pub fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+        self.cartridge.as_cpu_mapper_mut().reset();
+        self.trigger_reset();
+    }
+
+    /// Turns the console off and on again.
+    /// Everything but the cartridge and the controllers is returned to its power-on state.
+    pub fn power_cycle(&mut self) {
+        self.cpu = CPUState::new();
+        self.ppu = PPUState::new();
+        self.apu = APUState::new();
+        self.io.power_cycle();
+        self.cartridge.as_cpu_mapper_mut().reset();
+        self.fill_ram();
+        // the stack pointer starts at 0, and the RESET sequence brings it down to $FD
+        self.set_s(0);
+        self.trigger_reset();
+    }
+
+    /// The CRC32 of the inserted cartridge's ROM
+    pub fn get_rom_hash(&self) -> u32 {
+        self.cartridge.get_rom().crc32()
+    }
+}
+
+/// Hooks and the power-on state belong to the frontend, so they aren't saved
+impl Snapshot for NES {
+    fn save(&self, writer: &mut StateWriter) {
+        self.cpu.save(writer);
+        self.ppu.save(writer);
+        self.apu.save(writer);
+        self.io.save(writer);
+        self.cartridge.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.cpu.load(reader)?;
+        self.ppu.load(reader)?;
+        self.apu.load(reader)?;
+        self.io.load(reader)?;
+        self.cartridge.load(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu;
+    use crate::state::cpu::{InterruptState, Memory};
+    use crate::state::ppu::VBlank;
+
+    #[test]
+    fn test_reset() {
+        let mut nes = NES::mock();
+        nes.set_mem(0x0000, 0x12);
+        nes.set_mem(0x2000, 0b1000_0000);
+        nes.start_vblank();
+        nes.reset();
+        assert_eq!(nes.poll_interrupts(), InterruptState::Reset);
+        assert_eq!(nes.is_nmi_asserted(), false);
+        // RAM is left alone
+        assert_eq!(nes.get_mem(0x0000), 0x12);
+    }
+
+    #[test]
+    fn test_power_cycle() {
+        let mut nes = NES::mock();
+        nes.set_mem(0x0000, 0x12);
+        nes.power_cycle();
+        assert_eq!(nes.poll_interrupts(), InterruptState::Reset);
+        assert_eq!(nes.get_s(), 0);
+        assert_eq!(nes.get_mem(0x0000), 0);
+    }
+
+    #[test]
+    fn test_power_on_state() {
+        let mut nes = NES::with_power_on_state(Box::new(Mapper0::mock()), PowerOnState::Ones);
+        assert_eq!(nes.get_mem(0x07FF), 0xFF);
+        assert_eq!(nes.get_mem(0x6000), 0xFF);
+        assert_eq!(nes.ppu.oam.memory[0], 0xFF);
+        nes.set_mem(0x07FF, 0);
+        nes.power_cycle();
+        assert_eq!(nes.get_mem(0x07FF), 0xFF);
+    }
+
+    #[test]
+    fn test_power_on_palette() {
+        let mut nes = NES::with_power_on_state(Box::new(Mapper0::mock()), PowerOnState::Ones);
+        nes.set_mem(0x2006, 0x3F);
+        nes.set_mem(0x2006, 0x00);
+        // palette reads aren't buffered
+        assert_eq!(nes.get_mem(0x2007), 0x3F);
+        nes.set_mem(0x2001, 0b0001_1110);
+        let mut pixels: usize = 0;
+        for _ in 0..(341 * 262) {
+            // looking up the color of an unmasked palette entry would panic here
+            if ppu::cycle(&mut nes).is_some() {
+                pixels += 1;
+            }
         }
+        assert_eq!(pixels, 256 * 240);
     }
 }
@@ -0,0 +1,131 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Mixed into `PowerOnState::Random`'s seed, so that small seeds don't start out mostly zeros
+const RANDOM_SEED_MASK: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// The contents of RAM when the console is switched on.
+/// Real hardware powers on with mostly unpredictable garbage in RAM, which some games (and plenty
+/// of homebrew) accidentally depend on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PowerOnState {
+    /// Every byte is $00
+    Zeros,
+    /// Every byte is $FF
+    Ones,
+    /// Four bytes of $00 followed by four bytes of $FF, repeated
+    Alternating,
+    /// Pseudo-random bytes generated from a seed, so that runs are reproducible
+    Random(u64),
+}
+
+impl PowerOnState {
+    /// Returns a `RAMFiller` that fills memory regions one after another with this pattern
+    pub fn filler(self) -> RAMFiller {
+        RAMFiller {
+            state: self,
+            rng: match self {
+                // xorshift gets stuck on 0, so the seed that would start it there is moved
+                PowerOnState::Random(seed) => match seed ^ RANDOM_SEED_MASK {
+                    0 => RANDOM_SEED_MASK,
+                    rng => rng,
+                },
+                _ => 0,
+            },
+        }
+    }
+}
+
+impl FromStr for PowerOnState {
+    type Err = ParsePowerOnStateError;
+
+    /// Parses `zeros`, `ones`, `alternating` or `random:<seed>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zeros" => Ok(PowerOnState::Zeros),
+            "ones" => Ok(PowerOnState::Ones),
+            "alternating" => Ok(PowerOnState::Alternating),
+            _ if s.starts_with("random:") => s["random:".len()..]
+                .parse()
+                .map(PowerOnState::Random)
+                .map_err(|_| ParsePowerOnStateError(s.to_string())),
+            _ => Err(ParsePowerOnStateError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsePowerOnStateError(String);
+
+impl fmt::Display for ParsePowerOnStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid power-on state '{}' (expected zeros, ones, alternating or random:<seed>)",
+            self.0
+        )
+    }
+}
+
+/// Fills memory according to a `PowerOnState`
+pub struct RAMFiller {
+    state: PowerOnState,
+    rng: u64,
+}
+
+impl RAMFiller {
+    pub fn fill(&mut self, memory: &mut [u8]) {
+        for (i, byte) in memory.iter_mut().enumerate() {
+            *byte = match self.state {
+                PowerOnState::Zeros => 0x00,
+                PowerOnState::Ones => 0xFF,
+                PowerOnState::Alternating if (i / 4) % 2 == 0 => 0x00,
+                PowerOnState::Alternating => 0xFF,
+                PowerOnState::Random(_) => self.next_random(),
+            };
+        }
+    }
+
+    fn next_random(&mut self) -> u8 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 32) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        let mut memory = [0x12; 10];
+        PowerOnState::Ones.filler().fill(&mut memory);
+        assert_eq!(memory, [0xFF; 10]);
+        PowerOnState::Alternating.filler().fill(&mut memory);
+        assert_eq!(memory, [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0]);
+        PowerOnState::Zeros.filler().fill(&mut memory);
+        assert_eq!(memory, [0; 10]);
+    }
+
+    #[test]
+    fn test_random() {
+        let mut a = [0; 0x100];
+        let mut b = [0; 0x100];
+        PowerOnState::Random(42).filler().fill(&mut a);
+        PowerOnState::Random(42).filler().fill(&mut b);
+        assert_eq!(a[..], b[..]);
+        PowerOnState::Random(43).filler().fill(&mut b);
+        assert_ne!(a[..], b[..]);
+        PowerOnState::Random(RANDOM_SEED_MASK).filler().fill(&mut b);
+        assert!(b.iter().any(|&byte| byte != 0));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("ones".parse(), Ok(PowerOnState::Ones));
+        assert_eq!("random:7".parse(), Ok(PowerOnState::Random(7)));
+        assert!("random".parse::<PowerOnState>().is_err());
+    }
+}
@@ -4,6 +4,7 @@ use crate::state::NES;
 
 impl Cycle for NES {
     fn update_cycle(&mut self) {
+        self.ppu.current_cycle.dots += 1;
         if self.ppu.current_cycle.tick == CycleStatus::MAX_TICKS
             && self.ppu.current_cycle.scanline == CycleStatus::MAX_SCANLINES
         {
@@ -3,6 +3,8 @@ pub struct CycleStatus {
     pub tick: usize,
     pub scanline: usize,
     pub is_odd_frame: bool,
+    /// The number of dots executed since power-on
+    pub dots: u64,
 }
 
 impl CycleStatus {
@@ -14,6 +16,7 @@ impl CycleStatus {
             tick: 0,
             scanline: Self::MAX_SCANLINES,
             is_odd_frame: false,
+            dots: 0,
         }
     }
 
@@ -1,6 +1,6 @@
 use super::{Background, DebugRegisters, MappedRegisters, Memory, Sprites};
 use crate::bitops::BitOps;
-use crate::state::cpu::Interrupt;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 use crate::state::NES;
 use std::cell::Cell;
 
@@ -36,12 +36,8 @@ impl MappedRegisters for NES {
             .internal_registers
             .t
             .replace_bits(0b11_00000_00000, u16::from(val) << 10);
-        let old_nmi_output = self.ppu.ctrl.should_output_nmi();
         self.ppu.ctrl.set(val);
         self.ppu.open_bus.set(val);
-        if !old_nmi_output && self.ppu.ctrl.should_output_nmi() && self.ppu.status.vblank.get() {
-            self.trigger_nmi();
-        }
     }
     fn get_ppu_mask(&self) -> u8 {
         self.ppu.open_bus.get()
@@ -51,6 +47,10 @@ impl MappedRegisters for NES {
         self.ppu.mask.set(val);
     }
     fn get_ppu_status(&self) -> u8 {
+        // the PPU is about to set the vblank flag, so the read races with it
+        if self.ppu.current_cycle.scanline == 241 && self.ppu.current_cycle.tick == 1 {
+            self.ppu.status.vblank_suppressed.set(true);
+        }
         let mut result = self.ppu.open_bus.get();
         result.assign_bit(7, self.ppu.status.vblank.get());
         result.assign_bit(6, self.ppu.status.sprite0_hit);
@@ -273,10 +273,42 @@ impl PPUMASK {
     pub fn should_render_sprites(&self) -> bool {
         self.register.is_bit_set(4)
     }
+
+    pub fn is_greyscale(&self) -> bool {
+        self.register.is_bit_set(0)
+    }
+
+    /// The red, green and blue emphasis bits, in bits 0-2
+    pub fn get_emphasis(&self) -> u8 {
+        self.register >> 5
+    }
+}
+
+impl Snapshot for PPUCTRL {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.register = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for PPUMASK {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        self.register = reader.read_u8()?;
+        Ok(())
+    }
 }
 
 pub struct PPUSTATUS {
     pub vblank: Cell<bool>,
+    pub vblank_suppressed: Cell<bool>,
     pub sprite0_hit: bool,
     pub sprite_overflow: bool,
 }
@@ -285,6 +317,7 @@ impl PPUSTATUS {
     pub fn new() -> Self {
         PPUSTATUS {
             vblank: Cell::new(false),
+            vblank_suppressed: Cell::new(false),
             sprite0_hit: false,
             sprite_overflow: false,
         }
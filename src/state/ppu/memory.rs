@@ -1,9 +1,35 @@
 use super::Memory;
 use crate::cartridge::NametableMirroring;
+use crate::state::hooks::{AccessKind, BusAccess};
 use crate::state::NES;
 
 impl Memory for NES {
     fn get(&self, addr: u16) -> u8 {
+        let val = self.read_ppu_bus(addr);
+        self.notify_ppu_access(addr, val, AccessKind::Read);
+        val
+    }
+
+    fn set(&mut self, addr: u16, val: u8) {
+        self.write_ppu_bus(addr, val);
+        self.notify_ppu_access(addr, val, AccessKind::Write);
+    }
+}
+
+impl NES {
+    #[inline]
+    fn notify_ppu_access(&self, addr: u16, val: u8, kind: AccessKind) {
+        if self.hooks.is_active() {
+            self.hooks.notify_ppu(BusAccess {
+                addr,
+                val,
+                cycle: self.ppu.current_cycle.dots,
+                kind,
+            });
+        }
+    }
+
+    fn read_ppu_bus(&self, addr: u16) -> u8 {
         match addr {
             0..=0x1FFF => self.cartridge.as_ppu_mapper().get(addr),
             0x2000..=0x3EFF => {
@@ -51,7 +77,8 @@ impl Memory for NES {
                 if (reduced_addr % 4) == 0 {
                     reduced_addr = 0x0;
                 }
-                self.ppu.ram.palatte_ram[reduced_addr]
+                // palette RAM is only 6 bits wide
+                self.ppu.ram.palatte_ram[reduced_addr] & 0x3F
             }
             // anything outside the given range should be unreachable because the internal vram address
             // only goes up to 14 bits
@@ -59,7 +86,7 @@ impl Memory for NES {
         }
     }
 
-    fn set(&mut self, addr: u16, val: u8) {
+    fn write_ppu_bus(&mut self, addr: u16, val: u8) {
         match addr {
             0..=0x1FFF => self.cartridge.as_ppu_mapper_mut().set(addr, val),
             0x2000..=0x3EFF => {
@@ -111,7 +138,7 @@ impl Memory for NES {
                 if reduced_addr >= 0x10 && (reduced_addr % 4) == 0 {
                     reduced_addr -= 0x10;
                 }
-                self.ppu.ram.palatte_ram[reduced_addr] = val;
+                self.ppu.ram.palatte_ram[reduced_addr] = val & 0x3F;
             }
             _ => {}
         }
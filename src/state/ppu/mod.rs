@@ -6,9 +6,14 @@ mod mapped_registers;
 mod memory;
 pub mod oam;
 mod ram;
+mod rendering;
 mod sprites;
 mod vblank;
 
+use crate::ppu::pipeline::Pipeline;
+use crate::ppu::RenderState;
+use crate::state::power_on::RAMFiller;
+use crate::state::snapshot::{SaveStateError, Snapshot, StateReader, StateWriter};
 use cycle_status::CycleStatus;
 use internal_registers::InternalRegisters;
 use mapped_registers::{SpriteHeight, PPUCTRL, PPUMASK, PPUSTATUS};
@@ -74,15 +79,35 @@ pub trait Cycle {
     fn update_cycle(&mut self);
     fn get_scanline(&self) -> usize;
     fn get_tick(&self) -> usize;
+
     fn increment_x(&mut self);
     fn increment_y(&mut self);
     fn reset_x(&mut self);
     fn reset_y(&mut self);
 }
 
+/// The shift registers that feed pixels to the screen, and the fetches that fill them
+pub trait Rendering {
+    fn get_pipeline(&self) -> &Pipeline;
+    fn get_pipeline_mut(&mut self) -> &mut Pipeline;
+
+    /// Moves the render state out of the PPU, leaving a default one in its place until
+    /// `set_render_state` is called
+    fn take_render_state(&mut self) -> RenderState;
+    fn set_render_state(&mut self, state: RenderState);
+
+    /// Whether PPUMASK limits the picture to the grey column of the palette
+    fn is_greyscale(&self) -> bool;
+    /// PPUMASK's red, green and blue emphasis bits, in bits 0-2
+    fn get_emphasis(&self) -> u8;
+}
+
 pub trait VBlank {
     fn start_vblank(&mut self);
     fn end_vlbank(&mut self);
+
+    /// Whether the PPU is pulling the NMI line low
+    fn is_nmi_asserted(&self) -> bool;
 }
 
 /// Represents the PPU's state
@@ -96,6 +121,8 @@ pub struct PPUState {
     status: PPUSTATUS,
     data_buffer: Cell<u8>,
     pub open_bus: Cell<u8>,
+    pipeline: Pipeline,
+    render_state: RenderState,
 }
 
 impl PPUState {
@@ -115,6 +142,88 @@ impl PPUState {
             status: PPUSTATUS::new(),
             data_buffer: Cell::new(0),
             open_bus: Cell::new(0),
+            pipeline: Pipeline::new(),
+            render_state: RenderState::default(),
+        }
+    }
+
+    pub fn fill_ram(&mut self, filler: &mut RAMFiller) {
+        filler.fill(&mut self.ram.nametable_a);
+        filler.fill(&mut self.ram.nametable_b);
+        filler.fill(&mut self.ram.palatte_ram);
+        // palette RAM is only 6 bits wide
+        for entry in self.ram.palatte_ram.iter_mut() {
+            *entry &= 0x3F;
+        }
+        filler.fill(&mut self.oam.memory);
+    }
+
+    /// Resets the PPU. Unlike the CPU, most of the PPU's state survives a reset.
+    pub fn reset(&mut self) {
+        self.ctrl = PPUCTRL::new();
+        self.mask = PPUMASK::new();
+        self.internal_registers.w.set(false);
+        self.internal_registers.x = 0;
+        self.data_buffer.set(0);
+        self.current_cycle.is_odd_frame = false;
+    }
+}
+
+impl Snapshot for PPUState {
+    fn save(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram.nametable_a);
+        writer.write_bytes(&self.ram.nametable_b);
+        writer.write_bytes(&self.ram.palatte_ram);
+        writer.write_bytes(&self.oam.memory);
+        writer.write_u8(self.oam.addr);
+        writer.write_usize(self.current_cycle.tick);
+        writer.write_usize(self.current_cycle.scanline);
+        writer.write_bool(self.current_cycle.is_odd_frame);
+        writer.write_u64(self.current_cycle.dots);
+        writer.write_u16(self.internal_registers.v.get());
+        writer.write_u16(self.internal_registers.t);
+        writer.write_u8(self.internal_registers.x);
+        writer.write_bool(self.internal_registers.w.get());
+        self.ctrl.save(writer);
+        self.mask.save(writer);
+        writer.write_bool(self.status.vblank.get());
+        writer.write_bool(self.status.vblank_suppressed.get());
+        writer.write_bool(self.status.sprite0_hit);
+        writer.write_bool(self.status.sprite_overflow);
+        writer.write_u8(self.data_buffer.get());
+        writer.write_u8(self.open_bus.get());
+        self.pipeline.save(writer);
+        self.render_state.save(writer);
+    }
+
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError> {
+        reader.read_bytes(&mut self.ram.nametable_a)?;
+        reader.read_bytes(&mut self.ram.nametable_b)?;
+        reader.read_bytes(&mut self.ram.palatte_ram)?;
+        reader.read_bytes(&mut self.oam.memory)?;
+        self.oam.addr = reader.read_u8()?;
+        self.current_cycle.tick = reader.read_usize()?;
+        self.current_cycle.scanline = reader.read_usize()?;
+        if self.current_cycle.tick > CycleStatus::MAX_TICKS
+            || self.current_cycle.scanline > CycleStatus::MAX_SCANLINES
+        {
+            return Err(SaveStateError::InvalidData);
         }
+        self.current_cycle.is_odd_frame = reader.read_bool()?;
+        self.current_cycle.dots = reader.read_u64()?;
+        self.internal_registers.v.set(reader.read_u16()?);
+        self.internal_registers.t = reader.read_u16()?;
+        self.internal_registers.x = reader.read_u8()?;
+        self.internal_registers.w.set(reader.read_bool()?);
+        self.ctrl.load(reader)?;
+        self.mask.load(reader)?;
+        self.status.vblank.set(reader.read_bool()?);
+        self.status.vblank_suppressed.set(reader.read_bool()?);
+        self.status.sprite0_hit = reader.read_bool()?;
+        self.status.sprite_overflow = reader.read_bool()?;
+        self.data_buffer.set(reader.read_u8()?);
+        self.open_bus.set(reader.read_u8()?);
+        self.pipeline.load(reader)?;
+        self.render_state.load(reader)
     }
 }
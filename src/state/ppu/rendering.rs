@@ -0,0 +1,30 @@
+use super::Rendering;
+use crate::ppu::pipeline::Pipeline;
+use crate::ppu::RenderState;
+use crate::state::NES;
+
+impl Rendering for NES {
+    fn get_pipeline(&self) -> &Pipeline {
+        &self.ppu.pipeline
+    }
+
+    fn get_pipeline_mut(&mut self) -> &mut Pipeline {
+        &mut self.ppu.pipeline
+    }
+
+    fn take_render_state(&mut self) -> RenderState {
+        std::mem::take(&mut self.ppu.render_state)
+    }
+
+    fn set_render_state(&mut self, state: RenderState) {
+        self.ppu.render_state = state;
+    }
+
+    fn is_greyscale(&self) -> bool {
+        self.ppu.mask.is_greyscale()
+    }
+
+    fn get_emphasis(&self) -> u8 {
+        self.ppu.mask.get_emphasis()
+    }
+}
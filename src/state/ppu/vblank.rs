@@ -1,18 +1,21 @@
 use super::VBlank;
-use crate::state::cpu::Interrupt;
 use crate::state::NES;
 
 impl VBlank for NES {
     fn start_vblank(&mut self) {
-        self.ppu.status.vblank.set(true);
-        if self.ppu.ctrl.should_output_nmi() {
-            self.trigger_nmi();
+        // reading $2002 just as vblank starts prevents the flag from being set for the frame
+        if !self.ppu.status.vblank_suppressed.replace(false) {
+            self.ppu.status.vblank.set(true);
         }
     }
 
     fn end_vlbank(&mut self) {
         self.ppu.status.vblank.set(false);
     }
+
+    fn is_nmi_asserted(&self) -> bool {
+        self.ppu.status.vblank.get() && self.ppu.ctrl.should_output_nmi()
+    }
 }
 
 #[cfg(test)]
@@ -25,4 +28,14 @@ mod tests {
         nes.start_vblank();
         assert_eq!(nes.ppu.status.vblank.get(), true);
     }
+
+    #[test]
+    fn test_suppressed_vblank() {
+        let mut nes = NES::mock();
+        nes.ppu.status.vblank_suppressed.set(true);
+        nes.start_vblank();
+        assert_eq!(nes.ppu.status.vblank.get(), false);
+        nes.start_vblank();
+        assert_eq!(nes.ppu.status.vblank.get(), true);
+    }
 }
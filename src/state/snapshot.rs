@@ -0,0 +1,273 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Save states are a flat little-endian byte stream. Every component writes its fields in a fixed
+/// order with `save`, and reads them back in the same order with `load`, so the format changes
+/// whenever a field is added or removed. Bump `VERSION` when that happens.
+pub trait Snapshot {
+    fn save(&self, writer: &mut StateWriter);
+    fn load(&mut self, reader: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+/// Identifies a save state file
+pub const MAGIC: &[u8; 4] = b"PESS";
+
+/// The version of the save state format
+pub const VERSION: u16 = 4;
+
+pub const THUMBNAIL_WIDTH: usize = 128;
+pub const THUMBNAIL_HEIGHT: usize = 120;
+
+/// The thumbnail is stored as RGB, without an alpha channel
+pub const THUMBNAIL_BYTES: usize = THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 3;
+
+/// Appends values to a save state
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_bool(&mut self, val: bool) {
+        self.write_u8(u8::from(val));
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.data.push(val);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, val: u32) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.data.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_usize(&mut self, val: usize) {
+        self.write_u64(val as u64);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Reads values from a save state, in the order they were written
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data }
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::InvalidData),
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut bytes = [0; 2];
+        self.read_bytes(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut bytes = [0; 4];
+        self.read_bytes(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0; 8];
+        self.read_bytes(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, SaveStateError> {
+        let val: u64 = self.read_u64()?;
+        usize::try_from(val).map_err(|_| SaveStateError::InvalidData)
+    }
+
+    /// Fills `bytes` completely
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<(), SaveStateError> {
+        bytes.copy_from_slice(self.take(bytes.len())?);
+        Ok(())
+    }
+
+    /// Returns an error if anything is left over
+    pub fn finish(&self) -> Result<(), SaveStateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(SaveStateError::InvalidData)
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.data.len() < len {
+            return Err(SaveStateError::UnexpectedEOF);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+}
+
+/// Describes a save state without loading it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveStateHeader {
+    pub version: u16,
+    /// The CRC32 of the ROM the state was saved from
+    pub rom_hash: u32,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// A downscaled screenshot, `THUMBNAIL_WIDTH` by `THUMBNAIL_HEIGHT` RGB pixels
+    pub thumbnail: Vec<u8>,
+}
+
+impl SaveStateHeader {
+    pub fn write(&self, writer: &mut StateWriter) {
+        writer.write_bytes(MAGIC);
+        writer.write_u16(self.version);
+        writer.write_u32(self.rom_hash);
+        writer.write_u64(self.timestamp);
+        writer.write_bytes(&self.thumbnail);
+    }
+
+    /// Reads a header, failing if it doesn't belong to a save state this version can load
+    pub fn read(reader: &mut StateReader) -> Result<Self, SaveStateError> {
+        let mut magic = [0; 4];
+        reader.read_bytes(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let version: u16 = reader.read_u16()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        let rom_hash: u32 = reader.read_u32()?;
+        let timestamp: u64 = reader.read_u64()?;
+        let mut thumbnail = vec![0; THUMBNAIL_BYTES];
+        reader.read_bytes(&mut thumbnail)?;
+        Ok(SaveStateHeader {
+            version,
+            rom_hash,
+            timestamp,
+            thumbnail,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveStateError {
+    NotASaveState,
+    UnsupportedVersion(u16),
+    /// The state was saved from a ROM with a different hash
+    ROMMismatch {
+        expected: u32,
+        found: u32,
+    },
+    UnexpectedEOF,
+    InvalidData,
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported (expected {})",
+                version, VERSION
+            ),
+            SaveStateError::ROMMismatch { expected, found } => write!(
+                f,
+                "save state is for a different ROM (expected CRC32 {:08X}, found {:08X})",
+                expected, found
+            ),
+            SaveStateError::UnexpectedEOF => write!(f, "save state is truncated"),
+            SaveStateError::InvalidData => write!(f, "save state is corrupted"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut writer = StateWriter::new();
+        writer.write_bool(true);
+        writer.write_u8(0x12);
+        writer.write_u16(0x3456);
+        writer.write_u32(0x789A_BCDE);
+        writer.write_u64(0x0123_4567_89AB_CDEF);
+        writer.write_usize(42);
+        writer.write_bytes(&[1, 2, 3]);
+        let data: Vec<u8> = writer.into_inner();
+
+        let mut reader = StateReader::new(&data);
+        assert_eq!(reader.read_bool(), Ok(true));
+        assert_eq!(reader.read_u8(), Ok(0x12));
+        assert_eq!(reader.read_u16(), Ok(0x3456));
+        assert_eq!(reader.read_u32(), Ok(0x789A_BCDE));
+        assert_eq!(reader.read_u64(), Ok(0x0123_4567_89AB_CDEF));
+        assert_eq!(reader.read_usize(), Ok(42));
+        let mut bytes = [0; 3];
+        assert_eq!(reader.read_bytes(&mut bytes), Ok(()));
+        assert_eq!(bytes, [1, 2, 3]);
+        assert_eq!(reader.finish(), Ok(()));
+        assert_eq!(reader.read_u8(), Err(SaveStateError::UnexpectedEOF));
+    }
+
+    #[test]
+    fn test_header() {
+        let header = SaveStateHeader {
+            version: VERSION,
+            rom_hash: 0xDEAD_BEEF,
+            timestamp: 1234,
+            thumbnail: vec![7; THUMBNAIL_BYTES],
+        };
+        let mut writer = StateWriter::new();
+        header.write(&mut writer);
+        let data: Vec<u8> = writer.into_inner();
+        assert_eq!(
+            SaveStateHeader::read(&mut StateReader::new(&data)),
+            Ok(header)
+        );
+
+        let mut bad_version: Vec<u8> = data.clone();
+        bad_version[4] = 0xFF;
+        assert_eq!(
+            SaveStateHeader::read(&mut StateReader::new(&bad_version)),
+            Err(SaveStateError::UnsupportedVersion(0x00FF))
+        );
+        assert_eq!(
+            SaveStateHeader::read(&mut StateReader::new(b"NES\x1A")),
+            Err(SaveStateError::NotASaveState)
+        );
+    }
+}
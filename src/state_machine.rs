@@ -0,0 +1,10 @@
+/// The result of running one cycle of a state machine, such as an opcode or a DMA transfer.
+/// Every state machine keeps its progress in a plain struct, so it can be inspected and saved
+/// between cycles.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CycleState<Y, R> {
+    /// The state machine ran a cycle and has more to do
+    Yielded(Y),
+    /// The state machine ran its last cycle
+    Complete(R),
+}
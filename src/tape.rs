@@ -0,0 +1,151 @@
+use std::convert::TryInto;
+use std::fmt;
+
+/// Sample rate of newly recorded tapes
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+const WAV_FORMAT_PCM: u16 = 1;
+
+/// Audio on a cassette tape, as used by the Famicom data recorder.
+/// Tapes are stored as `.wav` files. Any sample rate works, but only PCM audio is supported, and
+/// only the first channel is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tape {
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+impl Tape {
+    /// A blank tape
+    pub fn new() -> Self {
+        Tape {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Parses a `.wav` file with 8 or 16 bit PCM samples
+    pub fn from_wav(data: &[u8]) -> Result<Self, TapeError> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err(TapeError::NotAWav);
+        }
+        let mut format: Option<(u16, u32, u16)> = None;
+        let mut chunks: &[u8] = &data[12..];
+        while chunks.len() >= 8 {
+            let id: &[u8] = &chunks[0..4];
+            let len: usize = read_u32(chunks, 4) as usize;
+            let body: &[u8] = chunks.get(8..8 + len).ok_or(TapeError::NotAWav)?;
+            match id {
+                b"fmt " if body.len() >= 16 => {
+                    if read_u16(body, 0) != WAV_FORMAT_PCM {
+                        return Err(TapeError::Unsupported("compressed audio".to_string()));
+                    }
+                    format = Some((read_u16(body, 2), read_u32(body, 4), read_u16(body, 14)));
+                }
+                b"data" => {
+                    let (channels, sample_rate, bits) = format.ok_or(TapeError::NotAWav)?;
+                    let frame_size: usize = usize::from(channels.max(1)) * usize::from(bits / 8);
+                    let samples: Vec<i16> = match bits {
+                        8 => body
+                            .chunks_exact(frame_size)
+                            .map(|frame| (i16::from(frame[0]) - 0x80) << 8)
+                            .collect(),
+                        16 => body
+                            .chunks_exact(frame_size)
+                            .map(|frame| read_u16(frame, 0) as i16)
+                            .collect(),
+                        _ => return Err(TapeError::Unsupported(format!("{} bit samples", bits))),
+                    };
+                    return Ok(Tape {
+                        sample_rate,
+                        samples,
+                    });
+                }
+                _ => {}
+            }
+            // chunks are padded to an even length
+            chunks = chunks.get(8 + len + len % 2..).unwrap_or(&[]);
+        }
+        Err(TapeError::NotAWav)
+    }
+
+    /// Writes the tape as a mono 16 bit `.wav` file
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data_len: u32 = (self.samples.len() * 2) as u32;
+        let mut wav: Vec<u8> = Vec::with_capacity(44 + data_len as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&WAV_FORMAT_PCM.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in &self.samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        wav
+    }
+}
+
+impl Default for Tape {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TapeError {
+    NotAWav,
+    /// The file uses a feature this emulator doesn't support
+    Unsupported(String),
+}
+
+impl fmt::Display for TapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TapeError::NotAWav => write!(f, "not a valid .wav file"),
+            TapeError::Unsupported(feature) => write!(f, "unsupported .wav feature: {}", feature),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav() {
+        let tape = Tape {
+            sample_rate: 32000,
+            samples: vec![0, 1000, -1000, i16::MAX, i16::MIN],
+        };
+        assert_eq!(Tape::from_wav(&tape.to_wav()), Ok(tape));
+        assert_eq!(Tape::from_wav(b"not a wav"), Err(TapeError::NotAWav));
+    }
+
+    #[test]
+    fn test_8_bit_stereo_wav() {
+        let mut wav: Vec<u8> = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.extend_from_slice(&[16, 0, 0, 0, 1, 0, 2, 0]);
+        wav.extend_from_slice(&[0x11, 0x2B, 0, 0, 0x22, 0x56, 0, 0, 2, 0, 8, 0]);
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&[4, 0, 0, 0, 0x80, 0xFF, 0xC0, 0x00]);
+        let tape: Tape = Tape::from_wav(&wav).unwrap();
+        assert_eq!(tape.sample_rate, 11025);
+        assert_eq!(tape.samples, vec![0, 0x4000]);
+    }
+}
@@ -1,4 +1,3 @@
-#![feature(generator_trait)]
 use file_diff::diff;
 use pretendo_entertainment_system::cartridge::ines::{ROMError, INES};
 use pretendo_entertainment_system::cartridge::Mapper;
@@ -6,13 +5,10 @@ use pretendo_entertainment_system::cpu;
 use pretendo_entertainment_system::cpu::InstructionState;
 use pretendo_entertainment_system::state::cpu::Registers;
 use pretendo_entertainment_system::state::NES;
-use std::cell::RefCell;
 use std::env;
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::ops::{Generator, GeneratorState};
 use std::path::PathBuf;
-use std::pin::Pin;
 
 /// Test harness for the nestest ROM
 #[test]
@@ -23,9 +19,9 @@ fn nes_test_cpu() -> Result<(), ROMError> {
         .collect();
     let mut rom = File::open(rom_path)?;
     let mapper: Box<dyn Mapper> = INES::from_file(&mut rom)?.to_mapper();
-    let nes: RefCell<NES> = RefCell::new(NES::new(mapper));
-    nes.borrow_mut().set_pc(0xC000);
-    nes.borrow_mut().set_p(0x24);
+    let mut nes: NES = NES::new(mapper);
+    nes.set_pc(0xC000);
+    nes.set_p(0x24);
 
     // Create log
     let log_path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "data", "test.log"]
@@ -36,33 +32,30 @@ fn nes_test_cpu() -> Result<(), ROMError> {
     write!(
         log,
         "{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{:>3}\n",
-        nes.borrow().get_pc(),
-        nes.borrow().get_a(),
-        nes.borrow().get_x(),
-        nes.borrow().get_y(),
-        nes.borrow().get_p(),
-        nes.borrow().get_s(),
+        nes.get_pc(),
+        nes.get_a(),
+        nes.get_x(),
+        nes.get_y(),
+        nes.get_p(),
+        nes.get_s(),
         cycle
     )?;
 
     // Execute instructions cycle by cycle
-    let mut cpu_generator = cpu::cycle(&nes);
     // at this point the ROM starts executing unofficial unstructions
-    while nes.borrow().get_pc() != 0xC6BD {
+    while nes.get_pc() != 0xC6BD {
         cycle += 3;
         cycle %= 341;
-        if let GeneratorState::Yielded(InstructionState::Complete(_)) =
-            Pin::new(&mut cpu_generator).resume(())
-        {
+        if let InstructionState::Complete(_) = cpu::cycle(&mut nes) {
             write!(
                 log,
                 "{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{:>3}\n",
-                nes.borrow().get_pc(),
-                nes.borrow().get_a(),
-                nes.borrow().get_x(),
-                nes.borrow().get_y(),
-                nes.borrow().get_p(),
-                nes.borrow().get_s(),
+                nes.get_pc(),
+                nes.get_a(),
+                nes.get_x(),
+                nes.get_y(),
+                nes.get_p(),
+                nes.get_s(),
                 cycle
             )?;
         }